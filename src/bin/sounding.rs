@@ -0,0 +1,585 @@
+//! Command line tool for converting, summarizing, and checking sounding files.
+//!
+//! ```text
+//! sounding convert [--from FMT] [--to FMT] [INPUT] [OUTPUT]
+//! sounding summary [--from FMT] [INPUT]
+//! sounding extract --pressure HPA [--from FMT] [INPUT]
+//! sounding validate [--from FMT] [INPUT]
+//! ```
+//!
+//! A missing path or `-` means standard input or standard output. Formats are taken from the file
+//! extensions unless given with `--from` and `--to`, and default to CSV for standard input and
+//! output.
+//!
+//! `convert` takes a single sounding, so an input with more than one, e.g. a BUFKIT file with
+//! several forecast hours, is an error. `validate` runs the `QualityControl` checks and exits with
+//! status 1 if any value fails them. Bad arguments and files that can't be read or written exit
+//! with status 2.
+#![allow(deprecated)]
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::process::exit;
+
+use metfor::{HectoPascal, Quantity};
+use sounding_base::{
+    interpolate_at_pressure, read_bufkit, read_sharppy, write_netcdf, write_sharppy, CsvReader,
+    CsvWriter, DataRow, IgraReader, ProfileVariable, QcFlag, QualityControl, Sounding,
+    TableFormatter,
+};
+
+const USAGE: &str = "\
+Usage:
+    sounding convert [--from FMT] [--to FMT] [INPUT] [OUTPUT]
+    sounding summary [--from FMT] [INPUT]
+    sounding extract --pressure HPA [--from FMT] [INPUT]
+    sounding validate [--from FMT] [INPUT]
+
+A missing path or '-' means stdin or stdout.
+
+Input formats:  csv, tsv, sharppy, igra, bufkit
+Output formats: csv, tsv, sharppy, netcdf, table
+
+Formats are guessed from the file extension (.csv, .tsv, .sharppy, .raw, -data.txt for IGRA, .buf,
+.nc) and default to csv.";
+
+/// Exit code when a sounding fails validation.
+const EXIT_INVALID: i32 = 1;
+/// Exit code for bad arguments or files that can't be read or written.
+const EXIT_ERROR: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Csv,
+    Tsv,
+    Sharppy,
+    Igra,
+    Bufkit,
+    NetCdf,
+    Table,
+}
+
+impl Format {
+    fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "sharppy" | "raw" => Ok(Format::Sharppy),
+            "igra" => Ok(Format::Igra),
+            "bufkit" | "buf" => Ok(Format::Bufkit),
+            "netcdf" | "nc" => Ok(Format::NetCdf),
+            "table" | "txt" => Ok(Format::Table),
+            _ => Err(format!("unknown format '{}'", name)),
+        }
+    }
+
+    fn from_path(path: Option<&str>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => path.to_lowercase(),
+            None => return Ok(Format::Csv),
+        };
+
+        if path.ends_with("-data.txt") {
+            Ok(Format::Igra)
+        } else {
+            match path.rsplit('.').next() {
+                Some(ext) if ext != path => Format::from_name(ext),
+                _ => Ok(Format::Csv),
+            }
+        }
+    }
+}
+
+/// Parsed command line options shared by all the commands.
+#[derive(Debug, Default)]
+struct Options {
+    from: Option<String>,
+    to: Option<String>,
+    pressure: Option<f64>,
+    paths: Vec<String>,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut opts = Options::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+
+            match arg.as_str() {
+                "--from" => opts.from = Some(value("--from")?),
+                "--to" => opts.to = Some(value("--to")?),
+                "--pressure" => {
+                    let val = value("--pressure")?;
+                    let p = val
+                        .parse()
+                        .map_err(|_| format!("invalid pressure '{}'", val))?;
+                    opts.pressure = Some(p);
+                }
+                "-" => opts.paths.push(arg),
+                _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+                _ => opts.paths.push(arg),
+            }
+        }
+
+        Ok(opts)
+    }
+
+    fn path(&self, idx: usize) -> Option<&str> {
+        self.paths
+            .get(idx)
+            .map(String::as_str)
+            .filter(|&path| path != "-")
+    }
+
+    fn input_format(&self) -> Result<Format, String> {
+        match self.from {
+            Some(ref name) => Format::from_name(name),
+            None => Format::from_path(self.path(0)),
+        }
+    }
+
+    fn output_format(&self) -> Result<Format, String> {
+        match self.to {
+            Some(ref name) => Format::from_name(name),
+            None => Format::from_path(self.path(1)),
+        }
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+
+    let command = match args.next() {
+        Some(command) => command,
+        None => fail(USAGE),
+    };
+    if command == "--help" || command == "-h" || command == "help" {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let opts = Options::parse(args).unwrap_or_else(|err| fail(&err));
+
+    let result = match command.as_str() {
+        "convert" => convert(&opts),
+        "summary" => summary(&opts),
+        "extract" => extract(&opts),
+        "validate" => validate(&opts),
+        _ => fail(&format!("unknown command '{}'\n\n{}", command, USAGE)),
+    };
+
+    match result {
+        Ok(true) => {}
+        Ok(false) => exit(EXIT_INVALID),
+        Err(err) => fail(&err),
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("sounding: {}", msg);
+    exit(EXIT_ERROR)
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                             Commands
+--------------------------------------------------------------------------------------------------*/
+fn convert(opts: &Options) -> Result<bool, String> {
+    let format = opts.output_format()?;
+    let soundings = read_input(opts)?;
+    if soundings.len() > 1 {
+        return Err(format!(
+            "input has {} soundings, but only one can be converted",
+            soundings.len()
+        ));
+    }
+    let snd = &soundings[0];
+
+    let dest: Box<dyn Write> = match opts.path(1) {
+        Some(path) => Box::new(File::create(path).map_err(|err| format!("{}: {}", path, err))?),
+        None => Box::new(io::stdout()),
+    };
+    let mut dest = BufWriter::new(dest);
+
+    let result = match format {
+        Format::Csv => CsvWriter::new().write(snd, &mut dest),
+        Format::Tsv => CsvWriter::new().with_delimiter('\t').write(snd, &mut dest),
+        Format::Sharppy => write_sharppy(snd, &mut dest),
+        Format::NetCdf => write_netcdf(snd, &mut dest),
+        Format::Table => TableFormatter::new().write(snd, &mut dest),
+        Format::Igra => return Err("writing IGRA files is not supported".to_owned()),
+        Format::Bufkit => return Err("writing BUFKIT files is not supported".to_owned()),
+    };
+    result.map_err(|err| err.to_string())?;
+    dest.flush().map_err(|err| err.to_string())?;
+
+    Ok(true)
+}
+
+fn summary(opts: &Options) -> Result<bool, String> {
+    for snd in read_input(opts)? {
+        let stn = snd.station_info();
+        let fmt_opt = |val: Option<f64>, units: &str| match val {
+            Some(val) => format!("{:.1} {}", val, units),
+            None => "missing".to_owned(),
+        };
+
+        println!(
+            "station:          {}",
+            stn.station_num()
+                .map(|num| num.to_string())
+                .unwrap_or_else(|| "missing".to_owned())
+        );
+        println!(
+            "location:         {}",
+            stn.location()
+                .map(|(lat, lon)| format!("{:.3}, {:.3}", lat, lon))
+                .unwrap_or_else(|| "missing".to_owned())
+        );
+        println!(
+            "elevation:        {}",
+            fmt_opt(stn.elevation().map(|e| e.unpack()), "m")
+        );
+        println!(
+            "valid time:       {}",
+            snd.valid_time()
+                .map(|vt| vt.format("%Y-%m-%d %H:%MZ").to_string())
+                .unwrap_or_else(|| "missing".to_owned())
+        );
+        if let Some(lt) = snd.lead_time().into_option() {
+            println!("lead time:        {} h", lt);
+        }
+
+        let levels: Vec<HectoPascal> = snd
+            .pressure_profile()
+            .iter()
+            .skip(1)
+            .filter_map(|p| p.into_option())
+            .collect();
+        println!("levels:           {}", levels.len());
+        println!(
+            "station pressure: {}",
+            fmt_opt(snd.station_pressure().map(|p| p.unpack()), "hPa")
+        );
+        println!(
+            "top pressure:     {}",
+            fmt_opt(
+                levels
+                    .iter()
+                    .map(|p| p.unpack())
+                    .fold(None, |acc: Option<f64>, p| Some(
+                        acc.map_or(p, |a| a.min(p))
+                    )),
+                "hPa"
+            )
+        );
+        println!(
+            "sfc temperature:  {}",
+            fmt_opt(snd.sfc_temperature().map(|t| t.unpack()), "C")
+        );
+        println!(
+            "sfc dew point:    {}",
+            fmt_opt(snd.sfc_dew_point().map(|t| t.unpack()), "C")
+        );
+        println!(
+            "mslp:             {}",
+            fmt_opt(snd.mslp().map(|p| p.unpack()), "hPa")
+        );
+
+        let present: Vec<_> = ProfileVariable::ALL
+            .iter()
+            .filter(|var| snd.bottom_up().any(|row| var.value(&row).is_some()))
+            .map(|var| var.name())
+            .collect();
+        println!("variables:        {}", present.join(", "));
+
+        let fmt_index = |val: Option<f64>| match val {
+            Some(val) => format!("{:.1}", val),
+            None => "missing".to_owned(),
+        };
+        println!("k index:          {}", fmt_index(k_index(&snd)));
+        println!("total totals:     {}", fmt_index(total_totals(&snd)));
+        println!("lifted index:     {}", fmt_index(lifted_index(&snd)));
+        println!(
+            "precip. water:    {}",
+            fmt_opt(precipitable_water(&snd), "mm")
+        );
+        println!();
+    }
+
+    Ok(true)
+}
+
+fn extract(opts: &Options) -> Result<bool, String> {
+    let target = opts
+        .pressure
+        .ok_or_else(|| "extract needs --pressure".to_owned())?;
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let delimiter = ",";
+    let header: Vec<_> = ProfileVariable::ALL.iter().map(|var| var.name()).collect();
+    writeln!(out, "{}", header.join(delimiter)).map_err(|err| err.to_string())?;
+
+    for (i, snd) in read_input(opts)?.iter().enumerate() {
+        let row = match interpolate_at_pressure(snd, HectoPascal(target)) {
+            Some(row) => row,
+            None => {
+                eprintln!(
+                    "sounding: sounding {} does not reach {:.1} hPa",
+                    i + 1,
+                    target
+                );
+                continue;
+            }
+        };
+
+        let vals: Vec<String> = ProfileVariable::ALL
+            .iter()
+            .map(|var| {
+                var.value(&row)
+                    .map(|val| format!("{:.2}", val))
+                    .unwrap_or_default()
+            })
+            .collect();
+        writeln!(out, "{}", vals.join(delimiter)).map_err(|err| err.to_string())?;
+    }
+
+    Ok(true)
+}
+
+fn validate(opts: &Options) -> Result<bool, String> {
+    let mut valid = true;
+
+    for (i, snd) in read_input(opts)?.iter().enumerate() {
+        let problems = check(snd);
+        if problems.is_empty() {
+            println!("sounding {}: ok", i + 1);
+        }
+        for (flag, problem) in problems {
+            if flag == QcFlag::Fail {
+                valid = false;
+            }
+            println!("sounding {}: {}", i + 1, problem);
+        }
+    }
+
+    Ok(valid)
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                             Helpers
+--------------------------------------------------------------------------------------------------*/
+fn read_input(opts: &Options) -> Result<Vec<Sounding>, String> {
+    let format = opts.input_format()?;
+    let name = opts.path(0).unwrap_or("stdin");
+
+    let src: Box<dyn Read> = match opts.path(0) {
+        Some(path) => Box::new(File::open(path).map_err(|err| format!("{}: {}", path, err))?),
+        None => Box::new(io::stdin()),
+    };
+    let src = BufReader::new(src);
+
+    let result: sounding_base::Result<Vec<Sounding>> = match format {
+        Format::Csv => read_csv(src, ','),
+        Format::Tsv => read_csv(src, '\t'),
+        Format::Sharppy => read_sharppy(src).map(|snd| vec![snd]),
+        Format::Igra => IgraReader::new(src).collect(),
+        Format::Bufkit => read_bufkit(src),
+        Format::NetCdf | Format::Table => {
+            return Err(format!("{}: reading this format is not supported", name))
+        }
+    };
+
+    match result {
+        Ok(ref soundings) if soundings.is_empty() => Err(format!("{}: no soundings found", name)),
+        Ok(soundings) => Ok(soundings),
+        Err(err) => Err(format!("{}: {}", name, err)),
+    }
+}
+
+fn read_csv<R: BufRead>(src: R, delimiter: char) -> sounding_base::Result<Vec<Sounding>> {
+    CsvReader::new()
+        .with_delimiter(delimiter)
+        .with_surface_row(true)
+        .read(src)
+        .map(|snd| vec![snd])
+}
+
+/// Check a sounding for internal consistency, then run the `QualityControl` checks on it.
+///
+/// Profiles with the wrong number of levels and pressures that don't decrease with height fail, as
+/// do the values flagged as failed by the quality control.
+fn check(snd: &Sounding) -> Vec<(QcFlag, String)> {
+    let mut problems = vec![];
+
+    let levels = snd.pressure_profile().len();
+    if levels == 0 {
+        problems.push((QcFlag::Fail, "no pressure profile".to_owned()));
+        return problems;
+    }
+
+    let lengths = [
+        ("temperature", snd.temperature_profile().len()),
+        ("wet bulb", snd.wet_bulb_profile().len()),
+        ("dew point", snd.dew_point_profile().len()),
+        ("theta-e", snd.theta_e_profile().len()),
+        ("wind", snd.wind_profile().len()),
+        ("pvv", snd.pvv_profile().len()),
+        ("height", snd.height_profile().len()),
+        ("cloud fraction", snd.cloud_fraction_profile().len()),
+    ];
+    for &(name, len) in lengths.iter() {
+        if len != 0 && len != levels {
+            problems.push((
+                QcFlag::Fail,
+                format!(
+                    "{} profile has {} levels, pressure has {}",
+                    name, len, levels
+                ),
+            ));
+        }
+    }
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    let level_name = |i: usize| {
+        if i == 0 {
+            "surface".to_owned()
+        } else {
+            format!("level {}", i)
+        }
+    };
+
+    let mut last_p: Option<f64> = None;
+    for (i, row) in snd.bottom_up().enumerate() {
+        if let Some(p) = row.pressure.into_option().map(|p| p.unpack()) {
+            if last_p.is_some_and(|last| p >= last) {
+                problems.push((
+                    QcFlag::Fail,
+                    format!(
+                        "{}: pressure {:.1} hPa does not decrease with height",
+                        level_name(i),
+                        p
+                    ),
+                ));
+            }
+            last_p = Some(p);
+        }
+    }
+
+    let flags = QualityControl::new().check(snd);
+    for (i, (flags, row)) in flags.iter().zip(snd.bottom_up()).enumerate() {
+        let checked = [
+            ("pressure", flags.pressure, row.pressure.map(|p| p.unpack())),
+            (
+                "temperature",
+                flags.temperature,
+                row.temperature.map(|t| t.unpack()),
+            ),
+            (
+                "dew point",
+                flags.dew_point,
+                row.dew_point.map(|t| t.unpack()),
+            ),
+            ("wind speed", flags.wind, row.wind.map(|w| w.speed.unpack())),
+            ("height", flags.height, row.height.map(|h| h.unpack())),
+        ];
+
+        for &(name, flag, val) in checked.iter() {
+            let verdict = match flag {
+                QcFlag::Pass => continue,
+                QcFlag::Suspect => "suspect",
+                QcFlag::Fail => "failed",
+            };
+            let val = val.map_or_else(|| "missing".to_owned(), |val| format!("{:.1}", val));
+            problems.push((
+                flag,
+                format!("{}: {} {} {}", level_name(i), name, val, verdict),
+            ));
+        }
+    }
+
+    problems
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Stability indices
+--------------------------------------------------------------------------------------------------*/
+/// The temperature and dew point at a pressure level, interpolated if needed.
+fn t_td_at(snd: &Sounding, p: f64) -> Option<(f64, f64)> {
+    let row = interpolate_at_pressure(snd, HectoPascal(p))?;
+    Some((
+        row.temperature.into_option()?.unpack(),
+        row.dew_point.into_option()?.unpack(),
+    ))
+}
+
+fn k_index(snd: &Sounding) -> Option<f64> {
+    let (t850, td850) = t_td_at(snd, 850.0)?;
+    let (t700, td700) = t_td_at(snd, 700.0)?;
+    let t500 = interpolate_at_pressure(snd, HectoPascal(500.0))?
+        .temperature
+        .into_option()?
+        .unpack();
+
+    Some(t850 - t500 + td850 - (t700 - td700))
+}
+
+fn total_totals(snd: &Sounding) -> Option<f64> {
+    let (t850, td850) = t_td_at(snd, 850.0)?;
+    let t500 = interpolate_at_pressure(snd, HectoPascal(500.0))?
+        .temperature
+        .into_option()?
+        .unpack();
+
+    Some(t850 + td850 - 2.0 * t500)
+}
+
+/// The lifted index of a parcel from the lowest level with a temperature and dew point.
+fn lifted_index(snd: &Sounding) -> Option<f64> {
+    let p500 = HectoPascal(500.0);
+    let t500 = interpolate_at_pressure(snd, p500)?
+        .temperature
+        .into_option()?
+        .unpack();
+
+    let (p, t, td) = snd.bottom_up().find_map(|row: DataRow| {
+        Some((
+            row.pressure.into_option()?,
+            row.temperature.into_option()?,
+            row.dew_point.into_option()?,
+        ))
+    })?;
+
+    let parcel = if metfor::pressure_hpa_at_lcl(t, td, p)? > p500 {
+        metfor::temperature_from_theta_e_saturated_and_pressure(p500, metfor::theta_e(t, td, p)?)?
+    } else {
+        metfor::Celsius::from(metfor::temperature_from_theta(metfor::theta(p, t), p500))
+    };
+
+    Some(t500 - parcel.unpack())
+}
+
+/// Precipitable water in mm, integrated over the levels with a dew point.
+fn precipitable_water(snd: &Sounding) -> Option<f64> {
+    let levels: Vec<(f64, f64)> = snd
+        .pressure_profile()
+        .iter()
+        .zip(snd.mixing_ratio_profile())
+        .filter_map(|(p, mw)| Some((p.into_option()?.unpack() * 100.0, mw.into_option()?)))
+        .collect();
+
+    if levels.len() < 2 {
+        return None;
+    }
+
+    let pw: f64 = levels
+        .windows(2)
+        .map(|pair| (pair[0].1 + pair[1].1) / 2.0 * (pair[0].0 - pair[1].0))
+        .sum();
+
+    // kg/m^2 is the same as mm of water.
+    Some(pw / -metfor::g)
+}
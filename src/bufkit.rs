@@ -0,0 +1,362 @@
+//! Reader for the BUFKIT text format of model forecast soundings.
+//!
+//! A BUFKIT file has an upper air section for each forecast time, starting with `STID`, `STNM`,
+//! `TIME` and other `KEY = value` pairs followed by a list of column names and the values of every
+//! level, one after another. After the upper air sections is a surface section, starting with
+//! `STN YYMMDD/HHMM` and a list of column names, with one row of values per forecast time. Rows of
+//! values may wrap over several lines, so the file is read as a stream of whitespace separated
+//! tokens. Missing values are `-9999.00`.
+//!
+//! The upper air columns used are `PRES`, `TMPC`, `TMWC`, `DWPC`, `THTE`, `DRCT`, `SKNT`, `OMEG`,
+//! `CFRL` and `HGHT`, and the surface columns used are `PMSL`, `PRES`, `T2MS`, `TD2M`, `UWND` and
+//! `VWND`. Other columns, and the stability indices in the upper air header, are ignored.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use chrono::NaiveDateTime;
+use metfor::{Celsius, HectoPascal, Kelvin, Knots, Meters, MetersPSec, PaPS, WindSpdDir, WindUV};
+use optional::{none, some, Optioned};
+
+use crate::error::{Result, SoundingError};
+use crate::sounding::Sounding;
+use crate::station_info::StationInfo;
+
+const MISSING: f64 = -9999.0;
+const TIME_FORMAT: &str = "%y%m%d/%H%M";
+
+/// Read all the soundings in a BUFKIT file.
+///
+/// The surface values of each sounding come from the row of the surface section with the same
+/// station number and valid time, if there is one. The `STID` is kept as the source description.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal, Meters};
+/// use sounding_base::read_bufkit;
+///
+/// let text = "\
+/// SNPARM = PRES;TMPC;TMWC;DWPC;THTE;DRCT;SKNT;OMEG;CFRL;HGHT
+///
+/// STID = KMSO         STNM = 727730   TIME = 170401/0000
+/// SLAT = 46.92        SLON = -114.08  SELV = 1335.0
+/// STIM = 0
+///
+/// SHOW = 7.37         LIFT = 9.06
+///
+/// PRES TMPC TMWC DWPC THTE DRCT SKNT OMEG
+/// CFRL HGHT
+/// 870.10 4.66 0.72 -4.64 298.61 264.81 5.25 -0.20
+/// 0.00 1362.00
+/// 700.30 -5.94 -9.52 -20.51 297.35 250.56 19.31 0.30
+/// -9999.00 3020.00
+///
+/// STN YYMMDD/HHMM PMSL PRES SKTC
+/// T2MS TD2M UWND VWND
+/// 727730 170401/0000 1018.40 872.60 3.41
+/// 6.20 -4.00 1.50 -2.00
+/// ";
+///
+/// let soundings = read_bufkit(text.as_bytes()).unwrap();
+/// assert_eq!(soundings.len(), 1);
+///
+/// let snd = &soundings[0];
+/// assert_eq!(snd.station_info().station_num().unwrap(), 727730);
+/// assert_eq!(snd.station_info().elevation().unwrap(), Meters(1335.0));
+/// assert_eq!(snd.lead_time().unwrap(), 0);
+/// assert_eq!(snd.station_pressure().unwrap(), HectoPascal(872.6));
+/// assert_eq!(snd.sfc_temperature().unwrap(), Celsius(6.2));
+/// assert_eq!(snd.pressure_profile().len(), 3);
+/// assert_eq!(snd.temperature_profile()[2].unwrap(), Celsius(-5.94));
+/// assert!(snd.cloud_fraction_profile()[2].is_none());
+/// ```
+pub fn read_bufkit<R: BufRead>(src: R) -> Result<Vec<Sounding>> {
+    let mut tokens: Vec<(usize, String)> = vec![];
+    for (i, line) in src.lines().enumerate() {
+        let line = line?;
+        tokens.extend(line.split_whitespace().map(|tok| (i + 1, tok.to_owned())));
+    }
+
+    let mut tokens = Tokens { tokens, idx: 0 };
+    let mut upper_air: Vec<(HashMap<String, String>, Table)> = vec![];
+    let mut surface: Option<Table> = None;
+
+    while let Some((line_num, token)) = tokens.peek() {
+        match token {
+            "SNPARM" | "STNPRM" => {
+                tokens.key_value();
+            }
+            "STID" => {
+                let header = tokens.key_values();
+                let table = tokens.table(line_num)?;
+                upper_air.push((header, table));
+            }
+            "STN" => surface = Some(tokens.table(line_num)?),
+            _ => {
+                return Err(SoundingError::parse(
+                    line_num,
+                    format!("unexpected '{}'", token),
+                ))
+            }
+        }
+    }
+
+    upper_air
+        .into_iter()
+        .map(|(header, table)| build_sounding(&header, &table, surface.as_ref()))
+        .collect()
+}
+
+/// Column names and rows of values. Any text values, i.e. times, are missing.
+#[derive(Debug)]
+struct Table {
+    columns: Vec<String>,
+    rows: Vec<Vec<Option<f64>>>,
+    // The time of each row, for the surface section.
+    times: Vec<Option<NaiveDateTime>>,
+}
+
+impl Table {
+    fn get(&self, row: usize, column: &str) -> Option<f64> {
+        let col = self.columns.iter().position(|name| name == column)?;
+        self.rows[row][col]
+    }
+
+    fn has(&self, column: &str) -> bool {
+        self.columns.iter().any(|name| name == column)
+    }
+}
+
+struct Tokens {
+    tokens: Vec<(usize, String)>,
+    idx: usize,
+}
+
+impl Tokens {
+    fn peek(&self) -> Option<(usize, &str)> {
+        self.tokens
+            .get(self.idx)
+            .map(|(line, tok)| (*line, tok.as_str()))
+    }
+
+    fn is_key(&self, idx: usize) -> bool {
+        self.tokens.get(idx + 1).map(|(_, tok)| tok.as_str()) == Some("=")
+    }
+
+    /// Read a `KEY = value` pair. The value may be blank, e.g. `STID = STNM = 727730`.
+    fn key_value(&mut self) -> (String, String) {
+        let key = self.tokens[self.idx].1.clone();
+        self.idx += 2;
+
+        if self.idx < self.tokens.len() && !self.is_key(self.idx) {
+            self.idx += 1;
+            (key, self.tokens[self.idx - 1].1.clone())
+        } else {
+            (key, String::new())
+        }
+    }
+
+    /// Read `KEY = value` pairs up to the next token that isn't a key.
+    fn key_values(&mut self) -> HashMap<String, String> {
+        let mut pairs = HashMap::new();
+        while self.is_key(self.idx) {
+            let (key, value) = self.key_value();
+            pairs.insert(key, value);
+        }
+
+        pairs
+    }
+
+    /// Read column names followed by rows of values. The first value of a row must be a number,
+    /// and the table ends at the first token after a complete row that isn't a number.
+    fn table(&mut self, line_num: usize) -> Result<Table> {
+        let mut columns = vec![];
+        while let Some((_, tok)) = self.peek() {
+            if tok.parse::<f64>().is_ok() {
+                break;
+            }
+            columns.push(tok.to_owned());
+            self.idx += 1;
+        }
+
+        if columns.is_empty() {
+            return Err(SoundingError::parse(line_num, "missing column names"));
+        }
+
+        let mut rows = vec![];
+        let mut times = vec![];
+        while let Some((row_line, tok)) = self.peek() {
+            if tok.parse::<f64>().is_err() {
+                break;
+            }
+            if self.idx + columns.len() > self.tokens.len() {
+                return Err(SoundingError::parse(row_line, "incomplete row"));
+            }
+
+            let mut row = Vec::with_capacity(columns.len());
+            let mut time = None;
+            for (line, tok) in &self.tokens[self.idx..self.idx + columns.len()] {
+                if tok.contains('/') {
+                    let vt = NaiveDateTime::parse_from_str(tok, TIME_FORMAT).map_err(|_| {
+                        SoundingError::parse(*line, format!("invalid time '{}'", tok))
+                    })?;
+                    time = Some(vt);
+                    row.push(None);
+                } else {
+                    let val = tok.parse::<f64>().map_err(|_| {
+                        SoundingError::parse(*line, format!("invalid value '{}'", tok))
+                    })?;
+                    row.push(if val == MISSING { None } else { Some(val) });
+                }
+            }
+            self.idx += columns.len();
+
+            rows.push(row);
+            times.push(time);
+        }
+
+        Ok(Table {
+            columns,
+            rows,
+            times,
+        })
+    }
+}
+
+fn build_sounding(
+    header: &HashMap<String, String>,
+    table: &Table,
+    surface: Option<&Table>,
+) -> Result<Sounding> {
+    let number = |key: &str| header.get(key).and_then(|val| val.parse::<f64>().ok());
+
+    let station_num = number("STNM").map(|num| num as i32);
+    let location = match (number("SLAT"), number("SLON")) {
+        (Some(lat), Some(lon)) => Some((lat, lon)),
+        _ => None,
+    };
+    let valid_time = header
+        .get("TIME")
+        .and_then(|vt| NaiveDateTime::parse_from_str(vt, TIME_FORMAT).ok());
+    let description = header.get("STID").filter(|stid| !stid.is_empty()).cloned();
+
+    let stn = StationInfo::new()
+        .with_station(station_num)
+        .with_lat_lon(location)
+        .with_elevation(number("SELV").map(Meters));
+
+    let mut snd = Sounding::new()
+        .with_source_description(description)
+        .with_station_info(stn)
+        .with_valid_time(valid_time)
+        .with_lead_time(number("STIM").map(|lt| lt as i32));
+
+    let sfc_row = surface.and_then(|sfc| {
+        (0..sfc.rows.len())
+            .find(|&i| {
+                sfc.times[i] == valid_time && sfc.get(i, "STN").map(|num| num as i32) == station_num
+            })
+            .map(|i| (sfc, i))
+    });
+    if let Some((sfc, i)) = sfc_row {
+        let sfc_wind = match (sfc.get(i, "UWND"), sfc.get(i, "VWND")) {
+            (Some(u), Some(v)) => some(WindUV {
+                u: MetersPSec(u),
+                v: MetersPSec(v),
+            }),
+            _ => none(),
+        };
+
+        snd = snd
+            .with_mslp(sfc.get(i, "PMSL").map(HectoPascal))
+            .with_station_pressure(sfc.get(i, "PRES").map(HectoPascal))
+            .with_sfc_temperature(sfc.get(i, "T2MS").map(Celsius))
+            .with_sfc_dew_point(sfc.get(i, "TD2M").map(Celsius))
+            .with_sfc_wind(sfc_wind);
+    }
+
+    let profile = |column: &str| -> Vec<Option<f64>> {
+        if table.has(column) {
+            (0..table.rows.len())
+                .map(|i| table.get(i, column))
+                .collect()
+        } else {
+            vec![]
+        }
+    };
+    fn map<T: optional::Noned + Copy>(vals: Vec<Option<f64>>, f: fn(f64) -> T) -> Vec<Optioned<T>> {
+        vals.into_iter()
+            .map(|val| Optioned::from(val.map(f)))
+            .collect()
+    }
+
+    let wind: Vec<Optioned<WindSpdDir<Knots>>> = profile("DRCT")
+        .into_iter()
+        .zip(profile("SKNT"))
+        .map(|(direction, speed)| match (direction, speed) {
+            (Some(direction), Some(speed)) => some(WindSpdDir {
+                speed: Knots(speed),
+                direction,
+            }),
+            _ => none(),
+        })
+        .collect();
+
+    Ok(snd
+        .with_pressure_profile(map(profile("PRES"), HectoPascal))
+        .with_temperature_profile(map(profile("TMPC"), Celsius))
+        .with_wet_bulb_profile(map(profile("TMWC"), Celsius))
+        .with_dew_point_profile(map(profile("DWPC"), Celsius))
+        .with_theta_e_profile(map(profile("THTE"), Kelvin))
+        .with_wind_profile(wind)
+        .with_pvv_profile(map(profile("OMEG"), PaPS))
+        .with_cloud_fraction_profile(map(profile("CFRL"), |cld| cld))
+        .with_height_profile(map(profile("HGHT"), Meters)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_multiple_soundings_and_blank_station_id() {
+        let text = "\
+STID = STNM = 727730 TIME = 170401/0000
+STIM = 0
+PRES TMPC
+870.0 4.0 700.0 -6.0
+STID = STNM = 727730 TIME = 170401/0100
+STIM = 1
+PRES TMPC
+871.0 5.0 700.0 -5.0
+STN YYMMDD/HHMM PRES T2MS
+727730 170401/0100 873.0 7.0
+";
+
+        let soundings = read_bufkit(text.as_bytes()).unwrap();
+        assert_eq!(soundings.len(), 2);
+
+        assert!(soundings[0].source_description().is_none());
+        assert!(soundings[0].station_pressure().is_none());
+        assert_eq!(soundings[1].lead_time().unwrap(), 1);
+        assert_eq!(soundings[1].station_pressure().unwrap(), HectoPascal(873.0));
+        assert_eq!(
+            soundings[1].pressure_profile()[1].unwrap(),
+            HectoPascal(871.0)
+        );
+        assert!(soundings[1].wind_profile().is_empty());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let text = "STID = KMSO STNM = 727730 TIME = 170401/0000\nPRES TMPC\n870.0 4.0 700.0\n";
+        match read_bufkit(text.as_bytes()) {
+            Err(SoundingError::Parse { line, .. }) => assert_eq!(line, 3),
+            res => panic!("expected a parse error, got {:?}", res),
+        }
+
+        let text = "STID = KMSO\nPRES TMPC\n870.0 abc\n";
+        assert!(read_bufkit(text.as_bytes()).is_err());
+    }
+}
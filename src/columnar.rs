@@ -0,0 +1,292 @@
+//! Columnar export of sounding archives to Apache Arrow, enabled with the `arrow` feature, and to
+//! Parquet files, enabled with the `parquet` feature.
+//!
+//! Each level of each sounding becomes a row. The station, valid time, and lead time are repeated
+//! on every row so the table can be filtered and grouped without any joins. The profile columns
+//! are named with `ProfileVariable::name`, the same as the headers written by `CsvWriter`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::builder::{Float64Builder, Int32Builder, TimestampSecondBuilder, UInt32Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+
+use crate::data_row::ProfileVariable;
+use crate::sounding::Sounding;
+
+/// The Arrow schema of the table created by `to_record_batch`.
+///
+/// The columns are `station_num`, `latitude_deg`, `longitude_deg`, `elevation_m`, `valid_time`
+/// (seconds since the Unix epoch, UTC), `lead_time_hours`, `level` (0 is the surface, counting
+/// up), and then one column per `ProfileVariable`. All columns except `level` are nullable.
+///
+/// The columns with units also have them in a `units` metadata entry, e.g. `degC`.
+pub fn arrow_schema() -> SchemaRef {
+    let with_units = |field: Field, units: &str| {
+        field.with_metadata(HashMap::from([("units".to_owned(), units.to_owned())]))
+    };
+
+    let mut fields = vec![
+        Field::new("station_num", DataType::Int32, true),
+        with_units(
+            Field::new("latitude_deg", DataType::Float64, true),
+            "degrees_north",
+        ),
+        with_units(
+            Field::new("longitude_deg", DataType::Float64, true),
+            "degrees_east",
+        ),
+        with_units(Field::new("elevation_m", DataType::Float64, true), "m"),
+        Field::new(
+            "valid_time",
+            DataType::Timestamp(TimeUnit::Second, None),
+            true,
+        ),
+        with_units(
+            Field::new("lead_time_hours", DataType::Int32, true),
+            "hours",
+        ),
+        Field::new("level", DataType::UInt32, false),
+    ];
+    fields.extend(
+        ProfileVariable::ALL
+            .iter()
+            .map(|&var| with_units(Field::new(var.name(), DataType::Float64, true), units(var))),
+    );
+
+    Arc::new(Schema::new(fields))
+}
+
+/// The units of a profile column, in the same notation as the netCDF writer.
+fn units(var: ProfileVariable) -> &'static str {
+    use crate::data_row::ProfileVariable::*;
+
+    match var {
+        Pressure => "hPa",
+        Temperature | WetBulb | DewPoint => "degC",
+        ThetaE => "K",
+        WindDirection => "degree",
+        WindSpeed => "kt",
+        Pvv => "Pa/s",
+        Height => "m",
+        CloudFraction => "percent",
+    }
+}
+
+/// Convert a collection of soundings into a single Arrow record batch with one row per level.
+///
+/// # Examples
+///
+/// ```rust
+/// use sounding_base::to_record_batch;
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let soundings = vec![make_test_sounding(), make_test_sounding().with_lead_time(6)];
+///
+/// let batch = to_record_batch(&soundings).unwrap();
+/// assert_eq!(batch.num_rows(), 10); // 5 levels, including the surface, in each sounding.
+/// assert!(batch.column_by_name("temperature_C").is_some());
+/// ```
+pub fn to_record_batch<'a, I>(soundings: I) -> Result<RecordBatch, ArrowError>
+where
+    I: IntoIterator<Item = &'a Sounding>,
+{
+    let mut station_num = Int32Builder::new();
+    let mut latitude = Float64Builder::new();
+    let mut longitude = Float64Builder::new();
+    let mut elevation = Float64Builder::new();
+    let mut valid_time = TimestampSecondBuilder::new();
+    let mut lead_time = Int32Builder::new();
+    let mut level = UInt32Builder::new();
+    let mut profiles: Vec<Float64Builder> = ProfileVariable::ALL
+        .iter()
+        .map(|_| Float64Builder::new())
+        .collect();
+
+    for snd in soundings {
+        let stn = snd.station_info();
+        let stn_num = stn.station_num().into_option();
+        let location = stn.location();
+        let elev = stn.elevation().map(|elev| elev.0);
+        let vt = snd.valid_time().map(|vt| vt.and_utc().timestamp());
+        let lt = snd.lead_time().into_option();
+
+        for (i, row) in snd.bottom_up().enumerate() {
+            station_num.append_option(stn_num);
+            latitude.append_option(location.map(|(lat, _)| lat));
+            longitude.append_option(location.map(|(_, lon)| lon));
+            elevation.append_option(elev);
+            valid_time.append_option(vt);
+            lead_time.append_option(lt);
+            level.append_value(i as u32);
+
+            for (var, builder) in ProfileVariable::ALL.iter().zip(profiles.iter_mut()) {
+                builder.append_option(var.value(&row).into_option());
+            }
+        }
+    }
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(station_num.finish()),
+        Arc::new(latitude.finish()),
+        Arc::new(longitude.finish()),
+        Arc::new(elevation.finish()),
+        Arc::new(valid_time.finish()),
+        Arc::new(lead_time.finish()),
+        Arc::new(level.finish()),
+    ];
+    columns.extend(
+        profiles
+            .iter_mut()
+            .map(|builder| Arc::new(builder.finish()) as ArrayRef),
+    );
+
+    RecordBatch::try_new(arrow_schema(), columns)
+}
+
+/// Write a collection of soundings to a Parquet file with one row per level.
+///
+/// See `arrow_schema` for a description of the columns. The data is compressed with Snappy.
+///
+/// # Examples
+///
+/// ```rust
+/// use sounding_base::write_parquet;
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let soundings = vec![make_test_sounding(), make_test_sounding()];
+///
+/// let mut file: Vec<u8> = vec![];
+/// write_parquet(&soundings, &mut file).unwrap();
+/// assert_eq!(&file[..4], b"PAR1");
+/// ```
+#[cfg(feature = "parquet")]
+pub fn write_parquet<'a, I, W>(soundings: I, dest: W) -> parquet::errors::Result<()>
+where
+    I: IntoIterator<Item = &'a Sounding>,
+    W: std::io::Write + Send,
+{
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+
+    let batch = to_record_batch(soundings)?;
+
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(dest, batch.schema(), Some(props))?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, Int32Type, UInt32Type};
+    use arrow_array::Array;
+    use metfor::{Knots, WindSpdDir};
+    use optional::{none, some};
+
+    fn soundings() -> Vec<Sounding> {
+        let wind = some(WindSpdDir {
+            speed: Knots(20.0),
+            direction: 270.0,
+        });
+        vec![
+            make_test_sounding(),
+            make_test_sounding()
+                .with_lead_time(6)
+                .with_wind_profile(vec![wind, none(), wind, wind]),
+        ]
+    }
+
+    fn check_batch(batch: &RecordBatch) {
+        assert_eq!(batch.num_rows(), 10);
+
+        let level: Vec<u32> = batch
+            .column_by_name("level")
+            .unwrap()
+            .as_primitive::<UInt32Type>()
+            .values()
+            .to_vec();
+        assert_eq!(level, vec![0, 1, 2, 3, 4, 0, 1, 2, 3, 4]);
+
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+
+        let pressure = column("pressure_hPa");
+        let pressure = pressure.as_primitive::<Float64Type>();
+        assert_eq!(pressure.value(0), 1005.0); // The surface comes first.
+        assert_eq!(pressure.value(4), 700.0);
+        let temperature = column("temperature_C");
+        let temperature = temperature.as_primitive::<Float64Type>();
+        assert_eq!(temperature.value(0), 21.0);
+        assert_eq!(temperature.value(8), 10.0);
+
+        // The test sounding has no dew points, heights or station information.
+        assert_eq!(column("dew_point_C").null_count(), 10);
+        assert_eq!(column("height_m").null_count(), 10);
+        assert_eq!(column("station_num").null_count(), 10);
+        assert_eq!(column("valid_time").null_count(), 10);
+
+        let lead_time = column("lead_time_hours");
+        let lead_time = lead_time.as_primitive::<Int32Type>();
+        assert!(lead_time.is_null(4));
+        assert_eq!(lead_time.value(5), 6);
+
+        let speed = column("wind_speed_kt");
+        let speed = speed.as_primitive::<Float64Type>();
+        assert!(speed.is_null(5)); // No surface wind
+        assert_eq!(speed.value(6), 20.0);
+        assert!(speed.is_null(7));
+        assert_eq!(speed.value(9), 20.0);
+
+        let schema = batch.schema();
+        let units = |name: &str| {
+            schema
+                .field_with_name(name)
+                .unwrap()
+                .metadata()
+                .get("units")
+                .cloned()
+        };
+        assert_eq!(units("temperature_C").unwrap(), "degC");
+        assert_eq!(units("pressure_hPa").unwrap(), "hPa");
+        assert_eq!(units("pvv_Pa/s").unwrap(), "Pa/s");
+        assert_eq!(units("elevation_m").unwrap(), "m");
+        assert!(units("level").is_none());
+    }
+
+    #[test]
+    fn test_record_batch_values() {
+        let batch = to_record_batch(&soundings()).unwrap();
+        check_batch(&batch);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_round_trip() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let path = std::env::temp_dir().join(format!(
+            "sounding-base-columnar-{}.parquet",
+            std::process::id()
+        ));
+        write_parquet(&soundings(), std::fs::File::create(&path).unwrap()).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|batch| batch.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(batches.len(), 1);
+        check_batch(&batches[0]);
+    }
+}
@@ -0,0 +1,469 @@
+//! Delimited text (CSV, TSV) export and import of sounding profiles.
+//!
+//! Tables have a single header line naming the columns followed by one line per level. Values are
+//! split on the delimiter without any support for quoting, which is plenty for numeric tables.
+
+use std::io::{BufRead, Write};
+
+use metfor::{Knots, WindSpdDir};
+use optional::{none, some, Optioned};
+
+use crate::data_row::{DataRow, ProfileVariable};
+use crate::error::{Result, SoundingError};
+use crate::sounding::Sounding;
+use crate::station_info::StationInfo;
+
+/// Write the profiles of a sounding as a delimited table.
+///
+/// The header uses `ProfileVariable::name` for the column names, which includes the units.
+///
+/// # Examples
+///
+/// ```rust
+/// use sounding_base::{CsvWriter, ProfileVariable};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding();
+///
+/// let mut text: Vec<u8> = vec![];
+/// CsvWriter::new()
+///     .with_columns(&[ProfileVariable::Pressure, ProfileVariable::Temperature])
+///     .write(&snd, &mut text)
+///     .unwrap();
+///
+/// let text = String::from_utf8(text).unwrap();
+/// let mut lines = text.lines();
+/// assert_eq!(lines.next().unwrap(), "pressure_hPa,temperature_C");
+/// assert_eq!(lines.next().unwrap(), "1005.00,21.00"); // The surface comes first.
+/// assert_eq!(lines.next().unwrap(), "1000.00,20.00");
+/// ```
+#[derive(Clone, Debug)]
+pub struct CsvWriter {
+    columns: Vec<ProfileVariable>,
+    delimiter: char,
+    missing: String,
+    top_down: bool,
+}
+
+impl Default for CsvWriter {
+    fn default() -> Self {
+        CsvWriter {
+            columns: ProfileVariable::ALL.to_vec(),
+            delimiter: ',',
+            missing: String::new(),
+            top_down: false,
+        }
+    }
+}
+
+impl CsvWriter {
+    /// Create a new writer for comma separated values with all variables as columns, writing from
+    /// the surface up and leaving missing values empty.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder method to select the columns to write.
+    #[inline]
+    pub fn with_columns(mut self, columns: &[ProfileVariable]) -> Self {
+        self.columns = columns.to_vec();
+        self
+    }
+
+    /// Builder method to set the delimiter, e.g. `'\t'` for tab separated values.
+    #[inline]
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Builder method to set the text written for missing values.
+    #[inline]
+    pub fn with_missing_value(mut self, missing: &str) -> Self {
+        self.missing = missing.to_owned();
+        self
+    }
+
+    /// Builder method to write the rows with `top_down()` instead of `bottom_up()`.
+    #[inline]
+    pub fn with_top_down(mut self, top_down: bool) -> Self {
+        self.top_down = top_down;
+        self
+    }
+
+    /// Write the table.
+    pub fn write<W: Write>(&self, snd: &Sounding, mut dest: W) -> Result<()> {
+        let delimiter = self.delimiter.to_string();
+
+        let header: Vec<_> = self.columns.iter().map(|var| var.name()).collect();
+        writeln!(dest, "{}", header.join(&delimiter))?;
+
+        let rows: Box<dyn Iterator<Item = DataRow>> = if self.top_down {
+            Box::new(snd.top_down())
+        } else {
+            Box::new(snd.bottom_up())
+        };
+
+        for row in rows {
+            let vals: Vec<String> = self
+                .columns
+                .iter()
+                .map(|var| {
+                    var.value(&row)
+                        .map(|val| format!("{:.*}", precision(*var), val))
+                        .unwrap_or_else(|| self.missing.clone())
+                })
+                .collect();
+            writeln!(dest, "{}", vals.join(&delimiter))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of decimal places to write for a variable.
+#[inline]
+fn precision(var: ProfileVariable) -> usize {
+    match var {
+        ProfileVariable::Pvv => 3,
+        _ => 2,
+    }
+}
+
+/// A variable and an optional units conversion for a column.
+type ColumnMap = (ProfileVariable, Option<fn(f64) -> f64>);
+
+/// Read a sounding from a delimited table.
+///
+/// By default the columns are matched to variables using `ProfileVariable::name`, so tables
+/// written by `CsvWriter` can be read back directly. For arbitrary tables, map the columns by name
+/// with `with_column`, or `with_converted_column` when the values are in different units. These
+/// mappings are added to the default ones, and take the place of any column matched to the same
+/// variable by name. Columns that are not mapped are ignored.
+///
+/// The rows are expected to be levels of a profile. They may be in either order, they are sorted
+/// from the bottom up by the first and last pressure.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal, Knots, MetersPSec};
+/// use sounding_base::{CsvReader, ProfileVariable};
+///
+/// let text = "\
+/// Campaign sonde 42, launched 2018-03-08 12:00Z
+/// time,P(hPa),T(K),RH(%),wspd(m/s),wdir
+/// 0,902.0,283.65,72,1.6,200
+/// 10,850.0,278.75,-999,4.1,230
+/// 25,700.0,268.95,35,7.9,250
+/// ";
+///
+/// let snd = CsvReader::new()
+///     .with_skip_lines(1)
+///     .with_column("P(hPa)", ProfileVariable::Pressure)
+///     .with_converted_column("T(K)", ProfileVariable::Temperature, |t| t - 273.15)
+///     .with_converted_column("wspd(m/s)", ProfileVariable::WindSpeed, |s| {
+///         Knots::from(MetersPSec(s)).0
+///     })
+///     .with_column("wdir", ProfileVariable::WindDirection)
+///     .with_missing_value("-999")
+///     .with_surface_row(true)
+///     .read(text.as_bytes())
+///     .unwrap();
+///
+/// assert_eq!(snd.station_pressure().unwrap(), HectoPascal(902.0));
+/// assert_eq!(snd.pressure_profile().len(), 3);
+/// assert!((snd.temperature_profile()[2].unwrap() - Celsius(-4.2)).0.abs() < 1.0e-9);
+/// assert!(snd.dew_point_profile().is_empty());
+/// assert_eq!(snd.wind_profile()[1].unwrap().direction, 230.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CsvReader {
+    columns: Vec<(String, ColumnMap)>,
+    delimiter: char,
+    missing: Vec<String>,
+    skip_lines: usize,
+    surface_row: bool,
+}
+
+impl Default for CsvReader {
+    fn default() -> Self {
+        CsvReader {
+            columns: vec![],
+            delimiter: ',',
+            missing: vec![],
+            skip_lines: 0,
+            surface_row: false,
+        }
+    }
+}
+
+impl CsvReader {
+    /// Create a new reader for comma separated values that maps the columns by
+    /// `ProfileVariable::name`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder method to map the column with the header `name` to a variable.
+    #[inline]
+    pub fn with_column(mut self, name: &str, var: ProfileVariable) -> Self {
+        self.columns.push((name.to_owned(), (var, None)));
+        self
+    }
+
+    /// Builder method to map the column with the header `name` to a variable, converting the
+    /// values into the units of the variable with `convert`.
+    #[inline]
+    pub fn with_converted_column(
+        mut self,
+        name: &str,
+        var: ProfileVariable,
+        convert: fn(f64) -> f64,
+    ) -> Self {
+        self.columns.push((name.to_owned(), (var, Some(convert))));
+        self
+    }
+
+    /// Builder method to set the delimiter, e.g. `'\t'` for tab separated values.
+    #[inline]
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Builder method to add a value that marks missing data. Empty values and values that parse
+    /// as NaN are always treated as missing.
+    #[inline]
+    pub fn with_missing_value(mut self, missing: &str) -> Self {
+        self.missing.push(missing.to_owned());
+        self
+    }
+
+    /// Builder method to skip some lines before the header line.
+    #[inline]
+    pub fn with_skip_lines(mut self, skip_lines: usize) -> Self {
+        self.skip_lines = skip_lines;
+        self
+    }
+
+    /// Builder method to treat the lowest row as the surface values. Defaults to `false`, but
+    /// should be `true` for tables written by `CsvWriter`.
+    #[inline]
+    pub fn with_surface_row(mut self, surface_row: bool) -> Self {
+        self.surface_row = surface_row;
+        self
+    }
+
+    /// Read the table.
+    pub fn read<R: BufRead>(&self, src: R) -> Result<Sounding> {
+        let mut lines = src
+            .lines()
+            .enumerate()
+            .skip(self.skip_lines)
+            .map(|(i, line)| line.map(|line| (i + 1, line)))
+            .filter(|line| match line {
+                Ok((_, line)) => !line.trim().is_empty(),
+                Err(_) => true,
+            });
+
+        let (line_num, header) = lines.next().ok_or(SoundingError::UnexpectedEof)??;
+        let names: Vec<&str> = self.split(&header).collect();
+        let columns = self.map_columns(&names, line_num)?;
+
+        let mut rows: Vec<DataRow> = vec![];
+        for line in lines {
+            let (line_num, line) = line?;
+
+            let mut row = DataRow::default();
+            let mut direction: Optioned<f64> = none();
+            let mut speed: Optioned<f64> = none();
+
+            for (i, val) in self.split(&line).enumerate() {
+                let (var, convert) = match columns.get(i) {
+                    Some(Some(col)) => *col,
+                    _ => continue,
+                };
+
+                let val = self.parse_value(val, line_num)?;
+                let val = match convert {
+                    Some(convert) => val.map_t(convert),
+                    None => val,
+                };
+
+                match var {
+                    ProfileVariable::Pressure => row.pressure = val.map_t(metfor::HectoPascal),
+                    ProfileVariable::Temperature => row.temperature = val.map_t(metfor::Celsius),
+                    ProfileVariable::WetBulb => row.wet_bulb = val.map_t(metfor::Celsius),
+                    ProfileVariable::DewPoint => row.dew_point = val.map_t(metfor::Celsius),
+                    ProfileVariable::ThetaE => row.theta_e = val.map_t(metfor::Kelvin),
+                    ProfileVariable::WindDirection => direction = val,
+                    ProfileVariable::WindSpeed => speed = val,
+                    ProfileVariable::Pvv => row.pvv = val.map_t(metfor::PaPS),
+                    ProfileVariable::Height => row.height = val.map_t(metfor::Meters),
+                    ProfileVariable::CloudFraction => row.cloud_fraction = val,
+                }
+            }
+
+            if let (Some(direction), Some(speed)) = (direction.into_option(), speed.into_option()) {
+                row.wind = some(WindSpdDir {
+                    speed: Knots(speed),
+                    direction,
+                });
+            }
+
+            rows.push(row);
+        }
+
+        // Put the rows in bottom up order.
+        let first_p = rows.iter().find_map(|row| row.pressure.into_option());
+        let last_p = rows.iter().rev().find_map(|row| row.pressure.into_option());
+        if let (Some(first_p), Some(last_p)) = (first_p, last_p) {
+            if first_p < last_p {
+                rows.reverse();
+            }
+        }
+
+        let mut stn = StationInfo::new();
+        if self.surface_row {
+            if let Some(sfc) = rows.first() {
+                stn = stn.with_elevation(sfc.height);
+            }
+        } else {
+            rows.insert(0, DataRow::default());
+        }
+
+        Ok(Sounding::new()
+            .with_station_info(stn)
+            .with_data_rows(&rows)
+            .fill_wet_bulb_and_theta_e())
+    }
+
+    #[inline]
+    fn split<'a>(&self, line: &'a str) -> impl Iterator<Item = &'a str> {
+        line.split(self.delimiter)
+            .map(|val| val.trim().trim_matches('"').trim())
+    }
+
+    /// Find the variable and conversion for each column in the header.
+    fn map_columns(&self, names: &[&str], line_num: usize) -> Result<Vec<Option<ColumnMap>>> {
+        let mut columns: Vec<Option<ColumnMap>> = names
+            .iter()
+            .map(|name| ProfileVariable::from_name(name).map(|var| (var, None)))
+            .collect();
+
+        for (name, col) in &self.columns {
+            let idx = names.iter().position(|n| n == name).ok_or_else(|| {
+                SoundingError::parse(line_num, format!("column '{}' not found", name))
+            })?;
+
+            // An explicit mapping replaces any column matched to the same variable by name.
+            for other in columns.iter_mut() {
+                if other.map(|(var, _)| var) == Some(col.0) {
+                    *other = None;
+                }
+            }
+            columns[idx] = Some(*col);
+        }
+
+        Ok(columns)
+    }
+
+    fn parse_value(&self, val: &str, line_num: usize) -> Result<Optioned<f64>> {
+        if val.is_empty() || self.missing.iter().any(|missing| missing == val) {
+            return Ok(none());
+        }
+
+        val.parse::<f64>()
+            .map(Optioned::from)
+            .map_err(|_| SoundingError::parse(line_num, format!("invalid value '{}'", val)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+    use metfor::{Celsius, PaPS};
+
+    #[test]
+    fn test_tsv_round_trip_top_down() {
+        let snd = make_test_sounding();
+
+        let mut text: Vec<u8> = vec![];
+        CsvWriter::new()
+            .with_delimiter('\t')
+            .with_top_down(true)
+            .with_missing_value("NA")
+            .write(&snd, &mut text)
+            .unwrap();
+
+        let snd2 = CsvReader::new()
+            .with_delimiter('\t')
+            .with_missing_value("NA")
+            .with_surface_row(true)
+            .read(text.as_slice())
+            .unwrap();
+
+        assert_eq!(snd.pressure_profile(), snd2.pressure_profile());
+        assert_eq!(snd.temperature_profile(), snd2.temperature_profile());
+        assert_eq!(snd.station_pressure(), snd2.station_pressure());
+        assert!(snd2.wind_profile().is_empty());
+    }
+
+    #[test]
+    fn test_write_empty_sounding() {
+        for &top_down in &[false, true] {
+            let mut text: Vec<u8> = vec![];
+            CsvWriter::new()
+                .with_top_down(top_down)
+                .write(&Sounding::new(), &mut text)
+                .unwrap();
+
+            let text = String::from_utf8(text).unwrap();
+            assert_eq!(text.lines().count(), 1);
+            assert!(text.starts_with("pressure_hPa,"));
+        }
+    }
+
+    #[test]
+    fn test_explicit_columns_merge_with_defaults() {
+        let text =
+            "pressure_hPa,temperature_C,T(K),dew_point_C\n1000,0,293.15,15\n850,0,283.15,5\n";
+
+        let snd = CsvReader::new()
+            .with_converted_column("T(K)", ProfileVariable::Temperature, |t| t - 273.15)
+            .read(text.as_bytes())
+            .unwrap();
+
+        assert_eq!(snd.pressure_profile().len(), 3);
+        assert!((snd.temperature_profile()[1].unwrap().0 - 20.0).abs() < 1.0e-9);
+        assert!((snd.temperature_profile()[2].unwrap().0 - 10.0).abs() < 1.0e-9);
+        assert_eq!(snd.dew_point_profile()[1], some(Celsius(15.0)));
+    }
+
+    #[test]
+    fn test_surface_row_values_kept() {
+        let text = "pressure_hPa,temperature_C,pvv_Pa/s,cloud_fraction_pct\n1000,20,-0.5,25\n850,10,0.1,50\n";
+
+        let snd = CsvReader::new()
+            .with_surface_row(true)
+            .read(text.as_bytes())
+            .unwrap();
+
+        assert_eq!(snd.pvv_profile()[0], some(PaPS(-0.5)));
+        assert_eq!(snd.cloud_fraction_profile()[0], some(25.0));
+        assert_eq!(snd.pvv_profile()[1], some(PaPS(0.1)));
+    }
+
+    #[test]
+    fn test_missing_mapped_column() {
+        let res = CsvReader::new()
+            .with_column("pres", ProfileVariable::Pressure)
+            .read("p,t\n1000,20\n".as_bytes());
+
+        assert!(res.is_err());
+    }
+}
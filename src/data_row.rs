@@ -1,4 +1,4 @@
-use metfor::{Celsius, HectoPascal, Kelvin, Knots, Meters, PaPS, WindSpdDir};
+use metfor::{Celsius, HectoPascal, Kelvin, Knots, Meters, PaPS, Quantity, WindSpdDir};
 use optional::Optioned;
 
 /// A copy of a row of the sounding data.
@@ -23,3 +23,100 @@ pub struct DataRow {
     /// Cloud fraction in percent
     pub cloud_fraction: Optioned<f64>,
 }
+
+/// The scalar variables available in a `DataRow`, with the wind split into direction and speed.
+///
+/// This is useful for selecting variables for output, e.g. as the columns of a table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProfileVariable {
+    /// Pressure in hPa
+    Pressure,
+    /// Temperature in C
+    Temperature,
+    /// Wet bulb temperature in C
+    WetBulb,
+    /// Dew point in C
+    DewPoint,
+    /// Equivalent potential temperature in Kelvin
+    ThetaE,
+    /// Direction the wind is blowing from in degrees
+    WindDirection,
+    /// Wind speed in knots
+    WindSpeed,
+    /// Pressure vertical velocity in Pa/sec
+    Pvv,
+    /// Geopotential Height in meters
+    Height,
+    /// Cloud fraction in percent
+    CloudFraction,
+}
+
+impl ProfileVariable {
+    /// All the variables, in the same order as the fields of `DataRow`.
+    pub const ALL: [ProfileVariable; 10] = [
+        ProfileVariable::Pressure,
+        ProfileVariable::Temperature,
+        ProfileVariable::WetBulb,
+        ProfileVariable::DewPoint,
+        ProfileVariable::ThetaE,
+        ProfileVariable::WindDirection,
+        ProfileVariable::WindSpeed,
+        ProfileVariable::Pvv,
+        ProfileVariable::Height,
+        ProfileVariable::CloudFraction,
+    ];
+
+    /// A short name for the variable with a units suffix, e.g. `temperature_C`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use sounding_base::ProfileVariable;
+    ///
+    /// for var in ProfileVariable::ALL.iter() {
+    ///     assert_eq!(ProfileVariable::from_name(var.name()), Some(*var));
+    /// }
+    /// ```
+    pub fn name(self) -> &'static str {
+        use crate::data_row::ProfileVariable::*;
+
+        match self {
+            Pressure => "pressure_hPa",
+            Temperature => "temperature_C",
+            WetBulb => "wet_bulb_C",
+            DewPoint => "dew_point_C",
+            ThetaE => "theta_e_K",
+            WindDirection => "wind_direction_deg",
+            WindSpeed => "wind_speed_kt",
+            Pvv => "pvv_Pa/s",
+            Height => "height_m",
+            CloudFraction => "cloud_fraction_pct",
+        }
+    }
+
+    /// Find the variable with the given `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        ProfileVariable::ALL
+            .iter()
+            .cloned()
+            .find(|var| var.name() == name)
+    }
+
+    /// Get the value of this variable from a `DataRow`.
+    pub fn value(self, row: &DataRow) -> Optioned<f64> {
+        use crate::data_row::ProfileVariable::*;
+
+        match self {
+            Pressure => row.pressure.map_t(Quantity::unpack),
+            Temperature => row.temperature.map_t(Quantity::unpack),
+            WetBulb => row.wet_bulb.map_t(Quantity::unpack),
+            DewPoint => row.dew_point.map_t(Quantity::unpack),
+            ThetaE => row.theta_e.map_t(Quantity::unpack),
+            WindDirection => row.wind.map_t(|wind| wind.direction),
+            WindSpeed => row.wind.map_t(|wind| wind.speed.unpack()),
+            Pvv => row.pvv.map_t(Quantity::unpack),
+            Height => row.height.map_t(Quantity::unpack),
+            CloudFraction => row.cloud_fraction,
+        }
+    }
+}
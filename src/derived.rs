@@ -0,0 +1,343 @@
+//! Profiles derived from the stored variables, e.g. relative humidity and potential temperature.
+
+use std::sync::OnceLock;
+
+use metfor::{HectoPascal, Kelvin, Quantity};
+use optional::Optioned;
+
+use crate::data_row::DataRow;
+use crate::sounding::Sounding;
+
+/// A `DataRow` with extra values derived from it.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal};
+/// use optional::some;
+/// use sounding_base::{DataRow, ExtendedDataRow};
+///
+/// let row = DataRow {
+///     pressure: some(HectoPascal(850.0)),
+///     temperature: some(Celsius(10.0)),
+///     dew_point: some(Celsius(10.0)),
+///     ..DataRow::default()
+/// };
+///
+/// let ext = ExtendedDataRow::from(row);
+/// assert!((ext.relative_humidity.unwrap() - 1.0).abs() < 1.0e-9);
+/// assert!(ext.relative_humidity_ice.is_none()); // Above freezing
+/// assert!(ext.mixing_ratio.unwrap() > ext.specific_humidity.unwrap());
+/// assert_eq!(ext.row.pressure, row.pressure);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExtendedDataRow {
+    /// The stored values.
+    pub row: DataRow,
+    /// Relative humidity with respect to liquid water from 0.0 to 1.0
+    pub relative_humidity: Optioned<f64>,
+    /// Relative humidity with respect to ice from 0.0 to 1.0, missing above freezing
+    pub relative_humidity_ice: Optioned<f64>,
+    /// Mixing ratio in kg/kg
+    pub mixing_ratio: Optioned<f64>,
+    /// Specific humidity in kg/kg
+    pub specific_humidity: Optioned<f64>,
+    /// Vapor pressure in hPa
+    pub vapor_pressure: Optioned<HectoPascal>,
+}
+
+impl From<DataRow> for ExtendedDataRow {
+    fn from(row: DataRow) -> Self {
+        ExtendedDataRow {
+            row,
+            relative_humidity: relative_humidity(&row).into(),
+            relative_humidity_ice: relative_humidity_ice(&row).into(),
+            mixing_ratio: mixing_ratio(&row).into(),
+            specific_humidity: specific_humidity(&row).into(),
+            vapor_pressure: vapor_pressure(&row).into(),
+        }
+    }
+}
+
+/// Thermodynamic profiles, calculated the first time they are requested.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DerivedCache {
+    theta: OnceLock<Vec<Optioned<Kelvin>>>,
+    theta_v: OnceLock<Vec<Optioned<Kelvin>>>,
+    virtual_temperature: OnceLock<Vec<Optioned<Kelvin>>>,
+    density: OnceLock<Vec<Optioned<f64>>>,
+    theta_w: OnceLock<Vec<Optioned<Kelvin>>>,
+}
+
+/// Thermodynamic profiles calculated from the temperature, dew point and pressure.
+///
+/// These are calculated the first time they are requested and then stored, until a builder method
+/// changes the profiles they depend on. A value is missing if any of its inputs are missing, and
+/// if there is no temperature profile the result is empty. Where there is no dew point, the
+/// virtual temperature and the profiles calculated from it use the temperature instead.
+impl Sounding {
+    /// Get the potential temperature profile.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::{Celsius, Kelvin};
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let snd = make_test_sounding();
+    /// let theta = snd.theta_profile();
+    /// assert_eq!(theta.len(), 5);
+    /// assert!((theta[1].unwrap().0 - 293.15).abs() < 1.0e-9); // 20 C at 1000 hPa
+    ///
+    /// // Without a dew point the virtual temperature is the temperature.
+    /// assert_eq!(snd.virtual_temperature_profile()[1].unwrap(), Kelvin(293.15));
+    ///
+    /// let dew_points = vec![15.0, 10.0, 0.0, -10.0].into_iter().map(Celsius).map(some).collect();
+    /// let snd = snd.with_dew_point_profile(dew_points);
+    /// assert_eq!(snd.virtual_temperature_profile()[0].unwrap(), Kelvin(294.15)); // Surface
+    /// assert!(snd.virtual_temperature_profile()[1].unwrap() > Kelvin(293.15));
+    ///
+    /// let density = snd.density_profile()[1].unwrap();
+    /// assert!(density > 1.1 && density < 1.3);
+    ///
+    /// let theta_w = snd.theta_w_profile()[1].unwrap();
+    /// assert!(theta_w > Kelvin(288.15) && theta_w < Kelvin(293.15));
+    /// ```
+    pub fn theta_profile(&self) -> &[Optioned<Kelvin>] {
+        self.derived_cache()
+            .theta
+            .get_or_init(|| self.thermo_profile(theta))
+    }
+
+    /// Get the virtual potential temperature profile.
+    pub fn theta_v_profile(&self) -> &[Optioned<Kelvin>] {
+        self.derived_cache()
+            .theta_v
+            .get_or_init(|| self.thermo_profile(theta_v))
+    }
+
+    /// Get the virtual temperature profile.
+    pub fn virtual_temperature_profile(&self) -> &[Optioned<Kelvin>] {
+        self.derived_cache()
+            .virtual_temperature
+            .get_or_init(|| self.thermo_profile(virtual_temperature))
+    }
+
+    /// Get the air density profile in kg/m^3.
+    pub fn density_profile(&self) -> &[Optioned<f64>] {
+        self.derived_cache()
+            .density
+            .get_or_init(|| self.thermo_profile(density))
+    }
+
+    /// Get the wet bulb potential temperature profile.
+    pub fn theta_w_profile(&self) -> &[Optioned<Kelvin>] {
+        self.derived_cache()
+            .theta_w
+            .get_or_init(|| self.thermo_profile(theta_w))
+    }
+
+    fn thermo_profile<T, F>(&self, f: F) -> Vec<Optioned<T>>
+    where
+        T: optional::Noned + Copy,
+        F: Fn(&DataRow) -> Option<T>,
+    {
+        if self.temperature_profile().is_empty() {
+            return vec![];
+        }
+
+        self.bottom_up().map(|row| f(&row).into()).collect()
+    }
+}
+
+/// Moisture profiles calculated on demand from the temperature, dew point and pressure.
+///
+/// Like the stored profiles, the surface is first, and if there is no dew point profile the result
+/// is empty.
+impl Sounding {
+    /// Get the relative humidity profile with respect to liquid water from 0.0 to 1.0.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::Celsius;
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let snd = make_test_sounding();
+    /// assert!(snd.relative_humidity_profile().is_empty());
+    ///
+    /// let dew_points = vec![20.0, 5.0, 0.0, -2.0].into_iter().map(Celsius).map(some).collect();
+    /// let snd = snd
+    ///     .with_sfc_dew_point(Celsius(11.0))
+    ///     .with_dew_point_profile(dew_points);
+    ///
+    /// let rh = snd.relative_humidity_profile();
+    /// assert_eq!(rh.len(), 5);
+    /// assert!((rh[1].unwrap() - 1.0).abs() < 1.0e-9);
+    /// assert!(rh[2].unwrap() < 0.5);
+    /// ```
+    pub fn relative_humidity_profile(&self) -> Vec<Optioned<f64>> {
+        self.moisture_profile(relative_humidity)
+    }
+
+    /// Get the relative humidity profile with respect to ice from 0.0 to 1.0. Values above
+    /// freezing are missing.
+    pub fn relative_humidity_ice_profile(&self) -> Vec<Optioned<f64>> {
+        self.moisture_profile(relative_humidity_ice)
+    }
+
+    /// Get the mixing ratio profile in kg/kg.
+    pub fn mixing_ratio_profile(&self) -> Vec<Optioned<f64>> {
+        self.moisture_profile(mixing_ratio)
+    }
+
+    /// Get the specific humidity profile in kg/kg.
+    pub fn specific_humidity_profile(&self) -> Vec<Optioned<f64>> {
+        self.moisture_profile(specific_humidity)
+    }
+
+    /// Get the vapor pressure profile.
+    pub fn vapor_pressure_profile(&self) -> Vec<Optioned<HectoPascal>> {
+        self.moisture_profile(vapor_pressure)
+    }
+
+    /// Get a bottom up iterator over the data rows with derived values added. The first value is
+    /// the surface.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let snd = make_test_sounding();
+    /// for ext in snd.bottom_up_extended() {
+    ///     assert!(ext.row.temperature.is_some());
+    ///     assert!(ext.mixing_ratio.is_none()); // There are no dew points.
+    /// }
+    /// ```
+    #[inline]
+    pub fn bottom_up_extended<'a>(&'a self) -> impl Iterator<Item = ExtendedDataRow> + 'a {
+        self.bottom_up().map(ExtendedDataRow::from)
+    }
+
+    /// Get a top down iterator over the data rows with derived values added. The last value is
+    /// the surface.
+    #[inline]
+    pub fn top_down_extended<'a>(&'a self) -> impl Iterator<Item = ExtendedDataRow> + 'a {
+        self.top_down().map(ExtendedDataRow::from)
+    }
+
+    fn moisture_profile<T, F>(&self, f: F) -> Vec<Optioned<T>>
+    where
+        T: optional::Noned + Copy,
+        F: Fn(&DataRow) -> Option<T>,
+    {
+        if self.dew_point_profile().is_empty() {
+            return vec![];
+        }
+
+        self.bottom_up().map(|row| f(&row).into()).collect()
+    }
+}
+
+fn relative_humidity(row: &DataRow) -> Option<f64> {
+    metfor::rh(row.temperature.into_option()?, row.dew_point.into_option()?)
+}
+
+/// The relative humidity with respect to ice, from the dew point over liquid water.
+pub(crate) fn relative_humidity_ice(row: &DataRow) -> Option<f64> {
+    let t = row.temperature.into_option()?;
+    let dp = row.dew_point.into_option()?;
+
+    if t.unpack() > 0.0 {
+        return None;
+    }
+
+    let e = metfor::vapor_pressure_liquid_water(dp)?;
+    let ei = metfor::vapor_pressure_ice(t)?;
+
+    Some(e.unpack() / ei.unpack())
+}
+
+fn mixing_ratio(row: &DataRow) -> Option<f64> {
+    metfor::mixing_ratio(row.dew_point.into_option()?, row.pressure.into_option()?)
+}
+
+fn specific_humidity(row: &DataRow) -> Option<f64> {
+    metfor::specific_humidity(row.dew_point.into_option()?, row.pressure.into_option()?)
+}
+
+fn vapor_pressure(row: &DataRow) -> Option<HectoPascal> {
+    metfor::vapor_pressure_liquid_water(row.dew_point.into_option()?)
+}
+
+fn theta(row: &DataRow) -> Option<Kelvin> {
+    let t = row.temperature.into_option()?;
+    let p = row.pressure.into_option()?;
+
+    Some(metfor::theta(p, t))
+}
+
+fn theta_v(row: &DataRow) -> Option<Kelvin> {
+    let tv = virtual_temperature(row)?;
+    let p = row.pressure.into_option()?;
+
+    Some(metfor::theta(p, tv))
+}
+
+/// The virtual temperature, or the temperature if there is no dew point to correct it with.
+pub(crate) fn virtual_temperature(row: &DataRow) -> Option<Kelvin> {
+    let t = row.temperature.into_option()?;
+    let p = row.pressure.into_option()?;
+
+    row.dew_point
+        .into_option()
+        .and_then(|dp| metfor::virtual_temperature(t, dp, p))
+        .or_else(|| Some(Kelvin::from(t)))
+}
+
+/// The air density in kg/m^3, from the virtual temperature.
+pub(crate) fn density(row: &DataRow) -> Option<f64> {
+    let tv = virtual_temperature(row)?;
+    let p = row.pressure.into_option()?;
+
+    // Convert hPa to Pa
+    Some(p.unpack() * 100.0 / (metfor::Rd.unpack() * tv.unpack()))
+}
+
+fn theta_w(row: &DataRow) -> Option<Kelvin> {
+    let theta_e = metfor::theta_e(
+        row.temperature.into_option()?,
+        row.dew_point.into_option()?,
+        row.pressure.into_option()?,
+    )?;
+
+    metfor::temperature_from_theta_e_saturated_and_pressure(HectoPascal(1000.0), theta_e)
+        .map(Kelvin::from)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sounding::doctest::make_test_sounding;
+    use metfor::Celsius;
+    use optional::some;
+
+    #[test]
+    fn test_cache_reset_by_builders() {
+        let snd = make_test_sounding();
+        let theta_sfc = snd.theta_profile()[0].unwrap();
+        let theta_1000 = snd.theta_profile()[1].unwrap();
+
+        let snd = snd.with_sfc_temperature(Celsius(30.0));
+        assert!(snd.theta_profile()[0].unwrap() > theta_sfc);
+
+        let snd = snd.with_temperature_profile(vec![some(Celsius(0.0)); 4]);
+        assert!(snd.theta_profile()[1].unwrap() < theta_1000);
+
+        // Clones keep the cached values.
+        let copy = snd.clone();
+        assert_eq!(copy.theta_profile(), snd.theta_profile());
+    }
+}
@@ -0,0 +1,351 @@
+//! Coordinate transforms for thermodynamic diagrams.
+//!
+//! Each transform maps a pressure and temperature to normalized diagram coordinates, where `x`
+//! increases to the right and `y` increases upward. The bottom edge of the diagram, at the bottom
+//! pressure, runs from `x = 0` at the left temperature to `x = 1` at the right temperature, and the
+//! top pressure is at `y = 1`. A renderer only has to scale and translate these coordinates to
+//! pixels, and the inverse transform turns a cursor position back into a pressure and temperature.
+
+use metfor::{Celsius, HectoPascal, Kelvin, Quantity};
+
+/// Map between pressure-temperature space and the coordinates of a thermodynamic diagram.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal, Quantity};
+/// use sounding_base::{DiagramTransform, TephigramTransform};
+///
+/// let teph = TephigramTransform::new(
+///     HectoPascal(100.0),
+///     HectoPascal(1050.0),
+///     Celsius(-40.0),
+///     Celsius(50.0),
+/// );
+///
+/// let (x, y) = teph.pt_to_xy(HectoPascal(500.0), Celsius(-10.0));
+/// let (p, t) = teph.xy_to_pt(x, y);
+///
+/// assert!((p.unpack() - 500.0).abs() < 1.0e-6);
+/// assert!((t.unpack() - -10.0).abs() < 1.0e-6);
+/// ```
+pub trait DiagramTransform {
+    /// Convert a pressure and temperature to diagram coordinates.
+    fn pt_to_xy(&self, p: HectoPascal, t: Celsius) -> (f64, f64);
+
+    /// Convert diagram coordinates to a pressure and temperature.
+    fn xy_to_pt(&self, x: f64, y: f64) -> (HectoPascal, Celsius);
+}
+
+/// The pressure and temperature ranges shared by all the diagrams.
+#[derive(Clone, Copy, Debug)]
+struct Bounds {
+    p_top: f64,
+    p_bottom: f64,
+    t_left: f64,
+    t_right: f64,
+}
+
+impl Bounds {
+    fn new<P, T>(p_top: P, p_bottom: P, t_left: T, t_right: T) -> Self
+    where
+        HectoPascal: From<P>,
+        Celsius: From<T>,
+    {
+        Bounds {
+            p_top: HectoPascal::from(p_top).unpack(),
+            p_bottom: HectoPascal::from(p_bottom).unpack(),
+            t_left: Celsius::from(t_left).unpack(),
+            t_right: Celsius::from(t_right).unpack(),
+        }
+    }
+
+    #[inline]
+    fn x(&self, t: f64) -> f64 {
+        (t - self.t_left) / (self.t_right - self.t_left)
+    }
+
+    #[inline]
+    fn t(&self, x: f64) -> f64 {
+        self.t_left + x * (self.t_right - self.t_left)
+    }
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Bounds {
+            p_top: 100.0,
+            p_bottom: 1050.0,
+            t_left: -40.0,
+            t_right: 50.0,
+        }
+    }
+}
+
+/// Rd / cp, the exponent relating potential temperature to pressure.
+#[inline]
+fn kappa() -> f64 {
+    metfor::Rd.unpack() / metfor::cp.unpack()
+}
+
+macro_rules! impl_new_and_default {
+    ($type:ident, $diagram:expr) => {
+        impl $type {
+            #[doc = "Create a "]
+            #[doc = $diagram]
+            #[doc = " for the given pressure range and the temperature range along the bottom."]
+            pub fn new<P, T>(p_top: P, p_bottom: P, t_left: T, t_right: T) -> Self
+            where
+                P: metfor::Pressure,
+                HectoPascal: From<P>,
+                T: metfor::Temperature,
+                Celsius: From<T>,
+            {
+                Self::from_bounds(Bounds::new(p_top, p_bottom, t_left, t_right))
+            }
+        }
+
+        impl Default for $type {
+            /// The range 1050 to 100 hPa and -40 to 50 C.
+            fn default() -> Self {
+                Self::from_bounds(Bounds::default())
+            }
+        }
+    };
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                             Skew-T
+--------------------------------------------------------------------------------------------------*/
+/// The skew-T log-P diagram. Isobars are horizontal and evenly spaced in log(p), isotherms are
+/// straight lines slanted to the right.
+#[derive(Clone, Copy, Debug)]
+pub struct SkewTTransform {
+    bounds: Bounds,
+    skew: f64,
+}
+
+impl_new_and_default!(SkewTTransform, "skew-T log-P diagram");
+
+impl SkewTTransform {
+    fn from_bounds(bounds: Bounds) -> Self {
+        SkewTTransform { bounds, skew: 1.0 }
+    }
+
+    /// Builder method to set how far the isotherms are shifted to the right at the top of the
+    /// diagram, in diagram widths. The default is 1.0, a value of 0.0 makes this an emagram.
+    #[inline]
+    pub fn with_skew(mut self, skew: f64) -> Self {
+        self.skew = skew;
+        self
+    }
+}
+
+impl DiagramTransform for SkewTTransform {
+    fn pt_to_xy(&self, p: HectoPascal, t: Celsius) -> (f64, f64) {
+        let y = log_p_y(&self.bounds, p.unpack());
+        (self.bounds.x(t.unpack()) + self.skew * y, y)
+    }
+
+    fn xy_to_pt(&self, x: f64, y: f64) -> (HectoPascal, Celsius) {
+        (
+            HectoPascal(log_p_inverse(&self.bounds, y)),
+            Celsius(self.bounds.t(x - self.skew * y)),
+        )
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                             Emagram
+--------------------------------------------------------------------------------------------------*/
+/// The emagram. Isobars are horizontal and evenly spaced in log(p), isotherms are vertical.
+#[derive(Clone, Copy, Debug)]
+pub struct EmagramTransform {
+    bounds: Bounds,
+}
+
+impl_new_and_default!(EmagramTransform, "emagram");
+
+impl EmagramTransform {
+    fn from_bounds(bounds: Bounds) -> Self {
+        EmagramTransform { bounds }
+    }
+}
+
+impl DiagramTransform for EmagramTransform {
+    fn pt_to_xy(&self, p: HectoPascal, t: Celsius) -> (f64, f64) {
+        (self.bounds.x(t.unpack()), log_p_y(&self.bounds, p.unpack()))
+    }
+
+    fn xy_to_pt(&self, x: f64, y: f64) -> (HectoPascal, Celsius) {
+        (
+            HectoPascal(log_p_inverse(&self.bounds, y)),
+            Celsius(self.bounds.t(x)),
+        )
+    }
+}
+
+#[inline]
+fn log_p_y(bounds: &Bounds, p: f64) -> f64 {
+    (bounds.p_bottom / p).ln() / (bounds.p_bottom / bounds.p_top).ln()
+}
+
+#[inline]
+fn log_p_inverse(bounds: &Bounds, y: f64) -> f64 {
+    bounds.p_bottom * (bounds.p_top / bounds.p_bottom).powf(y)
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                             Stüve
+--------------------------------------------------------------------------------------------------*/
+/// The Stüve diagram. Isobars are horizontal and evenly spaced in p^(Rd/cp), isotherms are
+/// vertical, and dry adiabats are straight lines.
+#[derive(Clone, Copy, Debug)]
+pub struct StuveTransform {
+    bounds: Bounds,
+}
+
+impl_new_and_default!(StuveTransform, "Stüve diagram");
+
+impl StuveTransform {
+    fn from_bounds(bounds: Bounds) -> Self {
+        StuveTransform { bounds }
+    }
+}
+
+impl DiagramTransform for StuveTransform {
+    fn pt_to_xy(&self, p: HectoPascal, t: Celsius) -> (f64, f64) {
+        let k = kappa();
+        let bottom = self.bounds.p_bottom.powf(k);
+        let top = self.bounds.p_top.powf(k);
+
+        (
+            self.bounds.x(t.unpack()),
+            (bottom - p.unpack().powf(k)) / (bottom - top),
+        )
+    }
+
+    fn xy_to_pt(&self, x: f64, y: f64) -> (HectoPascal, Celsius) {
+        let k = kappa();
+        let bottom = self.bounds.p_bottom.powf(k);
+        let top = self.bounds.p_top.powf(k);
+
+        (
+            HectoPascal((bottom - y * (bottom - top)).powf(1.0 / k)),
+            Celsius(self.bounds.t(x)),
+        )
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                            Tephigram
+--------------------------------------------------------------------------------------------------*/
+/// The tephigram. The temperature and log(theta) axes are rotated 45 degrees so isotherms slant up
+/// to the right, dry adiabats slant up to the left, and isobars are nearly horizontal curves.
+///
+/// Since the isobars are not exactly horizontal, the top pressure is at `y = 1` only in the middle
+/// of the temperature range.
+#[derive(Clone, Copy, Debug)]
+pub struct TephigramTransform {
+    // Rotated coordinates of the corners used to normalize the output.
+    x_left: f64,
+    x_right: f64,
+    y_bottom: f64,
+    y_top: f64,
+}
+
+impl_new_and_default!(TephigramTransform, "tephigram");
+
+/// Reference temperature for the entropy axis, this scaling makes the isobars close to horizontal.
+const TEPHIGRAM_T_REF: f64 = 273.15;
+
+impl TephigramTransform {
+    fn from_bounds(bounds: Bounds) -> Self {
+        let t_mid = (bounds.t_left + bounds.t_right) / 2.0;
+        let (x_left, _) = Self::rotated(bounds.p_bottom, bounds.t_left);
+        let (x_right, _) = Self::rotated(bounds.p_bottom, bounds.t_right);
+        let (_, y_bottom) = Self::rotated(bounds.p_bottom, t_mid);
+        let (_, y_top) = Self::rotated(bounds.p_top, t_mid);
+
+        TephigramTransform {
+            x_left,
+            x_right,
+            y_bottom,
+            y_top,
+        }
+    }
+
+    /// Coordinates before normalizing, pressure in hPa and temperature in Celsius.
+    fn rotated(p: f64, t: f64) -> (f64, f64) {
+        let t_k = Kelvin::from(Celsius(t)).unpack();
+        let theta = t_k * (1000.0 / p).powf(kappa());
+        let entropy = TEPHIGRAM_T_REF * (theta / TEPHIGRAM_T_REF).ln();
+
+        (t + entropy, entropy - t)
+    }
+}
+
+impl DiagramTransform for TephigramTransform {
+    fn pt_to_xy(&self, p: HectoPascal, t: Celsius) -> (f64, f64) {
+        let (x, y) = Self::rotated(p.unpack(), t.unpack());
+
+        (
+            (x - self.x_left) / (self.x_right - self.x_left),
+            (y - self.y_bottom) / (self.y_top - self.y_bottom),
+        )
+    }
+
+    fn xy_to_pt(&self, x: f64, y: f64) -> (HectoPascal, Celsius) {
+        let x = self.x_left + x * (self.x_right - self.x_left);
+        let y = self.y_bottom + y * (self.y_top - self.y_bottom);
+
+        let t = (x - y) / 2.0;
+        let entropy = (x + y) / 2.0;
+        let theta = TEPHIGRAM_T_REF * (entropy / TEPHIGRAM_T_REF).exp();
+        let t_k = Kelvin::from(Celsius(t)).unpack();
+
+        (
+            HectoPascal(1000.0 * (t_k / theta).powf(1.0 / kappa())),
+            Celsius(t),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check_round_trip<D: DiagramTransform>(diagram: D) {
+        for &p in &[1050.0, 1000.0, 850.0, 500.0, 250.0, 100.0] {
+            for &t in &[-80.0, -40.0, -10.0, 0.0, 25.0, 50.0] {
+                let (x, y) = diagram.pt_to_xy(HectoPascal(p), Celsius(t));
+                let (p2, t2) = diagram.xy_to_pt(x, y);
+                assert!((p2.unpack() - p).abs() < 1.0e-6, "{} != {}", p2.unpack(), p);
+                assert!((t2.unpack() - t).abs() < 1.0e-6, "{} != {}", t2.unpack(), t);
+            }
+        }
+    }
+
+    fn check_corners<D: DiagramTransform>(diagram: D) {
+        let (x, y) = diagram.pt_to_xy(HectoPascal(1050.0), Celsius(-40.0));
+        assert!(x.abs() < 1.0e-6 && y.abs() < 0.05, "({}, {})", x, y);
+        let (x, y) = diagram.pt_to_xy(HectoPascal(1050.0), Celsius(50.0));
+        assert!((x - 1.0).abs() < 1.0e-6 && y.abs() < 0.05, "({}, {})", x, y);
+        let (_, y) = diagram.pt_to_xy(HectoPascal(100.0), Celsius(5.0));
+        assert!((y - 1.0).abs() < 1.0e-6, "{}", y);
+    }
+
+    #[test]
+    fn test_transforms() {
+        check_round_trip(SkewTTransform::default());
+        check_round_trip(SkewTTransform::default().with_skew(0.5));
+        check_round_trip(EmagramTransform::default());
+        check_round_trip(StuveTransform::default());
+        check_round_trip(TephigramTransform::default());
+
+        check_corners(SkewTTransform::default().with_skew(0.0));
+        check_corners(EmagramTransform::default());
+        check_corners(StuveTransform::default());
+        check_corners(TephigramTransform::default());
+    }
+}
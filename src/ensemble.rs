@@ -0,0 +1,341 @@
+//! Ensembles of soundings for the same station and valid time.
+
+use metfor::{Celsius, HectoPascal, Kelvin, Knots, Meters, Mm, PaPS, Quantity, WindSpdDir, WindUV};
+use optional::{none, some, Optioned};
+
+use crate::data_row::{DataRow, ProfileVariable};
+use crate::error::{Result, SoundingError};
+use crate::interpolation::{common_pressure_levels, on_pressure_levels};
+use crate::sounding::Sounding;
+
+/// A collection of ensemble members for the same station and valid time.
+///
+/// The members are interpolated onto common pressure levels when the ensemble is created, so the
+/// profiles of all the members, and of the statistics calculated from them, line up level by
+/// level. Statistics are calculated separately for each level and variable using only the members
+/// that have a value there, and winds are treated as u and v components.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal};
+/// use sounding_base::{Ensemble, ProfileVariable};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let members = (0..5).map(|i| make_test_sounding().with_sfc_temperature(Celsius(19.0 + i as f64)));
+/// let ens = Ensemble::new(members).unwrap();
+///
+/// assert_eq!(ens.len(), 5);
+/// assert_eq!(ens.levels()[0], HectoPascal(1000.0));
+///
+/// assert_eq!(ens.mean().sfc_temperature().unwrap(), Celsius(21.0));
+/// assert_eq!(ens.median().sfc_temperature().unwrap(), Celsius(21.0));
+/// assert_eq!(ens.percentile(100.0).sfc_temperature().unwrap(), Celsius(23.0));
+///
+/// let spread = ens.std_dev(ProfileVariable::Temperature);
+/// assert!((spread[0].unwrap() - 2.0f64.sqrt()).abs() < 1.0e-9); // The surface.
+/// assert_eq!(spread[1].unwrap(), 0.0); // 1000 hPa
+///
+/// // Probability the surface temperature is over 21.5 C
+/// let prob = ens.probability_of_exceedance(|snd| snd.sfc_temperature().map(|t| t.0), 21.5);
+/// assert_eq!(prob.unwrap(), 0.4);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Ensemble {
+    levels: Vec<HectoPascal>,
+    members: Vec<Sounding>,
+}
+
+impl Ensemble {
+    /// Create an ensemble on the union of the pressure levels of all the members.
+    ///
+    /// This fails if there are no members, or if the members are not all for the same station and
+    /// valid time.
+    pub fn new<I>(members: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Sounding>,
+    {
+        let members: Vec<Sounding> = members.into_iter().collect();
+        let refs: Vec<&Sounding> = members.iter().collect();
+        let levels = common_pressure_levels(&refs);
+
+        Self::on_levels(members, &levels)
+    }
+
+    /// Create an ensemble on the given pressure levels, which should be sorted from the bottom up.
+    ///
+    /// This fails if there are no members, or if the members are not all for the same station and
+    /// valid time.
+    pub fn on_levels<I>(members: I, levels: &[HectoPascal]) -> Result<Self>
+    where
+        I: IntoIterator<Item = Sounding>,
+    {
+        let members: Vec<Sounding> = members.into_iter().collect();
+
+        let first = members
+            .first()
+            .ok_or_else(|| SoundingError::Incompatible("ensemble has no members".to_owned()))?;
+        for snd in &members[1..] {
+            if !first.station_info().is_same_station(&snd.station_info()) {
+                return Err(SoundingError::Incompatible(
+                    "ensemble members are from different stations".to_owned(),
+                ));
+            }
+            if first.valid_time() != snd.valid_time() {
+                return Err(SoundingError::Incompatible(
+                    "ensemble members have different valid times".to_owned(),
+                ));
+            }
+        }
+
+        let members = members
+            .iter()
+            .map(|snd| on_pressure_levels(snd, levels))
+            .collect();
+
+        Ok(Ensemble {
+            levels: levels.to_vec(),
+            members,
+        })
+    }
+
+    /// The common pressure levels, not including the surface.
+    #[inline]
+    pub fn levels(&self) -> &[HectoPascal] {
+        &self.levels
+    }
+
+    /// The members, interpolated to the common pressure levels.
+    #[inline]
+    pub fn members(&self) -> &[Sounding] {
+        &self.members
+    }
+
+    /// The number of members.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Check if the ensemble is empty. An ensemble always has at least one member.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// The mean of the members, level by level.
+    pub fn mean(&self) -> Sounding {
+        self.combine(|vals| mean(vals))
+    }
+
+    /// The median of the members, level by level.
+    pub fn median(&self) -> Sounding {
+        self.percentile(50.0)
+    }
+
+    /// A percentile, from 0 to 100, of the members level by level. Values between members are
+    /// linearly interpolated.
+    ///
+    /// The wind is made from the percentiles of the u and v components taken separately, so away
+    /// from the median it may be a wind that none of the members had.
+    pub fn percentile(&self, pct: f64) -> Sounding {
+        self.combine(|vals| percentile(vals, pct))
+    }
+
+    /// The standard deviation of a variable across the members at each level, with the surface
+    /// first, so it lines up with the profiles of `mean`. The wind direction spread is the
+    /// circular standard deviation in degrees, capped at 180 degrees when the directions cancel
+    /// out.
+    pub fn std_dev(&self, var: ProfileVariable) -> Vec<Optioned<f64>> {
+        (0..=self.levels.len())
+            .map(|i| {
+                let vals: Vec<f64> = self
+                    .members
+                    .iter()
+                    .filter_map(|snd| snd.data_row(i))
+                    .filter_map(|row| var.value(&row).into_option())
+                    .collect();
+
+                if var == ProfileVariable::WindDirection {
+                    circular_std_dev(&vals)
+                } else {
+                    std_dev(&vals)
+                }
+                .into()
+            })
+            .collect()
+    }
+
+    /// The fraction of members for which some value, e.g. CAPE, is greater than `threshold`.
+    ///
+    /// Members where the value is `None` are left out. Returns `None` if no members have a value.
+    pub fn probability_of_exceedance<F>(&self, value: F, threshold: f64) -> Option<f64>
+    where
+        F: Fn(&Sounding) -> Option<f64>,
+    {
+        let vals: Vec<f64> = self.members.iter().filter_map(value).collect();
+        if vals.is_empty() {
+            return None;
+        }
+
+        let count = vals.iter().filter(|&&val| val > threshold).count();
+        Some(count as f64 / vals.len() as f64)
+    }
+
+    /// Combine the members into a single sounding by applying a statistic to each variable at each
+    /// level. The statistic may reorder the values.
+    fn combine<F>(&self, stat: F) -> Sounding
+    where
+        F: Fn(&mut [f64]) -> Option<f64>,
+    {
+        macro_rules! combine_field {
+            ($rows:ident, $field:ident, $unit:ident) => {{
+                let mut vals: Vec<f64> = $rows
+                    .iter()
+                    .filter_map(|row| row.$field.into_option())
+                    .map(Quantity::unpack)
+                    .collect();
+                stat(&mut vals).map($unit).into()
+            }};
+        }
+
+        let rows: Vec<DataRow> = (0..=self.levels.len())
+            .map(|i| {
+                let rows: Vec<DataRow> = self
+                    .members
+                    .iter()
+                    .filter_map(|snd| snd.data_row(i))
+                    .collect();
+
+                let winds: Vec<WindUV<Knots>> = rows
+                    .iter()
+                    .filter_map(|row| row.wind.into_option())
+                    .map(WindUV::from)
+                    .collect();
+                let mut u: Vec<f64> = winds.iter().map(|w| w.u.unpack()).collect();
+                let mut v: Vec<f64> = winds.iter().map(|w| w.v.unpack()).collect();
+                let wind = match (stat(&mut u), stat(&mut v)) {
+                    (Some(u), Some(v)) => some(WindSpdDir::from(WindUV {
+                        u: Knots(u),
+                        v: Knots(v),
+                    })),
+                    _ => none(),
+                };
+
+                let mut row = DataRow {
+                    pressure: combine_field!(rows, pressure, HectoPascal),
+                    temperature: combine_field!(rows, temperature, Celsius),
+                    wet_bulb: combine_field!(rows, wet_bulb, Celsius),
+                    dew_point: combine_field!(rows, dew_point, Celsius),
+                    theta_e: combine_field!(rows, theta_e, Kelvin),
+                    wind,
+                    pvv: combine_field!(rows, pvv, PaPS),
+                    height: combine_field!(rows, height, Meters),
+                    cloud_fraction: {
+                        let mut vals: Vec<f64> = rows
+                            .iter()
+                            .filter_map(|row| row.cloud_fraction.into_option())
+                            .collect();
+                        stat(&mut vals).into()
+                    },
+                };
+
+                // Keep the levels exact.
+                if i > 0 {
+                    row.pressure = some(self.levels[i - 1]);
+                }
+
+                row
+            })
+            .collect();
+
+        let members = &self.members;
+        let combine_surface = |f: &dyn Fn(&Sounding) -> Optioned<f64>| -> Optioned<f64> {
+            let mut vals: Vec<f64> = members
+                .iter()
+                .filter_map(|snd| f(snd).into_option())
+                .collect();
+            stat(&mut vals).into()
+        };
+
+        let mslp = combine_surface(&|snd| snd.mslp().map_t(Quantity::unpack));
+        let precipitation = combine_surface(&|snd| snd.precipitation().map_t(Quantity::unpack));
+        let low_cloud = combine_surface(&Sounding::low_cloud);
+        let mid_cloud = combine_surface(&Sounding::mid_cloud);
+        let high_cloud = combine_surface(&Sounding::high_cloud);
+
+        members[0]
+            .clone()
+            .with_mslp(mslp.map_t(HectoPascal))
+            .with_precipitation(precipitation.map_t(Mm))
+            .with_low_cloud(low_cloud)
+            .with_mid_cloud(mid_cloud)
+            .with_high_cloud(high_cloud)
+            .with_data_rows(&rows)
+    }
+}
+
+fn mean(vals: &[f64]) -> Option<f64> {
+    if vals.is_empty() {
+        None
+    } else {
+        Some(vals.iter().sum::<f64>() / vals.len() as f64)
+    }
+}
+
+fn percentile(vals: &mut [f64], pct: f64) -> Option<f64> {
+    if vals.is_empty() {
+        return None;
+    }
+    vals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let rank = (pct.clamp(0.0, 100.0) / 100.0) * (vals.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    Some(vals[lower] + (rank - lower as f64) * (vals[upper] - vals[lower]))
+}
+
+fn std_dev(vals: &[f64]) -> Option<f64> {
+    let mean = mean(vals)?;
+    let var = vals.iter().map(|val| (val - mean).powi(2)).sum::<f64>() / vals.len() as f64;
+
+    Some(var.sqrt())
+}
+
+fn circular_std_dev(degrees: &[f64]) -> Option<f64> {
+    if degrees.is_empty() {
+        return None;
+    }
+
+    let n = degrees.len() as f64;
+    let sin = degrees.iter().map(|d| d.to_radians().sin()).sum::<f64>() / n;
+    let cos = degrees.iter().map(|d| d.to_radians().cos()).sum::<f64>() / n;
+    let r = sin.hypot(cos).min(1.0);
+
+    // Directions spread evenly around the circle have no mean, and r goes to 0.
+    Some((-2.0 * r.ln()).sqrt().to_degrees().min(180.0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_statistics() {
+        let mut vals = vec![4.0, 1.0, 3.0, 2.0];
+        assert_eq!(percentile(&mut vals, 50.0).unwrap(), 2.5);
+        assert_eq!(percentile(&mut vals, 0.0).unwrap(), 1.0);
+        assert_eq!(percentile(&mut vals, 100.0).unwrap(), 4.0);
+        assert!(percentile(&mut [], 50.0).is_none());
+
+        assert_eq!(std_dev(&[2.0, 4.0]).unwrap(), 1.0);
+
+        // Directions on either side of north are close together.
+        assert!(circular_std_dev(&[350.0, 10.0]).unwrap() < 11.0);
+        assert!(circular_std_dev(&[90.0, 90.0]).unwrap() < 1.0e-6);
+        assert_eq!(circular_std_dev(&[0.0, 180.0]).unwrap(), 180.0);
+        assert_eq!(circular_std_dev(&[0.0, 120.0, 240.0]).unwrap(), 180.0);
+        assert!(circular_std_dev(&[]).is_none());
+    }
+}
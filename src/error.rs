@@ -0,0 +1,70 @@
+//! Error types for the sounding-base crate.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Errors that can occur while reading, writing, or manipulating soundings.
+#[derive(Debug)]
+pub enum SoundingError {
+    /// An underlying I/O error, e.g. while reading a file.
+    Io(std::io::Error),
+    /// A line or field of a text format could not be decoded.
+    Parse {
+        /// The 1-based line number in the input where the problem was found.
+        line: usize,
+        /// Description of what went wrong.
+        msg: String,
+    },
+    /// The input ended before a complete record was read.
+    UnexpectedEof,
+    /// A sounding without a valid time was used where one is required.
+    MissingValidTime,
+    /// Soundings that can't be combined, e.g. they are from different stations.
+    Incompatible(String),
+    /// A sounding without any levels that have a pressure was written to a format that needs them.
+    NoPressureLevels,
+}
+
+impl SoundingError {
+    #[inline]
+    pub(crate) fn parse<S: Into<String>>(line: usize, msg: S) -> Self {
+        SoundingError::Parse {
+            line,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl Display for SoundingError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        use crate::error::SoundingError::*;
+
+        match self {
+            Io(err) => write!(f, "i/o error: {}", err),
+            Parse { line, msg } => write!(f, "parse error on line {}: {}", line, msg),
+            UnexpectedEof => write!(f, "unexpected end of input"),
+            MissingValidTime => write!(f, "sounding has no valid time"),
+            Incompatible(msg) => write!(f, "incompatible soundings: {}", msg),
+            NoPressureLevels => write!(f, "sounding has no levels with a pressure"),
+        }
+    }
+}
+
+impl Error for SoundingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SoundingError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SoundingError {
+    #[inline]
+    fn from(err: std::io::Error) -> Self {
+        SoundingError::Io(err)
+    }
+}
+
+/// Shorthand for results with a `SoundingError`.
+pub type Result<T> = std::result::Result<T, SoundingError>;
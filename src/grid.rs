@@ -0,0 +1,309 @@
+//! Time-pressure and time-height grids of a profile variable, e.g. for meteograms.
+
+use chrono::{Duration, NaiveDateTime};
+use metfor::{HectoPascal, Meters, Quantity};
+use optional::{none, Optioned};
+
+use crate::data_row::{DataRow, ProfileVariable};
+use crate::error::{Result, SoundingError};
+use crate::interpolation::{interpolate_at_height, interpolate_at_pressure, interpolate_in_time};
+use crate::sounding::Sounding;
+
+/// The vertical coordinate of a `ProfileGrid`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerticalAxis {
+    /// Pressure levels in hPa.
+    Pressure,
+    /// Geopotential heights in meters, above mean sea level.
+    Height,
+}
+
+/// What to put in each cell of the grid.
+#[derive(Clone, Copy, Debug)]
+enum GridValue {
+    Variable(ProfileVariable),
+    Function(fn(&DataRow) -> Optioned<f64>),
+}
+
+impl GridValue {
+    #[inline]
+    fn get(self, row: &DataRow) -> Optioned<f64> {
+        match self {
+            GridValue::Variable(var) => var.value(row),
+            GridValue::Function(f) => f(row),
+        }
+    }
+}
+
+/// Build a `ProfileGrid` from a collection of soundings.
+///
+/// Each sounding is interpolated onto the requested levels, linearly in log(p) for pressure levels
+/// or linearly in height for height levels. The time axis is the valid times of the soundings, or
+/// a regular series of times from the first to the last valid time if a time step is set, with the
+/// soundings interpolated in time to any times between their valid times.
+///
+/// # Examples
+///
+/// ```rust
+/// use chrono::{Duration, NaiveDate};
+/// use metfor::HectoPascal;
+/// use sounding_base::{ProfileGridBuilder, ProfileVariable};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let vt = NaiveDate::from_ymd_opt(2019, 6, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+/// let soundings = vec![
+///     make_test_sounding().with_valid_time(vt),
+///     make_test_sounding().with_valid_time(vt + Duration::hours(6)),
+/// ];
+///
+/// let levels: Vec<_> = vec![1000.0, 850.0, 700.0, 500.0].into_iter().map(HectoPascal).collect();
+///
+/// let grid = ProfileGridBuilder::new(ProfileVariable::Temperature)
+///     .with_pressure_levels(&levels)
+///     .with_time_step(Duration::hours(3))
+///     .build(&soundings)
+///     .unwrap();
+///
+/// assert_eq!(grid.times().len(), 3);
+/// assert_eq!(grid.levels(), &[1000.0, 850.0, 700.0, 500.0]);
+///
+/// assert_eq!(grid.get(0, 1).unwrap(), 10.0); // 850 hPa at 12Z
+/// assert_eq!(grid.get(1, 1).unwrap(), 10.0); // 15Z is interpolated between 12Z and 18Z.
+/// assert!(grid.get(2, 3).is_none()); // The soundings don't reach 500 hPa.
+/// ```
+#[derive(Clone, Debug)]
+pub struct ProfileGridBuilder {
+    value: GridValue,
+    axis: VerticalAxis,
+    levels: Vec<f64>,
+    time_step: Option<Duration>,
+}
+
+impl ProfileGridBuilder {
+    /// Create a builder for a grid of `var`.
+    pub fn new(var: ProfileVariable) -> Self {
+        Self::from_value(GridValue::Variable(var))
+    }
+
+    /// Create a builder for a grid of a value calculated from each row, e.g. relative humidity.
+    ///
+    /// The rows are interpolated to the grid levels before `value` is calculated.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use optional::Optioned;
+    /// use sounding_base::{DataRow, ProfileGridBuilder};
+    ///
+    /// fn rh(row: &DataRow) -> Optioned<f64> {
+    ///     match (row.temperature.into_option(), row.dew_point.into_option()) {
+    ///         (Some(t), Some(dp)) => metfor::rh(t, dp).map(|rh| rh * 100.0).into(),
+    ///         _ => optional::none(),
+    ///     }
+    /// }
+    ///
+    /// let _builder = ProfileGridBuilder::from_fn(rh);
+    /// ```
+    pub fn from_fn(value: fn(&DataRow) -> Optioned<f64>) -> Self {
+        Self::from_value(GridValue::Function(value))
+    }
+
+    fn from_value(value: GridValue) -> Self {
+        ProfileGridBuilder {
+            value,
+            axis: VerticalAxis::Pressure,
+            levels: vec![],
+            time_step: None,
+        }
+    }
+
+    /// Builder method to use pressure levels for the vertical axis.
+    pub fn with_pressure_levels(mut self, levels: &[HectoPascal]) -> Self {
+        self.axis = VerticalAxis::Pressure;
+        self.levels = levels.iter().map(|p| p.unpack()).collect();
+        self
+    }
+
+    /// Builder method to use heights above mean sea level for the vertical axis.
+    pub fn with_height_levels(mut self, levels: &[Meters]) -> Self {
+        self.axis = VerticalAxis::Height;
+        self.levels = levels.iter().map(|z| z.unpack()).collect();
+        self
+    }
+
+    /// Builder method to make the time axis regular. At times between the valid times of the
+    /// soundings, the two soundings on either side are interpolated in time with
+    /// `interpolate_in_time`.
+    pub fn with_time_step(mut self, step: Duration) -> Self {
+        self.time_step = Some(step);
+        self
+    }
+
+    /// Build the grid.
+    ///
+    /// This fails if any of the soundings has no valid time, or if two have the same valid time.
+    /// With a time step, it also fails if soundings that need to be interpolated in time are from
+    /// different stations.
+    pub fn build<'a, I>(&self, soundings: I) -> Result<ProfileGrid>
+    where
+        I: IntoIterator<Item = &'a Sounding>,
+    {
+        let mut soundings: Vec<(NaiveDateTime, &Sounding)> = soundings
+            .into_iter()
+            .map(|snd| {
+                snd.valid_time()
+                    .map(|vt| (vt, snd))
+                    .ok_or(SoundingError::MissingValidTime)
+            })
+            .collect::<Result<_>>()?;
+        soundings.sort_by_key(|&(vt, _)| vt);
+
+        if let Some(pair) = soundings.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(SoundingError::Incompatible(format!(
+                "duplicate valid time {}",
+                pair[0].0
+            )));
+        }
+
+        let times: Vec<NaiveDateTime> = match (self.time_step, soundings.first(), soundings.last())
+        {
+            (Some(step), Some(&(first, _)), Some(&(last, _))) if step > Duration::zero() => {
+                let mut times = vec![];
+                let mut t = first;
+                while t <= last {
+                    times.push(t);
+                    t += step;
+                }
+                times
+            }
+            _ => soundings.iter().map(|&(vt, _)| vt).collect(),
+        };
+
+        let mut values = Vec::with_capacity(times.len() * self.levels.len());
+        for &t in &times {
+            // The times are never outside the valid times, so there is a sounding on either side
+            // of any time without one.
+            let interpolated;
+            let snd = match soundings.binary_search_by_key(&t, |&(vt, _)| vt) {
+                Ok(idx) => soundings[idx].1,
+                Err(idx) => {
+                    interpolated = interpolate_in_time(soundings[idx - 1].1, soundings[idx].1, t)?;
+                    &interpolated
+                }
+            };
+
+            for &level in &self.levels {
+                let row = match self.axis {
+                    VerticalAxis::Pressure => interpolate_at_pressure(snd, HectoPascal(level)),
+                    VerticalAxis::Height => interpolate_at_height(snd, Meters(level)),
+                };
+                values.push(row.map(|row| self.value.get(&row)).unwrap_or_else(none));
+            }
+        }
+
+        Ok(ProfileGrid {
+            axis: self.axis,
+            times,
+            levels: self.levels.clone(),
+            values,
+        })
+    }
+}
+
+/// A regular grid of a profile variable with time on one axis and pressure or height on the other.
+///
+/// Create one with a `ProfileGridBuilder`.
+#[derive(Clone, Debug)]
+pub struct ProfileGrid {
+    axis: VerticalAxis,
+    times: Vec<NaiveDateTime>,
+    levels: Vec<f64>,
+    // Indexed by time, then level.
+    values: Vec<Optioned<f64>>,
+}
+
+impl ProfileGrid {
+    /// The kind of vertical levels.
+    #[inline]
+    pub fn vertical_axis(&self) -> VerticalAxis {
+        self.axis
+    }
+
+    /// The times, in increasing order.
+    #[inline]
+    pub fn times(&self) -> &[NaiveDateTime] {
+        &self.times
+    }
+
+    /// The vertical levels in hPa or meters, see `vertical_axis`.
+    #[inline]
+    pub fn levels(&self) -> &[f64] {
+        &self.levels
+    }
+
+    /// The value at a time and level index, missing if either index is out of range.
+    pub fn get(&self, time_idx: usize, level_idx: usize) -> Optioned<f64> {
+        if time_idx >= self.times.len() || level_idx >= self.levels.len() {
+            return none();
+        }
+
+        self.values[time_idx * self.levels.len() + level_idx]
+    }
+
+    /// All the values at one time, in the same order as `levels`.
+    pub fn profile(&self, time_idx: usize) -> &[Optioned<f64>] {
+        let n = self.levels.len();
+        if time_idx >= self.times.len() {
+            return &[];
+        }
+
+        &self.values[time_idx * n..(time_idx + 1) * n]
+    }
+
+    /// All the values, one profile after another for each time.
+    #[inline]
+    pub fn values(&self) -> &[Optioned<f64>] {
+        &self.values
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+    use chrono::NaiveDate;
+    use metfor::Celsius;
+
+    #[test]
+    fn test_time_step_not_aligned_with_soundings() {
+        let vt = NaiveDate::from_ymd_opt(2019, 6, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let soundings = vec![
+            make_test_sounding().with_valid_time(vt),
+            make_test_sounding()
+                .with_valid_time(vt + Duration::hours(1))
+                .with_sfc_temperature(Celsius(25.0)),
+            make_test_sounding()
+                .with_valid_time(vt + Duration::hours(6))
+                .with_sfc_temperature(Celsius(30.0)),
+        ];
+
+        let grid = ProfileGridBuilder::new(ProfileVariable::Temperature)
+            .with_pressure_levels(&[HectoPascal(1005.0), HectoPascal(850.0)])
+            .with_time_step(Duration::hours(3))
+            .build(&soundings)
+            .unwrap();
+
+        assert_eq!(
+            grid.times(),
+            &[vt, vt + Duration::hours(3), vt + Duration::hours(6)]
+        );
+        assert_eq!(grid.get(0, 0).unwrap(), 21.0);
+        // 03Z is between the 01Z and 06Z soundings.
+        assert!((grid.get(1, 0).unwrap() - 27.0).abs() < 1.0e-9);
+        assert!((grid.get(1, 1).unwrap() - 10.0).abs() < 1.0e-9);
+        assert_eq!(grid.get(2, 0).unwrap(), 30.0);
+    }
+}
@@ -0,0 +1,264 @@
+//! Reconstruct and check geopotential heights with the hypsometric equation.
+
+use metfor::{Meters, Quantity};
+use optional::{none, Optioned};
+
+use crate::data_row::DataRow;
+use crate::derived::virtual_temperature;
+use crate::sounding::Sounding;
+
+/// Calculate the height of every level by integrating the hypsometric equation up from the
+/// surface, ignoring any reported heights above it.
+///
+/// The integration starts at the station elevation, or if that is missing, at the lowest level
+/// with a reported height, and uses the virtual temperature from the temperature and dew point.
+/// Levels without a pressure or temperature, and levels below the starting point, are missing. The
+/// result lines up with the profiles of `snd`, with the surface first.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::Meters;
+/// use sounding_base::{hydrostatic_heights, StationInfo};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding().with_station_info(StationInfo::new().with_elevation(Meters(100.0)));
+///
+/// let heights = hydrostatic_heights(&snd);
+/// assert_eq!(heights[0].unwrap(), Meters(100.0));
+///
+/// // 1000 hPa is about 40 meters above the surface at 1005 hPa.
+/// let z = heights[1].unwrap().0;
+/// assert!(z > 140.0 && z < 150.0);
+/// ```
+pub fn hydrostatic_heights(snd: &Sounding) -> Vec<Optioned<Meters>> {
+    integrate(snd, false)
+}
+
+/// Fill the missing values of the height profile with hydrostatic estimates.
+///
+/// Each missing height is integrated up from the level below it, starting again from every
+/// reported height, so the filled values agree with the reported values around them. If there is
+/// no height profile, one is created.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::Meters;
+/// use sounding_base::{fill_missing_heights, StationInfo};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding().with_station_info(StationInfo::new().with_elevation(Meters(100.0)));
+/// assert!(snd.height_profile().is_empty());
+///
+/// let snd = fill_missing_heights(&snd);
+/// assert!(snd.height_profile().iter().all(|z| z.is_some()));
+/// ```
+pub fn fill_missing_heights(snd: &Sounding) -> Sounding {
+    let estimates = integrate(snd, true);
+
+    let heights: Vec<Optioned<Meters>> = estimates
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &est)| match snd.height_profile().get(i) {
+            Some(z) if z.is_some() => *z,
+            _ => est,
+        })
+        .collect();
+
+    snd.clone().with_height_profile(heights)
+}
+
+/// Find the levels whose reported height departs from the hydrostatic estimate by more than
+/// `tolerance`.
+///
+/// Each level is checked against the thickness of the layer below it, so an error at one level is
+/// not carried up into the levels above, although the level above a bad height is usually flagged
+/// too. Returns the indices of the levels, with 0 for the surface.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::Meters;
+/// use optional::some;
+/// use sounding_base::{check_hydrostatic_heights, fill_missing_heights, StationInfo};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding().with_station_info(StationInfo::new().with_elevation(Meters(100.0)));
+/// let snd = fill_missing_heights(&snd);
+/// assert!(check_hydrostatic_heights(&snd, Meters(10.0)).is_empty());
+///
+/// let mut heights: Vec<_> = snd.height_profile().iter().skip(1).cloned().collect();
+/// heights[3] = some(Meters(heights[3].unwrap().0 + 100.0)); // 700 hPa
+/// let snd = snd.with_height_profile(heights);
+/// assert_eq!(check_hydrostatic_heights(&snd, Meters(10.0)), vec![4]);
+/// ```
+pub fn check_hydrostatic_heights<L>(snd: &Sounding, tolerance: L) -> Vec<usize>
+where
+    L: metfor::Length,
+    Meters: From<L>,
+{
+    let tolerance = Meters::from(tolerance).unpack();
+    let estimates = integrate(snd, true);
+
+    snd.height_profile()
+        .iter()
+        .zip(estimates)
+        .enumerate()
+        .filter_map(|(i, (z, est))| {
+            let diff = z.into_option()?.unpack() - est.into_option()?.unpack();
+            if diff.abs() > tolerance {
+                Some(i)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Integrate the hypsometric equation up through the sounding. If `restart` is true, each layer
+/// starts from the reported height at its bottom if there is one.
+fn integrate(snd: &Sounding, restart: bool) -> Vec<Optioned<Meters>> {
+    let rows: Vec<DataRow> = snd.bottom_up().collect();
+    let mut heights = vec![none(); rows.len()];
+
+    let sfc_elevation = snd.station_info().elevation();
+
+    // The pressure, virtual temperature and height of the last level with a height.
+    let mut below: Option<(f64, f64, f64)> = None;
+    for (i, row) in rows.iter().enumerate() {
+        let (p, tv) = match (row.pressure.into_option(), virtual_temperature(row)) {
+            (Some(p), Some(tv)) => (p.unpack(), tv.unpack()),
+            _ => continue,
+        };
+
+        let estimate = below.map(|(p0, tv0, z0)| {
+            let tv_mean = (tv0 + tv) / 2.0;
+            z0 + metfor::Rd.unpack() * tv_mean / -metfor::g * (p0 / p).ln()
+        });
+
+        let reported = if i == 0 {
+            sfc_elevation
+                .into_option()
+                .or_else(|| row.height.into_option())
+        } else {
+            row.height.into_option()
+        };
+        let reported = reported.map(Quantity::unpack);
+
+        heights[i] = match below {
+            Some(_) => estimate,
+            None => reported,
+        }
+        .map(Meters)
+        .into();
+
+        let z = match (estimate, reported) {
+            (None, reported) => reported,
+            (Some(_), Some(reported)) if restart => Some(reported),
+            (estimate, _) => estimate,
+        };
+
+        if let Some(z) = z {
+            below = Some((p, tv, z));
+        }
+    }
+
+    heights
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+    use crate::station_info::StationInfo;
+    use metfor::Celsius;
+    use optional::some;
+
+    fn with_elevation() -> Sounding {
+        make_test_sounding().with_station_info(StationInfo::new().with_elevation(Meters(100.0)))
+    }
+
+    fn meters(z: Optioned<Meters>) -> f64 {
+        z.unwrap().unpack()
+    }
+
+    #[test]
+    fn test_start_at_lowest_reported_height() {
+        let snd = make_test_sounding().with_height_profile(vec![
+            none(),
+            some(Meters(800.0)),
+            none(),
+            some(Meters(3200.0)),
+        ]);
+        assert!(snd.station_info().elevation().is_none());
+
+        let heights = hydrostatic_heights(&snd);
+        assert_eq!(heights.len(), 5);
+        assert!(heights[0].is_none());
+        assert!(heights[1].is_none()); // Below the start.
+        assert_eq!(heights[2].unwrap(), Meters(800.0));
+
+        // The thickness matches the integration from the station elevation.
+        let full = hydrostatic_heights(&with_elevation());
+        let thickness = meters(full[3]) - meters(full[2]);
+        assert!((meters(heights[3]) - 800.0 - thickness).abs() < 1.0e-6);
+
+        // The reported height at 700 hPa is not used.
+        let thickness = meters(full[4]) - meters(full[2]);
+        assert!((meters(heights[4]) - 800.0 - thickness).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_levels_without_temperature() {
+        let full = hydrostatic_heights(&with_elevation());
+
+        let snd = with_elevation().with_temperature_profile(vec![
+            some(Celsius(20.0)),
+            none(),
+            some(Celsius(10.0)),
+            some(Celsius(2.0)),
+        ]);
+        let heights = hydrostatic_heights(&snd);
+
+        assert!(heights[2].is_none());
+        assert_eq!(heights[1], full[1]);
+
+        // 850 hPa is integrated straight from 1000 hPa, which only changes the mean temperature of
+        // the layer a little.
+        assert!((meters(heights[3]) - meters(full[3])).abs() < 10.0);
+        assert!((meters(heights[4]) - meters(full[4])).abs() < 10.0);
+
+        // Without a surface temperature nothing can be integrated from the station elevation.
+        let snd = with_elevation().with_sfc_temperature(none::<Celsius>());
+        let heights = hydrostatic_heights(&snd);
+        assert!(heights.iter().all(|z| z.is_none()));
+    }
+
+    #[test]
+    fn test_fill_restarts_at_reported_heights() {
+        let full = hydrostatic_heights(&with_elevation());
+        let offset = 50.0;
+        let z850 = meters(full[3]) + offset;
+
+        let snd =
+            with_elevation().with_height_profile(vec![none(), none(), some(Meters(z850)), none()]);
+        let filled = fill_missing_heights(&snd);
+        let heights = filled.height_profile();
+
+        assert_eq!(heights[1], full[1]);
+        assert_eq!(heights[2], full[2]);
+        assert_eq!(heights[3].unwrap(), Meters(z850));
+        assert!((meters(heights[4]) - meters(full[4]) - offset).abs() < 1.0e-6);
+
+        // Without restarting, the reported height is ignored.
+        let heights = hydrostatic_heights(&snd);
+        assert_eq!(heights[3], full[3]);
+
+        // The departure is flagged at 850 hPa and, because the next layer restarts from it, not
+        // above.
+        assert_eq!(check_hydrostatic_heights(&filled, Meters(10.0)), vec![3]);
+        assert!(check_hydrostatic_heights(&filled, Meters(60.0)).is_empty());
+    }
+}
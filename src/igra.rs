@@ -0,0 +1,559 @@
+//! Reader for the NOAA Integrated Global Radiosonde Archive version 2 (IGRA2).
+//!
+//! IGRA2 data files are plain text with a fixed width header line for each sounding followed by
+//! one fixed width line per level. Files for a single station can be several gigabytes, so the
+//! reader here works on any `BufRead` and decodes one sounding at a time.
+//!
+//! Station elevations are not included in the data files, they are only available in the
+//! separate IGRA2 station list, which can be loaded with `IgraStationList`.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use metfor::{Celsius, HectoPascal, Knots, Meters, MetersPSec, Pascal, Quantity, WindSpdDir};
+use optional::{none, some, Optioned};
+
+use crate::error::{Result, SoundingError};
+use crate::sounding::Sounding;
+use crate::station_info::StationInfo;
+
+/// Quality control flag IGRA2 attaches to the pressure, height, and temperature of a level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IgraQcFlag {
+    /// The value was not checked against climatological limits (blank in the file).
+    NotChecked,
+    /// The value passed the tier-1 climatological limits check, but was not checked against the
+    /// tier-2 limits ('A' in the file).
+    TierOne,
+    /// The value passed both the tier-1 and tier-2 climatological limits checks ('B' in the file).
+    TierTwo,
+}
+
+/// The major level type of an IGRA2 level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IgraLevelType {
+    /// A standard (mandatory) pressure level.
+    StandardPressure,
+    /// Some other pressure level, e.g. a significant level.
+    OtherPressure,
+    /// A level without a pressure, usually a wind observation at a height.
+    NonPressure,
+}
+
+/// A decoded level from an IGRA2 data file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IgraLevel {
+    /// The major level type.
+    pub level_type: IgraLevelType,
+    /// Whether this level is the surface.
+    pub surface: bool,
+    /// Whether this level is a tropopause.
+    pub tropopause: bool,
+    /// Time elapsed since launch in seconds.
+    pub elapsed_time: Optioned<i32>,
+    /// Pressure.
+    pub pressure: Optioned<HectoPascal>,
+    /// Quality control flag for the pressure.
+    pub pressure_flag: IgraQcFlag,
+    /// Geopotential height.
+    pub height: Optioned<Meters>,
+    /// Quality control flag for the height.
+    pub height_flag: IgraQcFlag,
+    /// Temperature.
+    pub temperature: Optioned<Celsius>,
+    /// Quality control flag for the temperature.
+    pub temperature_flag: IgraQcFlag,
+    /// Relative humidity in percent.
+    pub relative_humidity: Optioned<f64>,
+    /// Dew point, calculated from the temperature and dew point depression.
+    pub dew_point: Optioned<Celsius>,
+    /// Wind.
+    pub wind: Optioned<WindSpdDir<Knots>>,
+}
+
+/// The header line that precedes the levels of each sounding in an IGRA2 data file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IgraHeader {
+    /// The 11 character IGRA2 station identifier, e.g. "USM00072776".
+    pub id: String,
+    /// The nominal valid time of the sounding. If the nominal hour is missing the release time is
+    /// used instead.
+    pub valid_time: Option<NaiveDateTime>,
+    /// The number of levels that follow the header.
+    pub num_levels: usize,
+    /// Latitude and longitude of the station at the time of the sounding.
+    pub location: Option<(f64, f64)>,
+}
+
+/// One complete sounding record from an IGRA2 data file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IgraRecord {
+    /// The header.
+    pub header: IgraHeader,
+    /// The levels in the order they appear in the file.
+    pub levels: Vec<IgraLevel>,
+}
+
+impl IgraRecord {
+    /// Convert this record into a `Sounding`.
+    ///
+    /// Levels without a pressure are skipped since pressure is the vertical coordinate of a
+    /// `Sounding`. The level flagged as the surface, if any, supplies the surface values. If a
+    /// station list is supplied it provides the station elevation, which is otherwise taken from
+    /// the height of the surface level.
+    pub fn to_sounding(&self, stations: Option<&IgraStationList>) -> Sounding {
+        let listed = stations.and_then(|list| list.get(&self.header.id));
+
+        let mut stn = StationInfo::new()
+            .with_station(station_num_from_id(&self.header.id))
+            .with_lat_lon(
+                self.header
+                    .location
+                    .or_else(|| listed.and_then(|s| s.location())),
+            );
+        if let Some(listed) = listed {
+            stn = stn.with_elevation(listed.elevation());
+        }
+
+        let sfc = self
+            .levels
+            .iter()
+            .find(|lvl| lvl.surface && lvl.pressure.is_some());
+        if stn.elevation().is_none() {
+            stn = stn.with_elevation(sfc.map(|lvl| lvl.height).unwrap_or_else(none));
+        }
+
+        let upper_air = self
+            .levels
+            .iter()
+            .filter(|lvl| !lvl.surface && lvl.pressure.is_some());
+
+        let mut pressure = vec![];
+        let mut temperature = vec![];
+        let mut dew_point = vec![];
+        let mut wind = vec![];
+        let mut height = vec![];
+        for lvl in upper_air {
+            pressure.push(lvl.pressure);
+            temperature.push(lvl.temperature);
+            dew_point.push(lvl.dew_point);
+            wind.push(lvl.wind);
+            height.push(lvl.height);
+        }
+
+        let mut snd = Sounding::new()
+            .with_source_description(format!("IGRA2 {}", self.header.id))
+            .with_station_info(stn)
+            .with_valid_time(self.header.valid_time);
+
+        if let Some(sfc) = sfc {
+            snd = snd
+                .with_station_pressure(sfc.pressure)
+                .with_sfc_temperature(sfc.temperature)
+                .with_sfc_dew_point(sfc.dew_point)
+                .with_sfc_wind(sfc.wind);
+        }
+
+        snd.with_pressure_profile(pressure)
+            .with_temperature_profile(temperature)
+            .with_dew_point_profile(dew_point)
+            .with_wind_profile(wind)
+            .with_height_profile(height)
+            .fill_wet_bulb_and_theta_e()
+    }
+}
+
+/// The IGRA2 station list, used to look up station locations and elevations.
+#[derive(Clone, Debug, Default)]
+pub struct IgraStationList {
+    stations: HashMap<String, StationInfo>,
+}
+
+impl IgraStationList {
+    /// Load the station list from the contents of the IGRA2 `igra2-station-list.txt` file.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::Meters;
+    /// use sounding_base::IgraStationList;
+    ///
+    /// let text = "USM00072776  47.4950 -111.3833 1131.0 MT GREAT FALLS                    \
+    ///             1939 2024  51234\n";
+    ///
+    /// let list = IgraStationList::from_reader(text.as_bytes()).unwrap();
+    /// let stn = list.get("USM00072776").unwrap();
+    ///
+    /// assert_eq!(stn.station_num().unwrap(), 72776);
+    /// assert_eq!(stn.location().unwrap(), (47.4950, -111.3833));
+    /// assert_eq!(stn.elevation().unwrap(), Meters(1131.0));
+    /// ```
+    pub fn from_reader<R: BufRead>(src: R) -> Result<Self> {
+        let mut stations = HashMap::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let line = line?;
+            let line_num = i + 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let id = field(&line, 1, 11).to_owned();
+            let lat: f64 = parse_field(&line, 13, 20, line_num, "latitude")?;
+            let lon: f64 = parse_field(&line, 22, 30, line_num, "longitude")?;
+            let elev: f64 = parse_field(&line, 32, 37, line_num, "elevation")?;
+
+            // -998.8 marks mobile stations and -999.9 a missing elevation.
+            let elevation = if elev < -998.0 {
+                none()
+            } else {
+                some(Meters(elev))
+            };
+
+            let stn = StationInfo::new_with_values(station_num_from_id(&id), (lat, lon), elevation);
+            stations.insert(id, stn);
+        }
+
+        Ok(IgraStationList { stations })
+    }
+
+    /// Get the station info for an IGRA2 station identifier.
+    #[inline]
+    pub fn get(&self, id: &str) -> Option<StationInfo> {
+        self.stations.get(id).cloned()
+    }
+
+    /// The number of stations in the list.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.stations.len()
+    }
+
+    /// Whether the list is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty()
+    }
+}
+
+/// A streaming reader for IGRA2 data files.
+///
+/// As an iterator it yields one `Sounding` per header record. Use `next_record` to get the
+/// decoded levels, including the quality control flags, instead.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal, Meters};
+/// use sounding_base::{IgraReader, IgraStationList};
+///
+/// let stations = "USM00072776  47.4950 -111.3833 1131.0 MT GREAT FALLS";
+/// let stations = IgraStationList::from_reader(stations.as_bytes()).unwrap();
+///
+/// let data = "\
+/// ##USM00072776 2018 03 08 12 1105    4 ncdc-gts ncdc-gts  475000 -1113833
+/// 21     0  90200B 1131B  105B-9999    45   200    31
+/// 10 -9999  85000B 1581B   56B-9999    60   230    82
+/// 10 -9999  70000B 3050B  -42B-9999   120   250   154
+/// 10 -9999  50000A 5640A -206A-9999 -9999   260   257
+/// ";
+///
+/// let mut reader = IgraReader::new(data.as_bytes()).with_station_list(&stations);
+///
+/// let snd = reader.next().unwrap().unwrap();
+/// assert_eq!(snd.station_info().station_num().unwrap(), 72776);
+/// assert_eq!(snd.station_info().elevation().unwrap(), Meters(1131.0));
+/// assert_eq!(snd.station_pressure().unwrap(), HectoPascal(902.0));
+/// assert_eq!(snd.sfc_temperature().unwrap(), Celsius(10.5));
+/// assert_eq!(snd.pressure_profile().len(), 4); // Surface plus three levels aloft.
+/// assert!(snd.dew_point_profile()[3].is_none());
+///
+/// assert!(reader.next().is_none());
+/// ```
+pub struct IgraReader<'a, R> {
+    src: R,
+    stations: Option<&'a IgraStationList>,
+    line_num: usize,
+    buf: String,
+}
+
+impl<R: BufRead> IgraReader<'static, R> {
+    /// Create a new reader without a station list.
+    #[inline]
+    pub fn new(src: R) -> Self {
+        IgraReader {
+            src,
+            stations: None,
+            line_num: 0,
+            buf: String::new(),
+        }
+    }
+}
+
+impl<'a, R: BufRead> IgraReader<'a, R> {
+    /// Builder method to add a station list used to fill in the `StationInfo` of soundings.
+    #[inline]
+    pub fn with_station_list<'b>(self, stations: &'b IgraStationList) -> IgraReader<'b, R> {
+        IgraReader {
+            src: self.src,
+            stations: Some(stations),
+            line_num: self.line_num,
+            buf: self.buf,
+        }
+    }
+
+    /// Read the next complete record, or `None` at the end of the input.
+    pub fn next_record(&mut self) -> Option<Result<IgraRecord>> {
+        // Skip any blank lines between records.
+        loop {
+            match self.read_line() {
+                Ok(true) if self.buf.trim().is_empty() => continue,
+                Ok(true) => break,
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        Some(self.read_record())
+    }
+
+    fn read_record(&mut self) -> Result<IgraRecord> {
+        let header = parse_header(&self.buf, self.line_num)?;
+
+        let mut levels = Vec::with_capacity(header.num_levels);
+        for _ in 0..header.num_levels {
+            if !self.read_line()? {
+                return Err(SoundingError::UnexpectedEof);
+            }
+            levels.push(parse_level(&self.buf, self.line_num)?);
+        }
+
+        Ok(IgraRecord { header, levels })
+    }
+
+    /// Read a line into the buffer, returns `false` at the end of the input.
+    fn read_line(&mut self) -> Result<bool> {
+        self.buf.clear();
+        let bytes = self.src.read_line(&mut self.buf)?;
+        self.line_num += 1;
+
+        let trimmed_len = self.buf.trim_end_matches(&['\r', '\n'][..]).len();
+        self.buf.truncate(trimmed_len);
+
+        Ok(bytes > 0)
+    }
+}
+
+impl<'a, R: BufRead> Iterator for IgraReader<'a, R> {
+    type Item = Result<Sounding>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stations = self.stations;
+        self.next_record()
+            .map(|res| res.map(|rec| rec.to_sounding(stations)))
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Line decoding
+--------------------------------------------------------------------------------------------------*/
+fn parse_header(line: &str, line_num: usize) -> Result<IgraHeader> {
+    if !line.starts_with('#') {
+        return Err(SoundingError::parse(line_num, "expected a header record"));
+    }
+
+    let id = field(line, 2, 12).to_owned();
+    let year: i32 = parse_field(line, 14, 17, line_num, "year")?;
+    let month: u32 = parse_field(line, 19, 20, line_num, "month")?;
+    let day: u32 = parse_field(line, 22, 23, line_num, "day")?;
+    let hour: u32 = parse_field(line, 25, 26, line_num, "hour")?;
+    let release: u32 = parse_field(line, 28, 31, line_num, "release time")?;
+    let num_levels: usize = parse_field(line, 33, 36, line_num, "number of levels")?;
+    let lat: i32 = parse_field(line, 56, 62, line_num, "latitude")?;
+    let lon: i32 = parse_field(line, 64, 71, line_num, "longitude")?;
+
+    // Fall back to the release hour when the nominal hour is missing.
+    let hour = if hour == 99 && release != 9999 && release / 100 != 99 {
+        release / 100
+    } else {
+        hour
+    };
+    let valid_time =
+        NaiveDate::from_ymd_opt(year, month, day).and_then(|d| d.and_hms_opt(hour, 0, 0));
+
+    let location = if lat == -99_999 || lon == -999_999 {
+        None
+    } else {
+        Some((f64::from(lat) / 10_000.0, f64::from(lon) / 10_000.0))
+    };
+
+    Ok(IgraHeader {
+        id,
+        valid_time,
+        num_levels,
+        location,
+    })
+}
+
+fn parse_level(line: &str, line_num: usize) -> Result<IgraLevel> {
+    let level_type = match field(line, 1, 1) {
+        "1" => IgraLevelType::StandardPressure,
+        "2" => IgraLevelType::OtherPressure,
+        "3" => IgraLevelType::NonPressure,
+        val => {
+            return Err(SoundingError::parse(
+                line_num,
+                format!("invalid level type '{}'", val),
+            ));
+        }
+    };
+    let minor_type = field(line, 2, 2);
+
+    let elapsed_time = missing_or(parse_field(line, 4, 8, line_num, "elapsed time")?)
+        .map_t(|mmmss| mmmss / 100 * 60 + mmmss % 100);
+    let pressure = missing_or(parse_field(line, 10, 15, line_num, "pressure")?)
+        .map_t(|p| HectoPascal::from(Pascal(f64::from(p))));
+    let height =
+        missing_or(parse_field(line, 17, 21, line_num, "height")?).map_t(|h| Meters(f64::from(h)));
+    let temperature = missing_or(parse_field(line, 23, 27, line_num, "temperature")?)
+        .map_t(|t| Celsius(f64::from(t) / 10.0));
+    let relative_humidity = missing_or(parse_field(line, 29, 33, line_num, "humidity")?)
+        .map_t(|rh| f64::from(rh) / 10.0);
+    let dpd = missing_or(parse_field(line, 35, 39, line_num, "dew point depression")?)
+        .map_t(|dpd| f64::from(dpd) / 10.0);
+    let direction = missing_or(parse_field(line, 41, 45, line_num, "wind direction")?);
+    let speed = missing_or(parse_field(line, 47, 51, line_num, "wind speed")?);
+
+    let dew_point = match (temperature.into_option(), dpd.into_option()) {
+        (Some(t), Some(dpd)) => some(Celsius(t.unpack() - dpd)),
+        _ => none(),
+    };
+
+    let wind = match (direction.into_option(), speed.into_option()) {
+        (Some(dir), Some(spd)) => some(WindSpdDir {
+            speed: Knots::from(MetersPSec(f64::from(spd) / 10.0)),
+            direction: f64::from(dir),
+        }),
+        _ => none(),
+    };
+
+    Ok(IgraLevel {
+        level_type,
+        surface: minor_type == "1",
+        tropopause: minor_type == "2",
+        elapsed_time,
+        pressure,
+        pressure_flag: parse_flag(line, 16, line_num)?,
+        height,
+        height_flag: parse_flag(line, 22, line_num)?,
+        temperature,
+        temperature_flag: parse_flag(line, 28, line_num)?,
+        relative_humidity,
+        dew_point,
+        wind,
+    })
+}
+
+fn parse_flag(line: &str, col: usize, line_num: usize) -> Result<IgraQcFlag> {
+    match field(line, col, col) {
+        "" => Ok(IgraQcFlag::NotChecked),
+        "A" => Ok(IgraQcFlag::TierOne),
+        "B" => Ok(IgraQcFlag::TierTwo),
+        val => Err(SoundingError::parse(
+            line_num,
+            format!("invalid quality control flag '{}'", val),
+        )),
+    }
+}
+
+/// IGRA2 uses -8888 for values removed by quality control and -9999 for missing values.
+#[inline]
+fn missing_or(val: i32) -> Optioned<i32> {
+    if val == -8888 || val == -9999 {
+        none()
+    } else {
+        some(val)
+    }
+}
+
+/// Get the trimmed contents of the 1-based, inclusive column range of a line. Columns past the end
+/// of the line are treated as blanks.
+#[inline]
+fn field(line: &str, start: usize, end: usize) -> &str {
+    let end = end.min(line.len());
+    let start = (start - 1).min(end);
+    line.get(start..end).unwrap_or("").trim()
+}
+
+fn parse_field<T: std::str::FromStr>(
+    line: &str,
+    start: usize,
+    end: usize,
+    line_num: usize,
+    name: &str,
+) -> Result<T> {
+    let val = field(line, start, end);
+    val.parse()
+        .map_err(|_| SoundingError::parse(line_num, format!("invalid {} '{}'", name, val)))
+}
+
+/// IGRA2 station ids end in an 8 character station code. For WMO stations that code is the WMO
+/// number padded with zeros.
+#[inline]
+fn station_num_from_id(id: &str) -> Optioned<i32> {
+    id.get(3..)
+        .and_then(|code| code.parse().ok())
+        .map(some)
+        .unwrap_or_else(none)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_level_missing_values() {
+        let lvl = parse_level("30   201  -9999  2000 -9999 -9999 -9999   270    51", 1).unwrap();
+
+        assert_eq!(lvl.level_type, IgraLevelType::NonPressure);
+        assert!(!lvl.surface);
+        assert_eq!(lvl.elapsed_time.unwrap(), 121);
+        assert!(lvl.pressure.is_none());
+        assert_eq!(lvl.pressure_flag, IgraQcFlag::NotChecked);
+        assert_eq!(lvl.height.unwrap(), Meters(2000.0));
+        assert!(lvl.temperature.is_none());
+        assert!(lvl.dew_point.is_none());
+        assert!(lvl.wind.is_some());
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let data = "#USM00072776 2018 03 08 99 1105    2 ncdc-gts ncdc-gts  475000 -1113833\n\
+                    21     0  90200B 1131B  105B-9999    45   200    31\n";
+        let mut reader = IgraReader::new(data.as_bytes());
+
+        match reader.next_record() {
+            Some(Err(SoundingError::UnexpectedEof)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_header_release_hour() {
+        let hdr = parse_header(
+            "#USM00072776 2018 03 08 99 1105    2 ncdc-gts ncdc-gts  475000 -1113833",
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(
+            hdr.valid_time.unwrap(),
+            NaiveDate::from_ymd_opt(2018, 3, 8)
+                .unwrap()
+                .and_hms_opt(11, 0, 0)
+                .unwrap()
+        );
+        assert_eq!(hdr.location.unwrap(), (47.5, -111.3833));
+    }
+}
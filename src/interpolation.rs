@@ -0,0 +1,356 @@
+//! Interpolating soundings to new pressure or height levels and to new times.
+//!
+//! Values are interpolated linearly in the log of pressure, or linearly in height, between levels
+//! and linearly in time between soundings. Winds are always interpolated as u and v components so
+//! that a veering wind doesn't sweep through the wrong side of the compass. If either of the two
+//! values being interpolated between is missing, the result is missing.
+
+use chrono::NaiveDateTime;
+use metfor::{HectoPascal, Knots, Meters, Quantity, WindSpdDir, WindUV};
+use optional::{none, some, Noned, Optioned};
+
+use crate::data_row::DataRow;
+use crate::error::{Result, SoundingError};
+use crate::sounding::Sounding;
+
+/// Interpolate all the profile variables of a sounding to the pressure `target_p`.
+///
+/// Returns `None` if `target_p` is below the surface or above the top of the sounding.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{HectoPascal, Quantity};
+/// use sounding_base::interpolate_at_pressure;
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding();
+///
+/// let row = interpolate_at_pressure(&snd, HectoPascal(775.0)).unwrap();
+/// assert_eq!(row.pressure.unwrap(), HectoPascal(775.0));
+///
+/// let t = row.temperature.unwrap().unpack();
+/// assert!(t < 10.0 && t > 2.0);
+///
+/// assert!(interpolate_at_pressure(&snd, HectoPascal(1020.0)).is_none());
+/// assert!(interpolate_at_pressure(&snd, HectoPascal(500.0)).is_none());
+/// ```
+pub fn interpolate_at_pressure<P>(snd: &Sounding, target_p: P) -> Option<DataRow>
+where
+    P: metfor::Pressure,
+    HectoPascal: From<P>,
+{
+    let target_p = HectoPascal::from(target_p);
+
+    // Use -ln(p) so the coordinate increases upward like height.
+    let mut row = interpolate_at_coordinate(
+        snd,
+        |row| row.pressure.map(|p| -p.unpack().ln()),
+        -target_p.unpack().ln(),
+    )?;
+    row.pressure = some(target_p);
+    Some(row)
+}
+
+/// Interpolate all the profile variables of a sounding to the geopotential height `target_z`.
+///
+/// The interpolation is linear in height. Returns `None` if the sounding has no height profile or
+/// `target_z` is outside of it.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Meters, Quantity};
+/// use optional::some;
+/// use sounding_base::interpolate_at_height;
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding().with_height_profile(vec![
+///     some(Meters(100.0)),
+///     some(Meters(750.0)),
+///     some(Meters(1500.0)),
+///     some(Meters(3000.0)),
+/// ]);
+///
+/// let row = interpolate_at_height(&snd, Meters(2250.0)).unwrap();
+/// let p = row.pressure.unwrap().unpack();
+/// assert!(p < 850.0 && p > 700.0);
+///
+/// assert!(interpolate_at_height(&snd, Meters(5000.0)).is_none());
+/// ```
+pub fn interpolate_at_height<L>(snd: &Sounding, target_z: L) -> Option<DataRow>
+where
+    L: metfor::Length,
+    Meters: From<L>,
+{
+    let target_z = Meters::from(target_z);
+
+    let mut row = interpolate_at_coordinate(
+        snd,
+        |row| row.height.map(Quantity::unpack),
+        target_z.unpack(),
+    )?;
+    row.height = some(target_z);
+    Some(row)
+}
+
+/// Interpolate linearly in a vertical coordinate that increases upward, skipping the levels where
+/// it is missing. Returns `None` if `target` is outside the range of the coordinate.
+fn interpolate_at_coordinate<F>(snd: &Sounding, coord: F, target: f64) -> Option<DataRow>
+where
+    F: Fn(&DataRow) -> Option<f64>,
+{
+    let rows: Vec<(f64, DataRow)> = snd
+        .bottom_up()
+        .filter_map(|row| coord(&row).map(|x| (x, row)))
+        .collect();
+
+    for pair in rows.windows(2) {
+        let (x0, row0) = pair[0];
+        let (x1, row1) = pair[1];
+
+        if x0 == target {
+            return Some(row0);
+        }
+        if x1 == target {
+            return Some(row1);
+        }
+        if x0 < target && target < x1 {
+            return Some(interpolate_rows(&row0, &row1, (target - x0) / (x1 - x0)));
+        }
+    }
+
+    // The surface may be the only level.
+    rows.first()
+        .filter(|&&(x, _)| x == target)
+        .map(|&(_, row)| row)
+}
+
+/// Interpolate a sounding onto a list of pressure levels.
+///
+/// The surface values and all the other properties of the sounding are unchanged. Levels that are
+/// below the surface or above the top of the original sounding have missing values for everything
+/// except the pressure.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::HectoPascal;
+/// use sounding_base::on_pressure_levels;
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding();
+/// let levels = [1000.0, 950.0, 900.0, 850.0, 800.0, 750.0, 700.0, 650.0];
+/// let levels: Vec<_> = levels.iter().cloned().map(HectoPascal).collect();
+///
+/// let new_snd = on_pressure_levels(&snd, &levels);
+///
+/// assert_eq!(new_snd.station_pressure(), snd.station_pressure());
+/// assert_eq!(new_snd.pressure_profile().len(), levels.len() + 1); // Plus the surface.
+/// assert!(new_snd.temperature_profile()[4].is_some()); // 850 hPa
+/// assert!(new_snd.temperature_profile()[8].is_none()); // 650 hPa is above the top.
+/// ```
+pub fn on_pressure_levels(snd: &Sounding, levels: &[HectoPascal]) -> Sounding {
+    let mut rows = Vec::with_capacity(levels.len() + 1);
+    rows.push(snd.surface_as_data_row().unwrap_or_default());
+
+    for &p in levels {
+        let row = interpolate_at_pressure(snd, p).unwrap_or(DataRow {
+            pressure: some(p),
+            ..DataRow::default()
+        });
+        rows.push(row);
+    }
+
+    snd.clone().with_data_rows(&rows)
+}
+
+/// Interpolate between two soundings from the same station to the time `target`.
+///
+/// Both soundings are first interpolated onto the pressure levels of either sounding, and then
+/// every level, the surface values, and the lead time are interpolated in time. Winds are
+/// interpolated as u and v components. The station info and source description are taken from
+/// the first sounding.
+///
+/// This fails if either sounding has no valid time, if the soundings are from different stations,
+/// or if `target` is not between the two valid times.
+///
+/// # Examples
+///
+/// ```rust
+/// use chrono::{Duration, NaiveDate};
+/// use metfor::{Celsius, HectoPascal};
+/// use sounding_base::interpolate_in_time;
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let vt = NaiveDate::from_ymd_opt(2019, 6, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+///
+/// let snd12z = make_test_sounding().with_valid_time(vt).with_lead_time(0);
+/// let snd18z = make_test_sounding()
+///     .with_valid_time(vt + Duration::hours(6))
+///     .with_lead_time(6)
+///     .with_sfc_temperature(Celsius(27.0));
+///
+/// let snd15z = interpolate_in_time(&snd12z, &snd18z, vt + Duration::hours(3)).unwrap();
+///
+/// assert_eq!(snd15z.valid_time().unwrap(), vt + Duration::hours(3));
+/// assert_eq!(snd15z.lead_time().unwrap(), 3);
+/// assert_eq!(snd15z.sfc_temperature().unwrap(), Celsius(24.0));
+/// assert_eq!(snd15z.pressure_profile()[1].unwrap(), HectoPascal(1000.0));
+///
+/// assert!(interpolate_in_time(&snd12z, &snd18z, vt + Duration::hours(7)).is_err());
+/// ```
+pub fn interpolate_in_time(
+    snd0: &Sounding,
+    snd1: &Sounding,
+    target: NaiveDateTime,
+) -> Result<Sounding> {
+    let vt0 = snd0.valid_time().ok_or(SoundingError::MissingValidTime)?;
+    let vt1 = snd1.valid_time().ok_or(SoundingError::MissingValidTime)?;
+
+    if !snd0.station_info().is_same_station(&snd1.station_info()) {
+        return Err(SoundingError::Incompatible(
+            "soundings are from different stations".to_owned(),
+        ));
+    }
+    if target < vt0.min(vt1) || target > vt0.max(vt1) {
+        return Err(SoundingError::Incompatible(format!(
+            "{} is not between the valid times {} and {}",
+            target, vt0, vt1
+        )));
+    }
+
+    let w = if vt0 == vt1 {
+        0.0
+    } else {
+        (target - vt0).num_seconds() as f64 / (vt1 - vt0).num_seconds() as f64
+    };
+
+    let levels = common_pressure_levels(&[snd0, snd1]);
+    let snd0 = on_pressure_levels(snd0, &levels);
+    let snd1 = on_pressure_levels(snd1, &levels);
+
+    let rows: Vec<DataRow> = snd0
+        .bottom_up()
+        .zip(snd1.bottom_up())
+        .map(|(row0, row1)| interpolate_rows(&row0, &row1, w))
+        .enumerate()
+        .map(|(i, mut row)| {
+            // Keep the levels exact, they're identical anyway.
+            if i > 0 {
+                row.pressure = some(levels[i - 1]);
+            }
+            row
+        })
+        .collect();
+
+    let lead_time = match (
+        snd0.lead_time().into_option(),
+        snd1.lead_time().into_option(),
+    ) {
+        (Some(lt0), Some(lt1)) => some(lt0 + (w * f64::from(lt1 - lt0)).round() as i32),
+        _ => none(),
+    };
+
+    Ok(snd0
+        .clone()
+        .with_valid_time(target)
+        .with_lead_time(lead_time)
+        .with_mslp(lerp(snd0.mslp(), snd1.mslp(), w))
+        .with_precipitation(lerp(snd0.precipitation(), snd1.precipitation(), w))
+        .with_low_cloud(lerp_f64(snd0.low_cloud(), snd1.low_cloud(), w))
+        .with_mid_cloud(lerp_f64(snd0.mid_cloud(), snd1.mid_cloud(), w))
+        .with_high_cloud(lerp_f64(snd0.high_cloud(), snd1.high_cloud(), w))
+        .with_data_rows(&rows))
+}
+
+/// Get every pressure level above the surface in any of the soundings, sorted from the bottom up
+/// with duplicates removed.
+pub(crate) fn common_pressure_levels(soundings: &[&Sounding]) -> Vec<HectoPascal> {
+    let mut levels: Vec<HectoPascal> = soundings
+        .iter()
+        .flat_map(|snd| snd.pressure_profile().iter().skip(1))
+        .filter_map(|p| p.into_option())
+        .collect();
+
+    levels.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    levels.dedup();
+    levels
+}
+
+/// Interpolate every variable between two rows with weight `w`, where 0.0 gives `row0` and 1.0
+/// gives `row1`.
+pub(crate) fn interpolate_rows(row0: &DataRow, row1: &DataRow, w: f64) -> DataRow {
+    DataRow {
+        pressure: lerp(row0.pressure, row1.pressure, w),
+        temperature: lerp(row0.temperature, row1.temperature, w),
+        wet_bulb: lerp(row0.wet_bulb, row1.wet_bulb, w),
+        dew_point: lerp(row0.dew_point, row1.dew_point, w),
+        theta_e: lerp(row0.theta_e, row1.theta_e, w),
+        wind: lerp_wind(row0.wind, row1.wind, w),
+        pvv: lerp(row0.pvv, row1.pvv, w),
+        height: lerp(row0.height, row1.height, w),
+        cloud_fraction: lerp_f64(row0.cloud_fraction, row1.cloud_fraction, w),
+    }
+}
+
+#[inline]
+fn lerp<Q>(a: Optioned<Q>, b: Optioned<Q>, w: f64) -> Optioned<Q>
+where
+    Q: Quantity + Noned,
+{
+    match (a.into_option(), b.into_option()) {
+        (Some(a), Some(b)) => some(Q::pack(a.unpack() + w * (b.unpack() - a.unpack()))),
+        _ => none(),
+    }
+}
+
+#[inline]
+fn lerp_f64(a: Optioned<f64>, b: Optioned<f64>, w: f64) -> Optioned<f64> {
+    match (a.into_option(), b.into_option()) {
+        (Some(a), Some(b)) => some(a + w * (b - a)),
+        _ => none(),
+    }
+}
+
+#[inline]
+fn lerp_wind(
+    a: Optioned<WindSpdDir<Knots>>,
+    b: Optioned<WindSpdDir<Knots>>,
+    w: f64,
+) -> Optioned<WindSpdDir<Knots>> {
+    match (a.into_option(), b.into_option()) {
+        (Some(a), Some(b)) => {
+            let a: WindUV<Knots> = WindUV::from(a);
+            let b: WindUV<Knots> = WindUV::from(b);
+
+            some(WindSpdDir::from(WindUV {
+                u: Knots(a.u.unpack() + w * (b.u.unpack() - a.u.unpack())),
+                v: Knots(a.v.unpack() + w * (b.v.unpack() - a.v.unpack())),
+            }))
+        }
+        _ => none(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wind_interpolated_as_vectors() {
+        let wind = |direction| {
+            some(WindSpdDir {
+                speed: Knots(10.0),
+                direction,
+            })
+        };
+
+        // Interpolating across north must not swing through south.
+        let mid = lerp_wind(wind(350.0), wind(10.0), 0.5).unwrap();
+        assert!(mid.direction < 1.0 || mid.direction > 359.0);
+        assert!((mid.speed.unpack() - 10.0 * 10.0f64.to_radians().cos()).abs() < 1.0e-6);
+
+        assert!(lerp_wind(wind(350.0), none(), 0.5).is_none());
+    }
+}
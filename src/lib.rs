@@ -1,4 +1,4 @@
-#![doc(test(attr(deny(warnings))))]
+#![doc(test(attr(deny(warnings), allow(deprecated))))]
 #![deprecated]
 /*!
 
@@ -102,23 +102,97 @@ You probably noticed a lot of `optional::Optioned`s in the example. Basically, a
 missing, and missing values are common in upper air soundings. For example, at high altitude the
 dew point or humidity are often missing (if not totally inaccurate).
 
+# Optional features
+
+* `serde` - `Serialize` and `Deserialize` for `Sounding`, `DataRow`, and `StationInfo`.
+* `arrow` - convert collections of soundings into Apache Arrow record batches.
+* `parquet` - write collections of soundings to Parquet files, implies `arrow`.
+* `plot` - render soundings as skew-T log-P diagrams and hodographs in SVG.
+
+The `arrow` and `parquet` features depend on version 57 of the Apache Arrow crates, which need
+Rust 1.85 or newer, a higher minimum version than the rest of the crate.
+
 */
 #![deny(missing_docs)]
 
 //
 // API
 //
-pub use crate::data_row::DataRow;
+pub use crate::bufkit::read_bufkit;
+pub use crate::csv::{CsvReader, CsvWriter};
+pub use crate::data_row::{DataRow, ProfileVariable};
+pub use crate::derived::ExtendedDataRow;
+pub use crate::diagram::{
+    DiagramTransform, EmagramTransform, SkewTTransform, StuveTransform, TephigramTransform,
+};
+pub use crate::ensemble::Ensemble;
+pub use crate::error::{Result, SoundingError};
+pub use crate::grid::{ProfileGrid, ProfileGridBuilder, VerticalAxis};
+pub use crate::hydrostatic::{
+    check_hydrostatic_heights, fill_missing_heights, hydrostatic_heights,
+};
+pub use crate::igra::{
+    IgraHeader, IgraLevel, IgraLevelType, IgraQcFlag, IgraReader, IgraRecord, IgraStationList,
+};
+pub use crate::interpolation::{
+    interpolate_at_height, interpolate_at_pressure, interpolate_in_time, on_pressure_levels,
+};
+pub use crate::merge::SoundingMerger;
+pub use crate::netcdf::{write_netcdf, NETCDF_FILL_VALUE};
+pub use crate::qc::{QcFlag, QcFlags, QualityControl};
+pub use crate::series::SoundingSeries;
+pub use crate::sharppy::{read_sharppy, write_sharppy};
 pub use crate::sounding::Sounding;
 pub use crate::station_info::StationInfo;
+pub use crate::table::TableFormatter;
+pub use crate::thinning::SignificantLevels;
+pub use crate::verification::{
+    Comparison, ErrorAccumulator, ErrorStats, LevelError, VerificationVariable,
+};
+pub use crate::wind::WindRepresentation;
+
+#[cfg(feature = "parquet")]
+pub use crate::columnar::write_parquet;
+#[cfg(feature = "arrow")]
+pub use crate::columnar::{arrow_schema, to_record_batch};
+#[cfg(feature = "plot")]
+pub use crate::plot::{Hodograph, SkewT};
+#[cfg(feature = "serde")]
+pub use crate::serde_impl::SCHEMA_VERSION;
 
 //
 // Internal use only
 //
 
+mod bufkit;
+#[cfg(feature = "arrow")]
+mod columnar;
+mod csv;
 mod data_row;
+mod derived;
+mod diagram;
+mod ensemble;
+mod error;
+mod grid;
+mod hydrostatic;
+mod igra;
+mod interpolation;
+mod merge;
+mod netcdf;
+#[cfg(feature = "plot")]
+mod plot;
+mod qc;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod series;
+mod sharppy;
 mod sounding;
 mod station_info;
+mod table;
+mod thinning;
+mod verification;
+mod vertical_velocity;
+mod wind;
 
 #[doc(hidden)]
 pub use crate::sounding::doctest;
@@ -0,0 +1,294 @@
+//! Merge partial soundings, e.g. mandatory, significant temperature and significant wind levels.
+
+use crate::data_row::DataRow;
+use crate::error::{Result, SoundingError};
+use crate::interpolation::interpolate_rows;
+use crate::sounding::Sounding;
+
+/// Merge several partial soundings, or lists of levels, into a single sounding sorted by pressure.
+///
+/// Levels with the same pressure are combined into one, taking each variable from the first level
+/// that has a value for it. Levels without a pressure are dropped. Optionally, variables still
+/// missing after the merge are interpolated, linearly in log(p), from the nearest levels above and
+/// below that have a value.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal, Knots, WindSpdDir};
+/// use optional::some;
+/// use sounding_base::{Sounding, SoundingMerger};
+///
+/// let temperatures = Sounding::new()
+///     .with_pressure_profile(vec![some(HectoPascal(1000.0)), some(HectoPascal(700.0))])
+///     .with_temperature_profile(vec![some(Celsius(20.0)), some(Celsius(2.0))]);
+///
+/// let wind = some(WindSpdDir { speed: Knots(20.0), direction: 270.0 });
+/// let winds = Sounding::new()
+///     .with_pressure_profile(vec![some(HectoPascal(850.0)), some(HectoPascal(700.0))])
+///     .with_wind_profile(vec![wind, wind]);
+///
+/// let snd = SoundingMerger::new().merge(&[temperatures.clone(), winds.clone()]).unwrap();
+/// let pressures: Vec<_> = snd.pressure_profile().iter().skip(1).map(|p| p.unwrap().0).collect();
+/// assert_eq!(pressures, vec![1000.0, 850.0, 700.0]);
+/// assert!(snd.temperature_profile()[2].is_none());
+/// assert!(snd.wind_profile()[3].is_some());
+///
+/// let snd = SoundingMerger::new()
+///     .with_interpolation(true)
+///     .merge(&[temperatures, winds])
+///     .unwrap();
+/// let t = snd.temperature_profile()[2].unwrap();
+/// assert!(t > Celsius(2.0) && t < Celsius(20.0));
+/// assert!(snd.wind_profile()[1].is_none()); // No extrapolation below the lowest wind.
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SoundingMerger {
+    interpolate: bool,
+}
+
+impl SoundingMerger {
+    /// Create a merger that leaves missing values missing.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder method to interpolate missing values from the levels above and below.
+    #[inline]
+    pub fn with_interpolation(mut self, interpolate: bool) -> Self {
+        self.interpolate = interpolate;
+        self
+    }
+
+    /// Merge soundings from the same station and valid time.
+    ///
+    /// The surface values are merged the same way as the levels. The station information, times
+    /// and other surface values like mean sea level pressure come from the first sounding. This
+    /// fails if there are no soundings, or if they are not all for the same station and valid time.
+    pub fn merge<'a, I>(&self, soundings: I) -> Result<Sounding>
+    where
+        I: IntoIterator<Item = &'a Sounding>,
+    {
+        let soundings: Vec<&Sounding> = soundings.into_iter().collect();
+
+        let first = soundings
+            .first()
+            .ok_or_else(|| SoundingError::Incompatible("no soundings to merge".to_owned()))?;
+        for snd in &soundings[1..] {
+            if !first.station_info().is_same_station(&snd.station_info()) {
+                return Err(SoundingError::Incompatible(
+                    "merged soundings are from different stations".to_owned(),
+                ));
+            }
+            if first.valid_time() != snd.valid_time() {
+                return Err(SoundingError::Incompatible(
+                    "merged soundings have different valid times".to_owned(),
+                ));
+            }
+        }
+
+        let mut sfc = DataRow::default();
+        for snd in &soundings {
+            if let Some(row) = snd.data_row(0) {
+                fill_from(&mut sfc, &row);
+            }
+        }
+
+        let mut rows = vec![sfc];
+        rows.extend(self.merge_rows(soundings.iter().flat_map(|snd| snd.bottom_up().skip(1))));
+
+        Ok((*first).clone().with_data_rows(&rows))
+    }
+
+    /// Merge lists of levels into one list sorted from the bottom up.
+    pub fn merge_rows<I>(&self, rows: I) -> Vec<DataRow>
+    where
+        I: IntoIterator<Item = DataRow>,
+    {
+        let mut rows: Vec<DataRow> = rows
+            .into_iter()
+            .filter(|row| row.pressure.is_some())
+            .collect();
+        // A stable sort, so the first level given for each pressure comes first.
+        rows.sort_by(|a, b| {
+            b.pressure
+                .partial_cmp(&a.pressure)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let mut merged: Vec<DataRow> = Vec::with_capacity(rows.len());
+        for row in rows {
+            match merged.last_mut() {
+                Some(last) if last.pressure == row.pressure => fill_from(last, &row),
+                _ => merged.push(row),
+            }
+        }
+
+        if self.interpolate {
+            fill_missing(&merged)
+        } else {
+            merged
+        }
+    }
+}
+
+/// Fill the missing values in `row` from `other`.
+fn fill_from(row: &mut DataRow, other: &DataRow) {
+    macro_rules! fill {
+        ($($field:ident),*) => {
+            $(
+                if row.$field.is_none() {
+                    row.$field = other.$field;
+                }
+            )*
+        };
+    }
+
+    fill!(
+        pressure,
+        temperature,
+        wet_bulb,
+        dew_point,
+        theta_e,
+        wind,
+        pvv,
+        height,
+        cloud_fraction
+    );
+}
+
+/// Interpolate each missing value from the nearest levels above and below with a value. The rows
+/// must all have a pressure and be sorted from the bottom up.
+fn fill_missing(rows: &[DataRow]) -> Vec<DataRow> {
+    let ln_p: Vec<f64> = rows
+        .iter()
+        .map(|row| row.pressure.map_or(f64::NAN, |p| p.0.ln()))
+        .collect();
+    let mut filled = rows.to_vec();
+
+    macro_rules! fill {
+        ($($field:ident),*) => {
+            $(
+                let present: Vec<usize> = (0..rows.len())
+                    .filter(|&i| rows[i].$field.is_some())
+                    .collect();
+                for pair in present.windows(2) {
+                    let (below, above) = (pair[0], pair[1]);
+                    for i in below + 1..above {
+                        let w = (ln_p[below] - ln_p[i]) / (ln_p[below] - ln_p[above]);
+                        filled[i].$field = interpolate_rows(&rows[below], &rows[above], w).$field;
+                    }
+                }
+            )*
+        };
+    }
+
+    fill!(
+        temperature,
+        wet_bulb,
+        dew_point,
+        theta_e,
+        wind,
+        pvv,
+        height,
+        cloud_fraction
+    );
+
+    filled
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::qc::QualityControl;
+    use crate::sounding::doctest::make_test_sounding;
+    use crate::station_info::StationInfo;
+    use chrono::NaiveDate;
+    use metfor::{Celsius, HectoPascal, PaPS};
+    use optional::some;
+
+    fn level(p: f64, t: Option<f64>, dp: Option<f64>) -> DataRow {
+        DataRow {
+            pressure: some(HectoPascal(p)),
+            temperature: t.map(Celsius).into(),
+            dew_point: dp.map(Celsius).into(),
+            ..DataRow::default()
+        }
+    }
+
+    #[test]
+    fn test_duplicate_pressures_and_precedence() {
+        let rows = vec![
+            level(850.0, Some(10.0), None),
+            level(1000.0, Some(20.0), Some(15.0)),
+            level(850.0, Some(12.0), Some(5.0)),
+            level(1000.0, Some(22.0), None),
+            DataRow::default(), // No pressure, dropped.
+        ];
+
+        let merged = SoundingMerger::new().merge_rows(rows);
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].pressure, some(HectoPascal(1000.0)));
+        // The first level given for a pressure wins, the later ones only fill in missing values.
+        assert_eq!(merged[0].temperature, some(Celsius(20.0)));
+        assert_eq!(merged[0].dew_point, some(Celsius(15.0)));
+        assert_eq!(merged[1].temperature, some(Celsius(10.0)));
+        assert_eq!(merged[1].dew_point, some(Celsius(5.0)));
+    }
+
+    #[test]
+    fn test_merge_surface_values_and_qc_flags() {
+        let first = QualityControl::new().apply(make_test_sounding());
+        assert_eq!(first.qc_flags().len(), 5);
+
+        let second = make_test_sounding()
+            .with_pvv_profile(vec![some(PaPS(1.0)); 4])
+            .with_cloud_fraction_profile(vec![some(50.0); 4]);
+        let mut rows: Vec<DataRow> = second.bottom_up().collect();
+        rows[0].pvv = some(PaPS(-0.5));
+        rows[0].cloud_fraction = some(25.0);
+        rows.push(level(500.0, Some(-20.0), None));
+        let second = second.with_data_rows(&rows);
+
+        let merged = SoundingMerger::new().merge(&[first, second]).unwrap();
+
+        assert!(merged.qc_flags().is_empty());
+        assert_eq!(merged.pressure_profile().len(), 6);
+        assert_eq!(merged.pvv_profile()[0], some(PaPS(-0.5)));
+        assert_eq!(merged.cloud_fraction_profile()[0], some(25.0));
+    }
+
+    #[test]
+    fn test_incompatible_soundings() {
+        let vt = NaiveDate::from_ymd_opt(2019, 6, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let snd = make_test_sounding()
+            .with_station_info(StationInfo::new().with_station(72776))
+            .with_valid_time(vt);
+
+        let other_station = snd
+            .clone()
+            .with_station_info(StationInfo::new().with_station(72558));
+        assert!(matches!(
+            SoundingMerger::new().merge(&[snd.clone(), other_station]),
+            Err(SoundingError::Incompatible(_))
+        ));
+
+        let other_time = snd
+            .clone()
+            .with_valid_time(vt + chrono::Duration::hours(12));
+        assert!(matches!(
+            SoundingMerger::new().merge(&[snd.clone(), other_time]),
+            Err(SoundingError::Incompatible(_))
+        ));
+
+        let no_time = snd.clone().with_valid_time(None);
+        assert!(SoundingMerger::new().merge(&[snd, no_time]).is_err());
+
+        assert!(SoundingMerger::new().merge(&[]).is_err());
+    }
+}
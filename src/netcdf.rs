@@ -0,0 +1,658 @@
+//! Writer for CF-convention profiles in the netCDF classic file format.
+//!
+//! The classic format is simple enough to write directly, so there is no dependency on the netCDF
+//! or HDF5 C libraries. A sounding is written as a single profile discrete sampling geometry
+//! (`featureType = "profile"`) with one `level` dimension. Levels without a pressure are skipped
+//! since pressure is the vertical coordinate, and only the profiles present in the sounding are
+//! written. Missing values are set to the `_FillValue` of the variable.
+
+use std::io::Write;
+
+use metfor::Quantity;
+use optional::{Noned, Optioned};
+
+use crate::data_row::DataRow;
+use crate::error::{Result, SoundingError};
+use crate::sounding::Sounding;
+
+/// The `_FillValue` used for all floating point variables.
+pub const NETCDF_FILL_VALUE: f64 = -9999.0;
+
+/// The `_FillValue` used for all integer variables.
+const INT_FILL_VALUE: i32 = -2_147_483_647;
+
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+const NC_CHAR: u32 = 2;
+const NC_INT: u32 = 4;
+const NC_DOUBLE: u32 = 6;
+
+/// Write a sounding as a CF-compliant netCDF classic (CDF-1) file.
+///
+/// Station metadata is written as global attributes and as the scalar `lat`, `lon`, and `alt`
+/// variables. Each profile variable has its CF `standard_name` and `units`, with the units being
+/// the ones used by `Sounding`.
+///
+/// Returns `SoundingError::NoPressureLevels` if no level has a pressure, since a `level` dimension
+/// of length zero would be read as the unlimited dimension.
+///
+/// # Examples
+///
+/// ```rust
+/// use sounding_base::write_netcdf;
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding();
+///
+/// let mut file: Vec<u8> = vec![];
+/// write_netcdf(&snd, &mut file).unwrap();
+/// assert_eq!(&file[..4], b"CDF\x01");
+/// ```
+pub fn write_netcdf<W: Write>(snd: &Sounding, mut dest: W) -> Result<()> {
+    let rows: Vec<DataRow> = snd
+        .bottom_up()
+        .filter(|row| row.pressure.is_some())
+        .collect();
+
+    if rows.is_empty() {
+        return Err(SoundingError::NoPressureLevels);
+    }
+
+    let file = NcFile::from_sounding(snd, &rows);
+    dest.write_all(&file.to_bytes())?;
+
+    Ok(())
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                    Mapping the sounding to CF
+--------------------------------------------------------------------------------------------------*/
+impl NcFile {
+    fn from_sounding(snd: &Sounding, rows: &[DataRow]) -> Self {
+        let stn = snd.station_info();
+
+        let mut global_attrs = vec![
+            NcAttr::text("Conventions", "CF-1.8"),
+            NcAttr::text("featureType", "profile"),
+            NcAttr::text("title", "Atmospheric sounding"),
+        ];
+        if let Some(source) = snd.source_description() {
+            global_attrs.push(NcAttr::text("source", source));
+        }
+        if let Some(num) = stn.station_num().into_option() {
+            global_attrs.push(NcAttr::int("station_number", num));
+        }
+        if let Some((lat, lon)) = stn.location() {
+            global_attrs.push(NcAttr::double("station_latitude", lat));
+            global_attrs.push(NcAttr::double("station_longitude", lon));
+        }
+        if let Some(elev) = stn.elevation().into_option() {
+            global_attrs.push(NcAttr::double("station_elevation", elev.unpack()));
+        }
+
+        let (lat, lon) = stn
+            .location()
+            .map(|(lat, lon)| (Optioned::from(lat), Optioned::from(lon)))
+            .unwrap_or_else(|| (optional::none(), optional::none()));
+        let vt = snd
+            .valid_time()
+            .map(|vt| vt.and_utc().timestamp() as f64)
+            .into();
+
+        let mut vars = vec![
+            NcVar::scalar("time", vt)
+                .with_attr(NcAttr::text("standard_name", "time"))
+                .with_attr(NcAttr::text("units", "seconds since 1970-01-01 00:00:00"))
+                .with_attr(NcAttr::text("calendar", "standard")),
+            NcVar::scalar("lat", lat)
+                .with_attr(NcAttr::text("standard_name", "latitude"))
+                .with_attr(NcAttr::text("units", "degrees_north")),
+            NcVar::scalar("lon", lon)
+                .with_attr(NcAttr::text("standard_name", "longitude"))
+                .with_attr(NcAttr::text("units", "degrees_east")),
+            NcVar::scalar("alt", quantity(stn.elevation()))
+                .with_attr(NcAttr::text("standard_name", "surface_altitude"))
+                .with_attr(NcAttr::text("units", "m")),
+            NcVar::int_scalar("profile", stn.station_num())
+                .with_attr(NcAttr::text("cf_role", "profile_id"))
+                .with_attr(NcAttr::text("long_name", "station number")),
+        ];
+
+        if snd.lead_time().is_some() {
+            vars.push(
+                NcVar::scalar("forecast_period", snd.lead_time().map_t(f64::from))
+                    .with_attr(NcAttr::text("standard_name", "forecast_period"))
+                    .with_attr(NcAttr::text("units", "hours")),
+            );
+        }
+        if snd.mslp().is_some() {
+            vars.push(
+                NcVar::scalar("mslp", quantity(snd.mslp()))
+                    .with_attr(NcAttr::text(
+                        "standard_name",
+                        "air_pressure_at_mean_sea_level",
+                    ))
+                    .with_attr(NcAttr::text("units", "hPa")),
+            );
+        }
+        if snd.precipitation().is_some() {
+            vars.push(
+                NcVar::scalar("precipitation", quantity(snd.precipitation()))
+                    .with_attr(NcAttr::text(
+                        "standard_name",
+                        "thickness_of_precipitation_amount",
+                    ))
+                    .with_attr(NcAttr::text("units", "mm")),
+            );
+        }
+
+        macro_rules! add_profile {
+            ($name:expr, $std_name:expr, $units:expr, $profile:expr, $value:expr) => {
+                if !$profile.is_empty() {
+                    let data = rows.iter().map($value).collect();
+                    vars.push(
+                        NcVar::profile($name, data)
+                            .with_attr(NcAttr::text("standard_name", $std_name))
+                            .with_attr(NcAttr::text("units", $units))
+                            .with_attr(NcAttr::text("coordinates", "time lat lon pressure")),
+                    );
+                }
+            };
+        }
+
+        vars.push(
+            NcVar::profile(
+                "pressure",
+                rows.iter().map(|row| quantity(row.pressure)).collect(),
+            )
+            .with_attr(NcAttr::text("standard_name", "air_pressure"))
+            .with_attr(NcAttr::text("units", "hPa"))
+            .with_attr(NcAttr::text("axis", "Z"))
+            .with_attr(NcAttr::text("positive", "down")),
+        );
+        add_profile!(
+            "height",
+            "geopotential_height",
+            "m",
+            snd.height_profile(),
+            |row| quantity(row.height)
+        );
+        add_profile!(
+            "temperature",
+            "air_temperature",
+            "degC",
+            snd.temperature_profile(),
+            |row| quantity(row.temperature)
+        );
+        add_profile!(
+            "dew_point",
+            "dew_point_temperature",
+            "degC",
+            snd.dew_point_profile(),
+            |row| quantity(row.dew_point)
+        );
+        add_profile!(
+            "wet_bulb",
+            "wet_bulb_temperature",
+            "degC",
+            snd.wet_bulb_profile(),
+            |row| quantity(row.wet_bulb)
+        );
+        add_profile!(
+            "theta_e",
+            "equivalent_potential_temperature",
+            "K",
+            snd.theta_e_profile(),
+            |row| quantity(row.theta_e)
+        );
+        add_profile!(
+            "wind_direction",
+            "wind_from_direction",
+            "degree",
+            snd.wind_profile(),
+            |row| row.wind.map_t(|wind| wind.direction)
+        );
+        add_profile!(
+            "wind_speed",
+            "wind_speed",
+            "knot",
+            snd.wind_profile(),
+            |row| row.wind.map_t(|wind| wind.speed.unpack())
+        );
+        add_profile!(
+            "pvv",
+            "lagrangian_tendency_of_air_pressure",
+            "Pa s-1",
+            snd.pvv_profile(),
+            |row| quantity(row.pvv)
+        );
+        add_profile!(
+            "cloud_fraction",
+            "cloud_area_fraction_in_atmosphere_layer",
+            "percent",
+            snd.cloud_fraction_profile(),
+            |row| row.cloud_fraction
+        );
+
+        NcFile {
+            dims: vec![("level", rows.len())],
+            global_attrs,
+            vars,
+        }
+    }
+}
+
+#[inline]
+fn quantity<T: Quantity + Noned>(val: Optioned<T>) -> Optioned<f64> {
+    val.map_t(Quantity::unpack)
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                 netCDF classic format encoding
+--------------------------------------------------------------------------------------------------*/
+struct NcFile {
+    dims: Vec<(&'static str, usize)>,
+    global_attrs: Vec<NcAttr>,
+    vars: Vec<NcVar>,
+}
+
+enum NcValues {
+    Text(String),
+    Int(Vec<i32>),
+    Double(Vec<f64>),
+}
+
+struct NcAttr {
+    name: &'static str,
+    values: NcValues,
+}
+
+struct NcVar {
+    name: &'static str,
+    /// Dimension ids, empty for a scalar.
+    dims: Vec<u32>,
+    attrs: Vec<NcAttr>,
+    data: NcValues,
+}
+
+impl NcAttr {
+    fn text(name: &'static str, val: &str) -> Self {
+        NcAttr {
+            name,
+            values: NcValues::Text(val.to_owned()),
+        }
+    }
+
+    fn int(name: &'static str, val: i32) -> Self {
+        NcAttr {
+            name,
+            values: NcValues::Int(vec![val]),
+        }
+    }
+
+    fn double(name: &'static str, val: f64) -> Self {
+        NcAttr {
+            name,
+            values: NcValues::Double(vec![val]),
+        }
+    }
+}
+
+impl NcVar {
+    fn scalar(name: &'static str, val: Optioned<f64>) -> Self {
+        NcVar {
+            name,
+            dims: vec![],
+            attrs: vec![NcAttr::double("_FillValue", NETCDF_FILL_VALUE)],
+            data: NcValues::Double(vec![val.unwrap_or(NETCDF_FILL_VALUE)]),
+        }
+    }
+
+    fn int_scalar(name: &'static str, val: Optioned<i32>) -> Self {
+        NcVar {
+            name,
+            dims: vec![],
+            attrs: vec![NcAttr::int("_FillValue", INT_FILL_VALUE)],
+            data: NcValues::Int(vec![val.unwrap_or(INT_FILL_VALUE)]),
+        }
+    }
+
+    /// A variable along the `level` dimension.
+    fn profile(name: &'static str, vals: Vec<Optioned<f64>>) -> Self {
+        NcVar {
+            name,
+            dims: vec![0],
+            attrs: vec![NcAttr::double("_FillValue", NETCDF_FILL_VALUE)],
+            data: NcValues::Double(
+                vals.into_iter()
+                    .map(|val| val.unwrap_or(NETCDF_FILL_VALUE))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn with_attr(mut self, attr: NcAttr) -> Self {
+        self.attrs.push(attr);
+        self
+    }
+}
+
+impl NcValues {
+    fn nc_type(&self) -> u32 {
+        match self {
+            NcValues::Text(_) => NC_CHAR,
+            NcValues::Int(_) => NC_INT,
+            NcValues::Double(_) => NC_DOUBLE,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            NcValues::Text(txt) => txt.len(),
+            NcValues::Int(vals) => vals.len(),
+            NcValues::Double(vals) => vals.len(),
+        }
+    }
+
+    /// Write the values padded to a 4 byte boundary.
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            NcValues::Text(txt) => buf.extend_from_slice(txt.as_bytes()),
+            NcValues::Int(vals) => vals
+                .iter()
+                .for_each(|val| buf.extend_from_slice(&val.to_be_bytes())),
+            NcValues::Double(vals) => vals
+                .iter()
+                .for_each(|val| buf.extend_from_slice(&val.to_be_bytes())),
+        }
+        pad(buf);
+    }
+
+    /// The size of the encoded values, including padding.
+    fn encoded_size(&self) -> usize {
+        let size = match self {
+            NcValues::Text(txt) => txt.len(),
+            NcValues::Int(vals) => vals.len() * 4,
+            NcValues::Double(vals) => vals.len() * 8,
+        };
+        size.div_ceil(4) * 4
+    }
+}
+
+impl NcFile {
+    fn to_bytes(&self) -> Vec<u8> {
+        // The header size doesn't depend on the data offsets, so encode it once to find the size.
+        let header_size = self.encode_header(0).len();
+        let mut buf = self.encode_header(header_size);
+
+        for var in &self.vars {
+            var.data.encode(&mut buf);
+        }
+
+        buf
+    }
+
+    fn encode_header(&self, data_start: usize) -> Vec<u8> {
+        let mut buf = b"CDF\x01".to_vec();
+        put_u32(&mut buf, 0); // No record variables.
+
+        put_u32(&mut buf, NC_DIMENSION);
+        put_u32(&mut buf, self.dims.len() as u32);
+        for (name, len) in &self.dims {
+            put_name(&mut buf, name);
+            put_u32(&mut buf, *len as u32);
+        }
+
+        put_attrs(&mut buf, &self.global_attrs);
+
+        put_u32(&mut buf, NC_VARIABLE);
+        put_u32(&mut buf, self.vars.len() as u32);
+        let mut offset = data_start;
+        for var in &self.vars {
+            put_name(&mut buf, var.name);
+            put_u32(&mut buf, var.dims.len() as u32);
+            for dim_id in &var.dims {
+                put_u32(&mut buf, *dim_id);
+            }
+            put_attrs(&mut buf, &var.attrs);
+            put_u32(&mut buf, var.data.nc_type());
+
+            let vsize = var.data.encoded_size();
+            put_u32(&mut buf, vsize as u32);
+            put_u32(&mut buf, offset as u32);
+            offset += vsize;
+        }
+
+        buf
+    }
+}
+
+fn put_attrs(buf: &mut Vec<u8>, attrs: &[NcAttr]) {
+    if attrs.is_empty() {
+        // ABSENT
+        put_u32(buf, 0);
+        put_u32(buf, 0);
+        return;
+    }
+
+    put_u32(buf, NC_ATTRIBUTE);
+    put_u32(buf, attrs.len() as u32);
+    for attr in attrs {
+        put_name(buf, attr.name);
+        put_u32(buf, attr.values.nc_type());
+        put_u32(buf, attr.values.len() as u32);
+        attr.values.encode(buf);
+    }
+}
+
+#[inline]
+fn put_u32(buf: &mut Vec<u8>, val: u32) {
+    buf.extend_from_slice(&val.to_be_bytes());
+}
+
+#[inline]
+fn put_name(buf: &mut Vec<u8>, name: &str) {
+    put_u32(buf, name.len() as u32);
+    buf.extend_from_slice(name.as_bytes());
+    pad(buf);
+}
+
+// `usize::is_multiple_of` needs Rust 1.87, newer than the rest of the crate needs.
+#[allow(clippy::manual_is_multiple_of)]
+#[inline]
+fn pad(buf: &mut Vec<u8>) {
+    while buf.len() % 4 != 0 {
+        buf.push(0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+
+    #[test]
+    fn test_data_offsets() {
+        let snd = make_test_sounding();
+        let rows: Vec<DataRow> = snd.bottom_up().collect();
+        let file = NcFile::from_sounding(&snd, &rows);
+
+        let header_size = file.encode_header(0).len();
+        let bytes = file.to_bytes();
+
+        let data_size: usize = file.vars.iter().map(|var| var.data.encoded_size()).sum();
+        assert_eq!(bytes.len(), header_size + data_size);
+
+        // The pressure data is the first profile variable, after five scalars.
+        let scalars: usize = file.vars[..5]
+            .iter()
+            .map(|var| var.data.encoded_size())
+            .sum();
+        let start = header_size + scalars;
+        let mut first_p = [0u8; 8];
+        first_p.copy_from_slice(&bytes[start..start + 8]);
+        assert_eq!(f64::from_be_bytes(first_p), 1005.0);
+    }
+
+    /// The parts of a netCDF classic header needed to check the layout.
+    struct Header {
+        dims: Vec<(String, u32)>,
+        /// Name, dimension ids, type, size, and offset of each variable.
+        vars: Vec<(String, Vec<u32>, u32, u32, u32)>,
+    }
+
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        fn u32(&mut self) -> u32 {
+            let mut val = [0u8; 4];
+            val.copy_from_slice(&self.bytes[self.pos..self.pos + 4]);
+            self.pos += 4;
+            u32::from_be_bytes(val)
+        }
+
+        fn skip_padded(&mut self, len: usize) {
+            self.pos += len.div_ceil(4) * 4;
+        }
+
+        fn name(&mut self) -> String {
+            let len = self.u32() as usize;
+            let name = String::from_utf8(self.bytes[self.pos..self.pos + len].to_vec()).unwrap();
+            self.skip_padded(len);
+            name
+        }
+
+        fn skip_attrs(&mut self) {
+            let tag = self.u32();
+            let n = self.u32();
+            assert!(tag == NC_ATTRIBUTE || (tag == 0 && n == 0));
+            for _ in 0..n {
+                self.name();
+                let nc_type = self.u32();
+                let len = self.u32() as usize;
+                let size = match nc_type {
+                    NC_CHAR => 1,
+                    NC_INT => 4,
+                    NC_DOUBLE => 8,
+                    _ => panic!("unexpected attribute type {}", nc_type),
+                };
+                self.skip_padded(len * size);
+            }
+        }
+    }
+
+    fn parse_header(bytes: &[u8]) -> Header {
+        assert_eq!(&bytes[..4], b"CDF\x01");
+        let mut rdr = Reader { bytes, pos: 4 };
+        assert_eq!(rdr.u32(), 0); // numrecs
+
+        assert_eq!(rdr.u32(), NC_DIMENSION);
+        let n_dims = rdr.u32();
+        let dims = (0..n_dims).map(|_| (rdr.name(), rdr.u32())).collect();
+
+        rdr.skip_attrs();
+
+        assert_eq!(rdr.u32(), NC_VARIABLE);
+        let n_vars = rdr.u32();
+        let vars = (0..n_vars)
+            .map(|_| {
+                let name = rdr.name();
+                let n = rdr.u32();
+                let dim_ids = (0..n).map(|_| rdr.u32()).collect();
+                rdr.skip_attrs();
+                (name, dim_ids, rdr.u32(), rdr.u32(), rdr.u32())
+            })
+            .collect();
+
+        Header { dims, vars }
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let snd = make_test_sounding();
+        let mut bytes: Vec<u8> = vec![];
+        write_netcdf(&snd, &mut bytes).unwrap();
+
+        let header = parse_header(&bytes);
+        assert_eq!(header.dims, vec![("level".to_owned(), 5)]);
+
+        let mut expected_offset = None;
+        for (name, dim_ids, nc_type, vsize, begin) in &header.vars {
+            assert_eq!(*nc_type, if name == "profile" { NC_INT } else { NC_DOUBLE });
+
+            let len: u32 = dim_ids
+                .iter()
+                .map(|&id| header.dims[id as usize].1)
+                .product();
+            let size = if *nc_type == NC_INT { 4 } else { 8 };
+            assert_eq!(*vsize, (len * size).div_ceil(4) * 4);
+
+            // The variables are laid out back to back after the header.
+            if let Some(offset) = expected_offset {
+                assert_eq!(*begin, offset);
+            }
+            expected_offset = Some(begin + vsize);
+        }
+        assert_eq!(expected_offset, Some(bytes.len() as u32));
+
+        let (_, _, _, _, begin) = header
+            .vars
+            .iter()
+            .find(|var| var.0 == "temperature")
+            .unwrap();
+        let temperatures: Vec<f64> = bytes[*begin as usize..]
+            .chunks(8)
+            .take(5)
+            .map(|chunk| {
+                let mut val = [0u8; 8];
+                val.copy_from_slice(chunk);
+                f64::from_be_bytes(val)
+            })
+            .collect();
+        assert_eq!(temperatures, vec![21.0, 20.0, 18.0, 10.0, 2.0]);
+    }
+
+    #[test]
+    fn test_profile_coordinates() {
+        let snd = make_test_sounding();
+        let rows: Vec<DataRow> = snd.bottom_up().collect();
+        let file = NcFile::from_sounding(&snd, &rows);
+
+        let attr = |var: &NcVar, name: &str| {
+            var.attrs
+                .iter()
+                .find(|attr| attr.name == name)
+                .map(|attr| match attr.values {
+                    NcValues::Text(ref text) => text.clone(),
+                    _ => panic!("{} is not text", name),
+                })
+        };
+
+        for var in file.vars.iter().filter(|var| !var.dims.is_empty()) {
+            if var.name == "pressure" {
+                assert_eq!(attr(var, "axis").unwrap(), "Z");
+                assert_eq!(attr(var, "positive").unwrap(), "down");
+            } else {
+                assert_eq!(attr(var, "coordinates").unwrap(), "time lat lon pressure");
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_pressure_levels() {
+        let snd = make_test_sounding()
+            .with_station_pressure(optional::none::<metfor::HectoPascal>())
+            .with_pressure_profile(vec![optional::none(); 4]);
+
+        let mut bytes: Vec<u8> = vec![];
+        assert!(matches!(
+            write_netcdf(&snd, &mut bytes),
+            Err(SoundingError::NoPressureLevels)
+        ));
+        assert!(bytes.is_empty());
+    }
+}
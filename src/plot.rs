@@ -0,0 +1,10 @@
+//! Rendering soundings to SVG, enabled with the `plot` feature.
+//!
+//! The SVG is generated as plain text, there are no drawing dependencies.
+
+pub use self::hodograph::Hodograph;
+pub use self::skew_t::SkewT;
+
+mod hodograph;
+mod skew_t;
+mod svg;
@@ -0,0 +1,335 @@
+//! Hodographs.
+
+use std::io::Write;
+
+use metfor::{Knots, Quantity, WindSpdDir, WindUV};
+
+use super::svg::{Style, SvgDoc};
+use crate::error::Result;
+use crate::sounding::Sounding;
+
+const MARGIN: f64 = 20.0;
+
+/// Height above ground level bands in meters, and the color used for each.
+const BANDS: [(f64, f64, &str); 4] = [
+    (0.0, 1000.0, "magenta"),
+    (1000.0, 3000.0, "red"),
+    (3000.0, 6000.0, "green"),
+    (6000.0, 9000.0, "#cccc00"),
+];
+
+const RING: Style = Style::new("#999999", 0.5);
+const AXIS: Style = Style::new("#999999", 1.0);
+const STORM_MOTION: Style = Style::new("black", 1.5);
+
+/// Renders the wind profile of a `Sounding` as a hodograph in SVG.
+///
+/// Speed rings are labeled in knots. The trace is colored by height above ground level, with
+/// separate colors for the 0-1, 1-3, 3-6, and 6-9 km layers, so the sounding must have a height
+/// profile. Anything above 9 km is not plotted.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Knots, Meters, WindSpdDir};
+/// use optional::some;
+/// use sounding_base::Hodograph;
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let wind = |direction, speed| some(WindSpdDir { speed: Knots(speed), direction });
+///
+/// let snd = make_test_sounding()
+///     .with_height_profile(vec![
+///         some(Meters(100.0)),
+///         some(Meters(750.0)),
+///         some(Meters(1500.0)),
+///         some(Meters(3000.0)),
+///     ])
+///     .with_wind_profile(vec![
+///         wind(160.0, 15.0),
+///         wind(190.0, 30.0),
+///         wind(220.0, 35.0),
+///         wind(250.0, 45.0),
+///     ])
+///     .with_sfc_wind(wind(140.0, 10.0));
+///
+/// let svg = Hodograph::new()
+///     .with_max_speed(Knots(60.0))
+///     .with_size(400)
+///     .with_storm_motion(WindSpdDir {
+///         speed: Knots(25.0),
+///         direction: 240.0,
+///     })
+///     .to_svg(&snd);
+///
+/// assert!(svg.starts_with("<svg"));
+/// assert!(svg.contains(r#"stroke="magenta""#));
+/// assert!(svg.contains(r#"stroke="red""#));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Hodograph {
+    max_speed: Knots,
+    ring_spacing: Knots,
+    size: u32,
+    storm_motions: Vec<WindSpdDir<Knots>>,
+}
+
+impl Default for Hodograph {
+    fn default() -> Self {
+        Hodograph {
+            max_speed: Knots(80.0),
+            ring_spacing: Knots(10.0),
+            size: 400,
+            storm_motions: vec![],
+        }
+    }
+}
+
+impl Hodograph {
+    /// Create a 400 by 400 pixel hodograph out to 80 knots with rings every 10 knots.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder method to set the wind speed at the edge of the plot.
+    #[inline]
+    pub fn with_max_speed<S>(mut self, max_speed: S) -> Self
+    where
+        S: metfor::Speed,
+        Knots: From<S>,
+    {
+        self.max_speed = Knots::from(max_speed);
+        self
+    }
+
+    /// Builder method to set the spacing between the speed rings.
+    #[inline]
+    pub fn with_ring_spacing<S>(mut self, spacing: S) -> Self
+    where
+        S: metfor::Speed,
+        Knots: From<S>,
+    {
+        self.ring_spacing = Knots::from(spacing);
+        self
+    }
+
+    /// Builder method to set the width and height of the image in pixels.
+    ///
+    /// # Panics
+    ///
+    /// If the size is 40 or less, which leaves no room inside the margins.
+    #[inline]
+    pub fn with_size(mut self, size: u32) -> Self {
+        assert!(
+            f64::from(size) > 2.0 * MARGIN,
+            "hodograph size {} is too small for the margins",
+            size
+        );
+        self.size = size;
+        self
+    }
+
+    /// Builder method to add a storm motion marker. This may be called more than once, e.g. to
+    /// mark both the right and left mover.
+    #[inline]
+    pub fn with_storm_motion(mut self, motion: WindSpdDir<Knots>) -> Self {
+        self.storm_motions.push(motion);
+        self
+    }
+
+    /// Write the hodograph to `dest` as SVG.
+    pub fn write_svg<W: Write>(&self, snd: &Sounding, mut dest: W) -> Result<()> {
+        dest.write_all(self.to_svg(snd).as_bytes())?;
+        Ok(())
+    }
+
+    /// Render the hodograph as an SVG document.
+    pub fn to_svg(&self, snd: &Sounding) -> String {
+        let mut doc = SvgDoc::new(self.size, self.size);
+
+        self.draw_background(&mut doc);
+        self.draw_trace(&mut doc, snd);
+        for &motion in &self.storm_motions {
+            let center = self.pixel_coords(WindUV::from(motion));
+            doc.circle(center, 4.0, &STORM_MOTION);
+            doc.line(
+                (center.0 - 6.0, center.1),
+                (center.0 + 6.0, center.1),
+                &STORM_MOTION,
+            );
+            doc.line(
+                (center.0, center.1 - 6.0),
+                (center.0, center.1 + 6.0),
+                &STORM_MOTION,
+            );
+        }
+
+        doc.finish()
+    }
+
+    #[inline]
+    fn center(&self) -> f64 {
+        f64::from(self.size) / 2.0
+    }
+
+    #[inline]
+    fn pixels_per_knot(&self) -> f64 {
+        (self.center() - MARGIN) / self.max_speed.unpack()
+    }
+
+    fn pixel_coords(&self, wind: WindUV<Knots>) -> (f64, f64) {
+        let scale = self.pixels_per_knot();
+        (
+            self.center() + wind.u.unpack() * scale,
+            self.center() - wind.v.unpack() * scale,
+        )
+    }
+
+    fn draw_background(&self, doc: &mut SvgDoc) {
+        let c = self.center();
+        let r = c - MARGIN;
+
+        doc.line((c - r, c), (c + r, c), &AXIS);
+        doc.line((c, c - r), (c, c + r), &AXIS);
+
+        let spacing = self.ring_spacing.unpack();
+        if spacing <= 0.0 {
+            return;
+        }
+
+        let mut speed = spacing;
+        while speed <= self.max_speed.unpack() {
+            let radius = speed * self.pixels_per_knot();
+            doc.circle((c, c), radius, &RING);
+            doc.text(
+                (c + radius * 0.707 + 2.0, c + radius * 0.707 + 10.0),
+                "start",
+                &format!("{:.0}", speed),
+            );
+            speed += spacing;
+        }
+    }
+
+    fn draw_trace(&self, doc: &mut SvgDoc, snd: &Sounding) {
+        let points: Vec<(f64, f64, f64)> = snd
+            .height_profile()
+            .iter()
+            .zip(snd.wind_profile())
+            .filter_map(|(h, wind)| match (h.into_option(), wind.into_option()) {
+                (Some(h), Some(wind)) => {
+                    let uv: WindUV<Knots> = WindUV::from(wind);
+                    Some((h.unpack(), uv.u.unpack(), uv.v.unpack()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let ground = match snd
+            .station_info()
+            .elevation()
+            .map(|elev| elev.unpack())
+            .or_else(|| points.first().map(|&(h, _, _)| h))
+        {
+            Some(ground) => ground,
+            None => return,
+        };
+
+        for &(bottom, top, color) in BANDS.iter() {
+            let band: Vec<_> = layer(&points, ground + bottom, ground + top)
+                .into_iter()
+                .map(|(u, v)| {
+                    self.pixel_coords(WindUV {
+                        u: Knots(u),
+                        v: Knots(v),
+                    })
+                })
+                .collect();
+            doc.polyline(&band, &Style::new(color, 2.5));
+        }
+    }
+}
+
+/// Get the (u, v) points between the heights `bottom` and `top`, interpolating at the ends so
+/// adjacent layers join up.
+fn layer(points: &[(f64, f64, f64)], bottom: f64, top: f64) -> Vec<(f64, f64)> {
+    let interp = |(h0, u0, v0): (f64, f64, f64), (h1, u1, v1): (f64, f64, f64), h: f64| {
+        let w = (h - h0) / (h1 - h0);
+        (u0 + w * (u1 - u0), v0 + w * (v1 - v0))
+    };
+
+    let mut result = vec![];
+    for (i, &(h, u, v)) in points.iter().enumerate() {
+        if i > 0 {
+            let prev = points[i - 1];
+            if prev.0 < bottom && h > bottom {
+                result.push(interp(prev, (h, u, v), bottom));
+            }
+            if prev.0 < top && h > top {
+                result.push(interp(prev, (h, u, v), top));
+            }
+        }
+
+        if h >= bottom && h <= top {
+            result.push((u, v));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+
+    #[test]
+    fn test_layer_endpoints() {
+        let points = [(0.0, 0.0, 0.0), (500.0, 10.0, 0.0), (1500.0, 20.0, 10.0)];
+
+        // The top of one layer is the bottom of the next.
+        assert_eq!(
+            layer(&points, 0.0, 1000.0),
+            vec![(0.0, 0.0), (10.0, 0.0), (15.0, 5.0)]
+        );
+        assert_eq!(
+            layer(&points, 1000.0, 3000.0),
+            vec![(15.0, 5.0), (20.0, 10.0)]
+        );
+        // Both ends inside one segment.
+        assert_eq!(layer(&points, 100.0, 400.0), vec![(2.0, 0.0), (8.0, 0.0)]);
+        assert!(layer(&points, 2000.0, 3000.0).is_empty());
+    }
+
+    #[test]
+    fn test_rings_and_range() {
+        let hodo = Hodograph::new()
+            .with_max_speed(Knots(50.0))
+            .with_ring_spacing(Knots(20.0))
+            .with_size(240);
+
+        // The max speed is at the edge of the margin.
+        let (x, y) = hodo.pixel_coords(WindUV {
+            u: Knots(50.0),
+            v: Knots(-25.0),
+        });
+        assert!((x - 220.0).abs() < 1.0e-9);
+        assert!((y - 170.0).abs() < 1.0e-9);
+
+        // Rings at 20 and 40 knots, labeled in knots.
+        let svg = hodo.to_svg(&make_test_sounding());
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert!(svg.contains(">20</text>") && svg.contains(">40</text>"));
+
+        let svg = hodo
+            .with_ring_spacing(Knots(0.0))
+            .to_svg(&make_test_sounding());
+        assert_eq!(svg.matches("<circle").count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_size_smaller_than_margins() {
+        let _ = Hodograph::new().with_size(40);
+    }
+}
@@ -0,0 +1,507 @@
+//! Skew-T log-P diagrams.
+
+use std::io::Write;
+
+use metfor::{Celsius, HectoPascal, Kelvin, Knots, Quantity, WindSpdDir};
+
+use super::svg::{Style, SvgDoc};
+use crate::diagram::{DiagramTransform, SkewTTransform};
+use crate::error::Result;
+use crate::sounding::Sounding;
+
+const MARGIN_LEFT: f64 = 50.0;
+const MARGIN_RIGHT: f64 = 60.0;
+const MARGIN_TOP: f64 = 30.0;
+const MARGIN_BOTTOM: f64 = 30.0;
+
+/// How far the isotherms are skewed to the right, in plot widths per plot height.
+const SKEW: f64 = 1.0;
+
+const ISOBARS: [f64; 13] = [
+    1050.0, 1000.0, 925.0, 850.0, 700.0, 600.0, 500.0, 400.0, 300.0, 250.0, 200.0, 150.0, 100.0,
+];
+const MIXING_RATIOS_G_PER_KG: [f64; 10] = [0.4, 1.0, 2.0, 3.0, 5.0, 8.0, 12.0, 16.0, 20.0, 28.0];
+
+const BORDER: Style = Style::new("black", 1.0);
+const ISOBAR: Style = Style::new("#999999", 0.5);
+const ISOTHERM: Style = Style::new("#999999", 0.5);
+const FREEZING: Style = Style::new("#3366cc", 1.0);
+const DRY_ADIABAT: Style = Style::new("#cc9966", 0.5);
+const MOIST_ADIABAT: Style = Style::dashed("#66aa66", 0.5, "4,2");
+const MIXING_RATIO: Style = Style::dashed("#6699cc", 0.5, "2,3");
+const TEMPERATURE: Style = Style::new("red", 2.0);
+const DEW_POINT: Style = Style::new("green", 2.0);
+const WET_BULB: Style = Style::new("blue", 1.5);
+const BARB: Style = Style::new("black", 1.0);
+
+/// Renders a `Sounding` on a skew-T log-P diagram as SVG.
+///
+/// The background has isobars, isotherms, dry and moist adiabats, and mixing ratio lines. The
+/// temperature, dew point, and wet bulb profiles are plotted in red, green, and blue, and wind
+/// barbs (in knots) are drawn along the right side.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal};
+/// use sounding_base::SkewT;
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding();
+///
+/// let svg = SkewT::new()
+///     .with_pressure_range(HectoPascal(300.0), HectoPascal(1050.0))
+///     .with_temperature_range(Celsius(-30.0), Celsius(40.0))
+///     .with_size(600, 600)
+///     .to_svg(&snd);
+///
+/// assert!(svg.starts_with("<svg"));
+/// assert!(svg.contains(r#"stroke="red""#));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct SkewT {
+    p_top: HectoPascal,
+    p_bottom: HectoPascal,
+    t_left: Celsius,
+    t_right: Celsius,
+    width: u32,
+    height: u32,
+}
+
+impl Default for SkewT {
+    fn default() -> Self {
+        SkewT {
+            p_top: HectoPascal(100.0),
+            p_bottom: HectoPascal(1050.0),
+            t_left: Celsius(-40.0),
+            t_right: Celsius(50.0),
+            width: 800,
+            height: 800,
+        }
+    }
+}
+
+impl SkewT {
+    /// Create a diagram from 1050 to 100 hPa, -40 to 50 C at the bottom, and 800 by 800 pixels.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder method to set the pressure at the top and bottom of the diagram.
+    #[inline]
+    pub fn with_pressure_range<P>(mut self, top: P, bottom: P) -> Self
+    where
+        P: metfor::Pressure,
+        HectoPascal: From<P>,
+    {
+        self.p_top = HectoPascal::from(top);
+        self.p_bottom = HectoPascal::from(bottom);
+        self
+    }
+
+    /// Builder method to set the temperature at the left and right edges of the bottom of the
+    /// diagram.
+    #[inline]
+    pub fn with_temperature_range<T>(mut self, left: T, right: T) -> Self
+    where
+        T: metfor::Temperature,
+        Celsius: From<T>,
+    {
+        self.t_left = Celsius::from(left);
+        self.t_right = Celsius::from(right);
+        self
+    }
+
+    /// Builder method to set the size of the image in pixels.
+    ///
+    /// # Panics
+    ///
+    /// If the size leaves no room for the plot inside the margins for the labels and wind barbs,
+    /// i.e. a width of 110 or less or a height of 60 or less.
+    #[inline]
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        assert!(
+            f64::from(width) > MARGIN_LEFT + MARGIN_RIGHT
+                && f64::from(height) > MARGIN_TOP + MARGIN_BOTTOM,
+            "skew-T size {}x{} is too small for the margins",
+            width,
+            height
+        );
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Write the diagram to `dest` as SVG.
+    pub fn write_svg<W: Write>(&self, snd: &Sounding, mut dest: W) -> Result<()> {
+        dest.write_all(self.to_svg(snd).as_bytes())?;
+        Ok(())
+    }
+
+    /// Render the diagram as an SVG document.
+    pub fn to_svg(&self, snd: &Sounding) -> String {
+        let mut doc = SvgDoc::new(self.width, self.height);
+        let (x0, y0) = (MARGIN_LEFT, MARGIN_TOP);
+        let (w, h) = (self.plot_width(), self.plot_height());
+
+        doc.clip_rect("skew-t-plot-area", x0, y0, w, h);
+        doc.begin_group(Some("skew-t-plot-area"));
+        self.draw_background(&mut doc);
+        self.draw_profiles(&mut doc, snd);
+        doc.end_group();
+
+        doc.polyline(
+            &[
+                (x0, y0),
+                (x0 + w, y0),
+                (x0 + w, y0 + h),
+                (x0, y0 + h),
+                (x0, y0),
+            ],
+            &BORDER,
+        );
+        self.draw_labels(&mut doc, snd);
+        self.draw_wind_barbs(&mut doc, snd);
+
+        doc.finish()
+    }
+
+    /*----------------------------------------------------------------------------------------------
+                                         Coordinates
+    ----------------------------------------------------------------------------------------------*/
+    #[inline]
+    fn plot_width(&self) -> f64 {
+        f64::from(self.width) - MARGIN_LEFT - MARGIN_RIGHT
+    }
+
+    #[inline]
+    fn plot_height(&self) -> f64 {
+        f64::from(self.height) - MARGIN_TOP - MARGIN_BOTTOM
+    }
+
+    #[inline]
+    fn transform(&self) -> SkewTTransform {
+        SkewTTransform::new(self.p_top, self.p_bottom, self.t_left, self.t_right).with_skew(SKEW)
+    }
+
+    /// Convert a pressure and temperature to pixel coordinates.
+    fn pixel_coords(&self, p: HectoPascal, t: Celsius) -> (f64, f64) {
+        let (x, y) = self.transform().pt_to_xy(p, t);
+
+        (
+            MARGIN_LEFT + x * self.plot_width(),
+            MARGIN_TOP + (1.0 - y) * self.plot_height(),
+        )
+    }
+
+    /// Pressures evenly spaced in log(p) from the bottom to `top`.
+    fn pressure_steps(&self, top: HectoPascal) -> impl Iterator<Item = HectoPascal> {
+        const STEPS: usize = 40;
+        let (bottom, top) = (self.p_bottom.unpack().ln(), top.unpack().ln());
+
+        (0..=STEPS)
+            .map(move |i| HectoPascal((bottom + (top - bottom) * i as f64 / STEPS as f64).exp()))
+    }
+
+    /*----------------------------------------------------------------------------------------------
+                                         Background
+    ----------------------------------------------------------------------------------------------*/
+    fn draw_background(&self, doc: &mut SvgDoc) {
+        for &p in ISOBARS.iter() {
+            let p = HectoPascal(p);
+            let (_, y) = self.pixel_coords(p, self.t_left);
+            doc.line(
+                (MARGIN_LEFT, y),
+                (MARGIN_LEFT + self.plot_width(), y),
+                &ISOBAR,
+            );
+        }
+
+        // Cover the whole skewed range of temperatures visible in the plot area.
+        let t_min = (self.t_left.unpack() - SKEW * (self.t_right - self.t_left).unpack()).floor();
+        let mut t = (t_min / 10.0).floor() * 10.0;
+        while t <= self.t_right.unpack() {
+            let style = if t == 0.0 { &FREEZING } else { &ISOTHERM };
+            doc.line(
+                self.pixel_coords(self.p_bottom, Celsius(t)),
+                self.pixel_coords(self.p_top, Celsius(t)),
+                style,
+            );
+            t += 10.0;
+        }
+
+        let mut theta = -40.0;
+        while theta <= 220.0 {
+            let theta_k = Kelvin::from(Celsius(theta));
+            let points: Vec<_> = self
+                .pressure_steps(self.p_top)
+                .map(|p| {
+                    let t = metfor::temperature_from_theta(theta_k, p);
+                    self.pixel_coords(p, Celsius::from(t))
+                })
+                .collect();
+            doc.polyline(&points, &DRY_ADIABAT);
+            theta += 10.0;
+        }
+
+        let moist_top = HectoPascal(self.p_top.unpack().max(200.0));
+        let mut t_start = -20.0;
+        while t_start <= 40.0 {
+            let start = Celsius(t_start);
+            if let Some(theta_e) = metfor::theta_e(start, start, HectoPascal(1000.0)) {
+                let points: Vec<_> = self
+                    .pressure_steps(moist_top)
+                    .filter_map(|p| {
+                        metfor::temperature_from_theta_e_saturated_and_pressure(p, theta_e)
+                            .map(|t| self.pixel_coords(p, t))
+                    })
+                    .collect();
+                doc.polyline(&points, &MOIST_ADIABAT);
+            }
+            t_start += 4.0;
+        }
+
+        let mw_top = HectoPascal(self.p_top.unpack().max(400.0));
+        for &mw in MIXING_RATIOS_G_PER_KG.iter() {
+            let points: Vec<_> = self
+                .pressure_steps(mw_top)
+                .filter_map(|p| {
+                    metfor::dew_point_from_p_and_mw(p, mw / 1000.0).map(|t| self.pixel_coords(p, t))
+                })
+                .collect();
+            doc.polyline(&points, &MIXING_RATIO);
+        }
+    }
+
+    fn draw_labels(&self, doc: &mut SvgDoc, snd: &Sounding) {
+        for &p in ISOBARS.iter() {
+            if p > self.p_bottom.unpack() || p < self.p_top.unpack() {
+                continue;
+            }
+            let (_, y) = self.pixel_coords(HectoPascal(p), self.t_left);
+            doc.text((MARGIN_LEFT - 4.0, y + 4.0), "end", &format!("{:.0}", p));
+        }
+
+        let bottom = MARGIN_TOP + self.plot_height();
+        let mut t = (self.t_left.unpack() / 10.0).ceil() * 10.0;
+        while t <= self.t_right.unpack() {
+            let (x, _) = self.pixel_coords(self.p_bottom, Celsius(t));
+            doc.text((x, bottom + 15.0), "middle", &format!("{:.0}", t));
+            t += 10.0;
+        }
+
+        let mut title = String::new();
+        if let Some(num) = snd.station_info().station_num().into_option() {
+            title.push_str(&format!("{} ", num));
+        }
+        if let Some(vt) = snd.valid_time() {
+            title.push_str(&vt.format("%Y-%m-%d %H:%MZ").to_string());
+        }
+        if let Some(lt) = snd.lead_time().into_option() {
+            title.push_str(&format!(" F{:03}", lt));
+        }
+        doc.text((MARGIN_LEFT, MARGIN_TOP - 10.0), "start", title.trim());
+    }
+
+    /*----------------------------------------------------------------------------------------------
+                                         Sounding data
+    ----------------------------------------------------------------------------------------------*/
+    fn draw_profiles(&self, doc: &mut SvgDoc, snd: &Sounding) {
+        let profiles = [
+            (snd.temperature_profile(), &TEMPERATURE),
+            (snd.wet_bulb_profile(), &WET_BULB),
+            (snd.dew_point_profile(), &DEW_POINT),
+        ];
+
+        for (profile, style) in profiles.iter() {
+            let points: Vec<_> = snd
+                .pressure_profile()
+                .iter()
+                .zip(profile.iter())
+                .filter_map(|(p, t)| match (p.into_option(), t.into_option()) {
+                    (Some(p), Some(t)) => Some(self.pixel_coords(p, t)),
+                    _ => None,
+                })
+                .collect();
+            doc.polyline(&points, style);
+        }
+    }
+
+    fn draw_wind_barbs(&self, doc: &mut SvgDoc, snd: &Sounding) {
+        const MIN_SPACING: f64 = 20.0;
+
+        let x = MARGIN_LEFT + self.plot_width() + MARGIN_RIGHT / 2.0;
+        let mut last_y = f64::MAX;
+
+        for (p, wind) in snd.pressure_profile().iter().zip(snd.wind_profile()) {
+            let (p, wind) = match (p.into_option(), wind.into_option()) {
+                (Some(p), Some(wind)) => (p, wind),
+                _ => continue,
+            };
+            if p > self.p_bottom || p < self.p_top {
+                continue;
+            }
+
+            let (_, y) = self.pixel_coords(p, self.t_left);
+            if last_y - y < MIN_SPACING {
+                continue;
+            }
+            last_y = y;
+
+            draw_barb(doc, (x, y), wind);
+        }
+    }
+}
+
+/// Draw a wind barb with the staff pointing into the wind and the feathers on the side with lower
+/// pressure in the northern hemisphere.
+fn draw_barb(doc: &mut SvgDoc, base: (f64, f64), wind: WindSpdDir<Knots>) {
+    const STAFF: f64 = 25.0;
+    const FEATHER: f64 = 10.0;
+    const SPACING: f64 = 4.0;
+
+    let speed = (wind.speed.unpack() / 5.0).round() as i32 * 5;
+    if speed < 5 {
+        doc.circle(base, 3.0, &BARB);
+        return;
+    }
+
+    let dir = wind.direction.to_radians();
+    // Unit vector along the staff, towards where the wind comes from (y is down in SVG).
+    let (ux, uy) = (dir.sin(), -dir.cos());
+    // Unit vector along the feathers.
+    let (fx, fy) = (-uy, ux);
+
+    let along = |dist: f64| (base.0 + ux * dist, base.1 + uy * dist);
+    let feather_tip = |(x, y): (f64, f64), len: f64| {
+        (x + fx * len + ux * len * 0.3, y + fy * len + uy * len * 0.3)
+    };
+
+    doc.line(base, along(STAFF), &BARB);
+
+    let mut remaining = speed;
+    let mut pos = STAFF;
+    while remaining >= 50 {
+        let start = along(pos);
+        let end = along(pos - SPACING * 1.5);
+        let tip = (start.0 + fx * FEATHER, start.1 + fy * FEATHER);
+        doc.polygon(&[start, tip, end], "black");
+        pos -= SPACING * 2.0;
+        remaining -= 50;
+    }
+    while remaining >= 10 {
+        let start = along(pos);
+        doc.line(start, feather_tip(start, FEATHER), &BARB);
+        pos -= SPACING;
+        remaining -= 10;
+    }
+    if remaining >= 5 {
+        // Don't put a lone half barb right at the end of the staff.
+        if pos == STAFF {
+            pos -= SPACING;
+        }
+        let start = along(pos);
+        doc.line(start, feather_tip(start, FEATHER / 2.0), &BARB);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+    use optional::some;
+
+    fn skew_t() -> SkewT {
+        SkewT::new()
+            .with_pressure_range(HectoPascal(800.0), HectoPascal(950.0))
+            .with_temperature_range(Celsius(-10.0), Celsius(30.0))
+            .with_size(600, 400)
+    }
+
+    #[test]
+    fn test_pixel_coords() {
+        let skew_t = skew_t();
+        let close = |(x0, y0): (f64, f64), (x1, y1): (f64, f64)| {
+            (x0 - x1).abs() < 1.0e-9 && (y0 - y1).abs() < 1.0e-9
+        };
+
+        // The bottom corners are the ends of the temperature range.
+        assert!(close(
+            skew_t.pixel_coords(HectoPascal(950.0), Celsius(-10.0)),
+            (50.0, 370.0)
+        ));
+        assert!(close(
+            skew_t.pixel_coords(HectoPascal(950.0), Celsius(30.0)),
+            (540.0, 370.0)
+        ));
+
+        // Isotherms lean to the right, one plot width over the full height.
+        let (x, y) = skew_t.pixel_coords(HectoPascal(800.0), Celsius(-10.0));
+        assert!((x - 540.0).abs() < 1.0e-9);
+        assert!((y - 30.0).abs() < 1.0e-9);
+
+        // Pressure is logarithmic, so 875 hPa is below the middle of the plot.
+        let (_, y) = skew_t.pixel_coords(HectoPascal(875.0), Celsius(0.0));
+        assert!(y > 200.0);
+    }
+
+    #[test]
+    fn test_range_clipping() {
+        let calm = some(WindSpdDir {
+            speed: Knots(0.0),
+            direction: 0.0,
+        });
+        let snd = make_test_sounding().with_wind_profile(vec![calm; 4]);
+
+        let svg = skew_t().to_svg(&snd);
+
+        // The background and profiles are clipped to the plot area.
+        assert!(svg.contains(
+            r#"<clipPath id="skew-t-plot-area"><rect x="50.0" y="30.0" width="490.0" height="340.0"/>"#
+        ));
+        let clip_start = svg
+            .find(r#"<g clip-path="url(#skew-t-plot-area)">"#)
+            .unwrap();
+        let clip_end = svg[clip_start..].find("</g>").unwrap() + clip_start;
+        let red = svg.find(r#"stroke="red""#).unwrap();
+        assert!(red > clip_start && red < clip_end);
+
+        // Only the winds at 925 and 850 hPa are in the pressure range, calm winds are circles.
+        assert_eq!(svg.matches(r#"r="3.0""#).count(), 2);
+    }
+
+    #[test]
+    fn test_barb_speed_rounding() {
+        // Count the circles, lines, and pennants of a barb.
+        let barb = |speed: f64| {
+            let mut doc = SvgDoc::new(100, 100);
+            let wind = WindSpdDir {
+                speed: Knots(speed),
+                direction: 270.0,
+            };
+            draw_barb(&mut doc, (50.0, 50.0), wind);
+            let svg = doc.finish();
+            (
+                svg.matches("<circle").count(),
+                svg.matches("<line").count(),
+                svg.matches("<polygon").count(),
+            )
+        };
+
+        assert_eq!(barb(2.4), (1, 0, 0)); // Calm
+        assert_eq!(barb(2.5), (0, 2, 0)); // Staff and a half barb
+        assert_eq!(barb(7.4), (0, 2, 0));
+        assert_eq!(barb(7.5), (0, 2, 0)); // Staff and a full barb
+        assert_eq!(barb(47.4), (0, 6, 0)); // Four full and a half barb
+        assert_eq!(barb(47.5), (0, 1, 1)); // Rounds up to a pennant
+        assert_eq!(barb(52.4), (0, 1, 1)); // A pennant
+        assert_eq!(barb(66.0), (0, 3, 1)); // A pennant, a full and a half barb
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_size_smaller_than_margins() {
+        let _ = SkewT::new().with_size(100, 600);
+    }
+}
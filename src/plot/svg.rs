@@ -0,0 +1,175 @@
+//! Small helpers for writing SVG elements as text.
+
+use std::fmt::Write;
+
+/// An SVG document under construction.
+pub(crate) struct SvgDoc {
+    buf: String,
+}
+
+impl SvgDoc {
+    pub(crate) fn new(width: u32, height: u32) -> Self {
+        let mut buf = String::new();
+        let _ = writeln!(
+            buf,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">"#,
+            w = width,
+            h = height
+        );
+        let _ = writeln!(
+            buf,
+            r#"<rect x="0" y="0" width="{}" height="{}" fill="white"/>"#,
+            width, height
+        );
+
+        SvgDoc { buf }
+    }
+
+    /// Define a rectangular clip path that can be referenced with `begin_group`.
+    pub(crate) fn clip_rect(&mut self, id: &str, x: f64, y: f64, width: f64, height: f64) {
+        let _ = writeln!(
+            self.buf,
+            r#"<defs><clipPath id="{}"><rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}"/></clipPath></defs>"#,
+            id, x, y, width, height
+        );
+    }
+
+    /// Start a group of elements, optionally clipped to a clip path.
+    pub(crate) fn begin_group(&mut self, clip_id: Option<&str>) {
+        match clip_id {
+            Some(id) => {
+                let _ = writeln!(self.buf, r#"<g clip-path="url(#{})">"#, id);
+            }
+            None => self.buf.push_str("<g>\n"),
+        }
+    }
+
+    pub(crate) fn end_group(&mut self) {
+        self.buf.push_str("</g>\n");
+    }
+
+    pub(crate) fn line(&mut self, (x0, y0): (f64, f64), (x1, y1): (f64, f64), style: &Style) {
+        let _ = writeln!(
+            self.buf,
+            r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" {}/>"#,
+            x0, y0, x1, y1, style
+        );
+    }
+
+    pub(crate) fn polyline(&mut self, points: &[(f64, f64)], style: &Style) {
+        if points.len() < 2 {
+            return;
+        }
+
+        self.buf.push_str(r#"<polyline points=""#);
+        for (x, y) in points {
+            let _ = write!(self.buf, "{:.1},{:.1} ", x, y);
+        }
+        let _ = writeln!(self.buf, r#"" fill="none" {}/>"#, style);
+    }
+
+    pub(crate) fn polygon(&mut self, points: &[(f64, f64)], color: &str) {
+        self.buf.push_str(r#"<polygon points=""#);
+        for (x, y) in points {
+            let _ = write!(self.buf, "{:.1},{:.1} ", x, y);
+        }
+        let _ = writeln!(self.buf, r#"" fill="{}" stroke="{}"/>"#, color, color);
+    }
+
+    pub(crate) fn circle(&mut self, (x, y): (f64, f64), radius: f64, style: &Style) {
+        let _ = writeln!(
+            self.buf,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="{:.1}" fill="none" {}/>"#,
+            x, y, radius, style
+        );
+    }
+
+    pub(crate) fn text(&mut self, (x, y): (f64, f64), anchor: &str, txt: &str) {
+        let _ = writeln!(
+            self.buf,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="{}">{}</text>"#,
+            x,
+            y,
+            anchor,
+            escape(txt)
+        );
+    }
+
+    pub(crate) fn finish(mut self) -> String {
+        self.buf.push_str("</svg>\n");
+        self.buf
+    }
+}
+
+/// Stroke style for lines.
+pub(crate) struct Style {
+    pub(crate) color: &'static str,
+    pub(crate) width: f64,
+    pub(crate) dash: Option<&'static str>,
+}
+
+impl Style {
+    pub(crate) const fn new(color: &'static str, width: f64) -> Self {
+        Style {
+            color,
+            width,
+            dash: None,
+        }
+    }
+
+    pub(crate) const fn dashed(color: &'static str, width: f64, dash: &'static str) -> Self {
+        Style {
+            color,
+            width,
+            dash: Some(dash),
+        }
+    }
+}
+
+impl std::fmt::Display for Style {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            r#"stroke="{}" stroke-width="{}""#,
+            self.color, self.width
+        )?;
+        if let Some(dash) = self.dash {
+            write!(f, r#" stroke-dasharray="{}""#, dash)?;
+        }
+        Ok(())
+    }
+}
+
+fn escape(txt: &str) -> String {
+    txt.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_elements() {
+        let mut doc = SvgDoc::new(100, 50);
+        doc.polyline(&[(1.0, 2.0)], &Style::new("red", 1.0));
+        doc.polyline(
+            &[(1.0, 2.0), (3.25, 4.0)],
+            &Style::dashed("blue", 0.5, "4,2"),
+        );
+        doc.text((10.0, 20.0), "middle", "a < b & c");
+        let svg = doc.finish();
+
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50""#)
+        );
+        assert!(svg.ends_with("</svg>\n"));
+        // A single point isn't drawn.
+        assert!(!svg.contains(r#"stroke="red""#));
+        assert!(svg.contains(
+            r#"<polyline points="1.0,2.0 3.2,4.0 " fill="none" stroke="blue" stroke-width="0.5" stroke-dasharray="4,2"/>"#
+        ));
+        assert!(svg.contains(">a &lt; b &amp; c</text>"));
+    }
+}
@@ -0,0 +1,427 @@
+//! Automated quality control of sounding data.
+
+use metfor::{HectoPascal, Knots, Meters, Quantity};
+use optional::none;
+
+use crate::data_row::{DataRow, ProfileVariable};
+use crate::derived::relative_humidity_ice;
+use crate::hydrostatic::check_hydrostatic_heights;
+use crate::sounding::Sounding;
+
+/// The result of quality control checks on a value, ordered from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum QcFlag {
+    /// The value passed all the checks, or wasn't checked.
+    #[default]
+    Pass,
+    /// The value is unusual and may be wrong.
+    Suspect,
+    /// The value is wrong.
+    Fail,
+}
+
+impl QcFlag {
+    #[inline]
+    fn raise(&mut self, flag: QcFlag) {
+        *self = (*self).max(flag);
+    }
+}
+
+/// The quality control flags for the variables at one level of a sounding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct QcFlags {
+    /// Pressure
+    pub pressure: QcFlag,
+    /// Temperature
+    pub temperature: QcFlag,
+    /// Dew point
+    pub dew_point: QcFlag,
+    /// Wind speed and direction
+    pub wind: QcFlag,
+    /// Geopotential height
+    pub height: QcFlag,
+}
+
+impl QcFlags {
+    /// The flag for a variable. Variables derived from temperature and dew point get the worst of
+    /// their flags, and variables that aren't checked always pass.
+    pub fn get(&self, var: ProfileVariable) -> QcFlag {
+        use crate::data_row::ProfileVariable::*;
+
+        match var {
+            Pressure => self.pressure,
+            Temperature => self.temperature,
+            WetBulb | ThetaE => self.temperature.max(self.dew_point),
+            DewPoint => self.dew_point,
+            WindDirection | WindSpeed => self.wind,
+            Height => self.height,
+            Pvv | CloudFraction => QcFlag::Pass,
+        }
+    }
+
+    /// The worst flag of any variable.
+    pub fn worst(&self) -> QcFlag {
+        self.pressure
+            .max(self.temperature)
+            .max(self.dew_point)
+            .max(self.wind)
+            .max(self.height)
+    }
+
+    /// Remove the values from `row` with flags worse than `allowed`.
+    pub(crate) fn mask(&self, mut row: DataRow, allowed: QcFlag) -> DataRow {
+        if self.pressure > allowed {
+            row.pressure = none();
+        }
+        if self.temperature > allowed {
+            row.temperature = none();
+        }
+        if self.dew_point > allowed {
+            row.dew_point = none();
+        }
+        if self.temperature.max(self.dew_point) > allowed {
+            row.wet_bulb = none();
+            row.theta_e = none();
+        }
+        if self.wind > allowed {
+            row.wind = none();
+        }
+        if self.height > allowed {
+            row.height = none();
+        }
+
+        row
+    }
+}
+
+/// Automated quality control checks for soundings.
+///
+/// The checks, and the flags they set, are:
+///
+/// - gross limits on pressure, temperature, dew point, wind and height (fail),
+/// - a dew point higher than the temperature (fail),
+/// - a superadiabatic lapse rate above the surface layer (suspect),
+/// - a wind speed spike above the speeds of the levels on either side (suspect),
+/// - a height that isn't hydrostatically consistent with the level below (suspect),
+/// - humidity supersaturated with respect to ice below freezing (suspect).
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::Celsius;
+/// use optional::some;
+/// use sounding_base::{QcFlag, QualityControl};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding();
+/// let mut t: Vec<_> = snd.temperature_profile().iter().skip(1).cloned().collect();
+/// t[2] = some(Celsius(75.0)); // 850 hPa
+/// let snd = snd.with_temperature_profile(t);
+///
+/// let snd = QualityControl::new().apply(snd);
+/// assert_eq!(snd.qc_flags()[3].temperature, QcFlag::Fail);
+/// assert_eq!(snd.qc_flags()[2].temperature, QcFlag::Pass);
+///
+/// // Leave out the failed values.
+/// let temperatures: Vec<_> = snd
+///     .bottom_up_with_qc(QcFlag::Suspect)
+///     .map(|row| row.temperature)
+///     .collect();
+/// assert!(temperatures[3].is_none());
+/// assert!(temperatures[2].is_some());
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct QualityControl {
+    surface_layer: f64,
+    superadiabatic: f64,
+    wind_spike: f64,
+    hydrostatic: f64,
+    ice_supersaturation: f64,
+}
+
+impl Default for QualityControl {
+    fn default() -> Self {
+        QualityControl {
+            surface_layer: 50.0,
+            superadiabatic: 1.0,
+            wind_spike: 40.0,
+            hydrostatic: 50.0,
+            ice_supersaturation: 5.0,
+        }
+    }
+}
+
+impl QualityControl {
+    /// Create the checks with the default thresholds.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder method for the depth of the surface layer, where superadiabatic lapse rates are
+    /// allowed. The default is 50 hPa.
+    #[inline]
+    pub fn with_surface_layer_depth<P>(mut self, depth: P) -> Self
+    where
+        P: metfor::Pressure,
+        HectoPascal: From<P>,
+    {
+        self.surface_layer = HectoPascal::from(depth).unpack();
+        self
+    }
+
+    /// Builder method for how much the potential temperature may decrease from one level to the
+    /// next above the surface layer. The default is 1 K.
+    #[inline]
+    pub fn with_superadiabatic_tolerance<T>(mut self, tolerance: T) -> Self
+    where
+        metfor::CelsiusDiff: From<T>,
+    {
+        self.superadiabatic = metfor::CelsiusDiff::from(tolerance).unpack();
+        self
+    }
+
+    /// Builder method for how much faster the wind may be than at the levels on either side. The
+    /// default is 40 knots.
+    #[inline]
+    pub fn with_wind_spike_threshold<S>(mut self, threshold: S) -> Self
+    where
+        S: metfor::Speed,
+        Knots: From<S>,
+    {
+        self.wind_spike = Knots::from(threshold).unpack();
+        self
+    }
+
+    /// Builder method for how far a height may depart from the hydrostatic estimate. The default
+    /// is 50 meters.
+    #[inline]
+    pub fn with_hydrostatic_tolerance<L>(mut self, tolerance: L) -> Self
+    where
+        L: metfor::Length,
+        Meters: From<L>,
+    {
+        self.hydrostatic = Meters::from(tolerance).unpack();
+        self
+    }
+
+    /// Builder method for the relative humidity with respect to ice, above 100 percent, allowed
+    /// below freezing. The default is 5 percent.
+    #[inline]
+    pub fn with_ice_supersaturation_tolerance(mut self, tolerance: f64) -> Self {
+        self.ice_supersaturation = tolerance;
+        self
+    }
+
+    /// Run the checks and return the flags for each level, with the surface first.
+    pub fn check(&self, snd: &Sounding) -> Vec<QcFlags> {
+        let rows: Vec<DataRow> = snd.bottom_up().collect();
+        let mut flags = vec![QcFlags::default(); rows.len()];
+
+        for (row, flags) in rows.iter().zip(flags.iter_mut()) {
+            check_limits(row, flags);
+            self.check_humidity(row, flags);
+        }
+
+        self.check_lapse_rates(&rows, &mut flags);
+        self.check_wind_spikes(&rows, &mut flags);
+
+        for idx in check_hydrostatic_heights(snd, Meters(self.hydrostatic)) {
+            flags[idx].height.raise(QcFlag::Suspect);
+        }
+
+        flags
+    }
+
+    /// Run the checks and store the flags in the sounding.
+    ///
+    /// The flags are not updated if the sounding is changed later.
+    pub fn apply(&self, snd: Sounding) -> Sounding {
+        let flags = self.check(&snd);
+        snd.with_qc_flags(flags)
+    }
+
+    fn check_humidity(&self, row: &DataRow, flags: &mut QcFlags) {
+        let (t, dp) = match (row.temperature.into_option(), row.dew_point.into_option()) {
+            (Some(t), Some(dp)) => (t, dp),
+            _ => return,
+        };
+
+        if dp.unpack() > t.unpack() + 0.1 {
+            flags.dew_point.raise(QcFlag::Fail);
+            return;
+        }
+
+        if let Some(rh_ice) = relative_humidity_ice(row) {
+            if rh_ice * 100.0 > 100.0 + self.ice_supersaturation {
+                flags.dew_point.raise(QcFlag::Suspect);
+            }
+        }
+    }
+
+    fn check_lapse_rates(&self, rows: &[DataRow], flags: &mut [QcFlags]) {
+        let sfc_p = match rows.iter().find_map(|row| row.pressure.into_option()) {
+            Some(p) => p.unpack(),
+            None => return,
+        };
+        let top_of_sfc_layer = sfc_p - self.surface_layer;
+
+        let thetas: Vec<(usize, f64, f64)> = rows
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| flags[i].temperature < QcFlag::Fail)
+            .filter_map(|(i, row)| {
+                let p = row.pressure.into_option()?;
+                let t = row.temperature.into_option()?;
+                Some((i, p.unpack(), metfor::theta(p, t).unpack()))
+            })
+            .collect();
+
+        for pair in thetas.windows(2) {
+            let (i0, p0, theta0) = pair[0];
+            let (i1, _, theta1) = pair[1];
+
+            if p0 <= top_of_sfc_layer && theta0 - theta1 > self.superadiabatic {
+                flags[i0].temperature.raise(QcFlag::Suspect);
+                flags[i1].temperature.raise(QcFlag::Suspect);
+            }
+        }
+    }
+
+    fn check_wind_spikes(&self, rows: &[DataRow], flags: &mut [QcFlags]) {
+        let speeds: Vec<(usize, f64)> = rows
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|&(i, _)| flags[i].wind < QcFlag::Fail)
+            .filter_map(|(i, row)| row.wind.into_option().map(|w| (i, w.speed.unpack())))
+            .collect();
+
+        for triple in speeds.windows(3) {
+            let (_, below) = triple[0];
+            let (i, speed) = triple[1];
+            let (_, above) = triple[2];
+
+            if speed - below.max(above) > self.wind_spike {
+                flags[i].wind.raise(QcFlag::Suspect);
+            }
+        }
+    }
+}
+
+fn check_limits(row: &DataRow, flags: &mut QcFlags) {
+    fn outside(val: f64, min: f64, max: f64) -> bool {
+        val < min || val > max
+    }
+
+    if let Some(p) = row.pressure.into_option() {
+        if outside(p.unpack(), 1.0e-6, 1100.0) {
+            flags.pressure.raise(QcFlag::Fail);
+        }
+    }
+    if let Some(t) = row.temperature.into_option() {
+        if outside(t.unpack(), -100.0, 60.0) {
+            flags.temperature.raise(QcFlag::Fail);
+        }
+    }
+    if let Some(dp) = row.dew_point.into_option() {
+        if outside(dp.unpack(), -120.0, 40.0) {
+            flags.dew_point.raise(QcFlag::Fail);
+        }
+    }
+    if let Some(wind) = row.wind.into_option() {
+        if outside(wind.speed.unpack(), 0.0, 300.0) || outside(wind.direction, 0.0, 360.0) {
+            flags.wind.raise(QcFlag::Fail);
+        }
+    }
+    if let Some(z) = row.height.into_option() {
+        if outside(z.unpack(), -1000.0, 60_000.0) {
+            flags.height.raise(QcFlag::Fail);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use metfor::{Celsius, WindSpdDir};
+    use optional::some;
+
+    #[test]
+    fn test_checks() {
+        let p = [1000.0, 950.0, 900.0, 850.0, 700.0, 500.0];
+        let t = [20.0, 16.0, 20.0, 10.0, 0.0, -20.0];
+        let dp = [10.0, 10.0, 5.0, 12.0, -5.0, -21.0];
+        let speed = [10.0, 15.0, 20.0, 80.0, 25.0, 30.0];
+
+        let snd = Sounding::new()
+            .with_station_pressure(HectoPascal(1010.0))
+            .with_pressure_profile(p.iter().map(|&p| some(HectoPascal(p))).collect())
+            .with_temperature_profile(t.iter().map(|&t| some(Celsius(t))).collect())
+            .with_dew_point_profile(dp.iter().map(|&dp| some(Celsius(dp))).collect())
+            .with_wind_profile(
+                speed
+                    .iter()
+                    .map(|&speed| {
+                        some(WindSpdDir {
+                            speed: Knots(speed),
+                            direction: 270.0,
+                        })
+                    })
+                    .collect(),
+            );
+
+        let flags = QualityControl::new().check(&snd);
+        assert_eq!(flags.len(), 7);
+
+        // Superadiabatic from 900 to 850 hPa
+        assert_eq!(flags[1].temperature, QcFlag::Pass);
+        assert_eq!(flags[3].temperature, QcFlag::Suspect);
+        assert_eq!(flags[4].temperature, QcFlag::Suspect);
+
+        // Dew point above the temperature at 850 hPa
+        assert_eq!(flags[4].dew_point, QcFlag::Fail);
+
+        // Supersaturated with respect to ice at 500 hPa
+        assert_eq!(flags[6].dew_point, QcFlag::Suspect);
+        assert_eq!(flags[5].dew_point, QcFlag::Pass);
+
+        // Wind spike at 850 hPa
+        assert_eq!(flags[4].wind, QcFlag::Suspect);
+        assert_eq!(flags[3].wind, QcFlag::Pass);
+
+        assert_eq!(flags[4].worst(), QcFlag::Fail);
+        assert_eq!(flags[4].get(ProfileVariable::ThetaE), QcFlag::Fail);
+    }
+
+    #[test]
+    fn test_hydrostatic_check() {
+        use crate::doctest::make_test_sounding;
+        use crate::{fill_missing_heights, StationInfo};
+
+        let snd = make_test_sounding()
+            .with_station_info(StationInfo::new().with_elevation(Meters(100.0)));
+        let snd = fill_missing_heights(&snd);
+
+        let flags = QualityControl::new().check(&snd);
+        assert!(flags.iter().all(|f| f.height == QcFlag::Pass));
+
+        // Raise 850 hPa by 30 meters.
+        let mut heights: Vec<_> = snd.height_profile()[1..].to_vec();
+        heights[2] = some(Meters(heights[2].unwrap().0 + 30.0));
+        let snd = snd.with_height_profile(heights);
+
+        let flags = QualityControl::new().check(&snd);
+        assert!(flags.iter().all(|f| f.height == QcFlag::Pass));
+
+        let flags = QualityControl::new()
+            .with_hydrostatic_tolerance(Meters(20.0))
+            .check(&snd);
+        let suspect: Vec<_> = (0..flags.len())
+            .filter(|&i| flags[i].height == QcFlag::Suspect)
+            .collect();
+        // The layer above is 30 meters too thin.
+        assert_eq!(suspect, vec![3, 4]);
+        assert_eq!(flags[3].worst(), QcFlag::Suspect);
+    }
+}
@@ -0,0 +1,477 @@
+//! Serde support for `Sounding`, `DataRow`, and `StationInfo`, enabled with the `serde` feature.
+//!
+//! The types are serialized through private mirror structs so the serialized schema is decoupled
+//! from the internal layout. Missing values are serialized as nulls and every field holding a
+//! quantity has its units in the field name, e.g. `pressure_hpa` or `theta_e_k`. Soundings also
+//! carry a `schema_version` field, and deserializing a sounding with an unknown version fails.
+//!
+//! Profiles are stored as returned by the getters, with the surface values in the first element,
+//! and are restored exactly as stored. Quality control flags are stored with one entry per level,
+//! and are empty if quality control hasn't been run.
+
+use chrono::NaiveDateTime;
+use metfor::{Knots, Quantity, WindSpdDir};
+use optional::{Noned, Optioned};
+use serde::de::Error as DeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::data_row::DataRow;
+use crate::qc::{QcFlag, QcFlags};
+use crate::sounding::{Profiles, Sounding};
+use crate::station_info::StationInfo;
+
+/// The version of the serialized `Sounding` schema. This is only incremented for changes that are
+/// not backwards compatible.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct WindRepr {
+    direction_deg: f64,
+    speed_kt: f64,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum QcFlagRepr {
+    Pass,
+    Suspect,
+    Fail,
+}
+
+#[derive(Serialize, Deserialize)]
+struct QcFlagsRepr {
+    pressure: QcFlagRepr,
+    temperature: QcFlagRepr,
+    dew_point: QcFlagRepr,
+    wind: QcFlagRepr,
+    height: QcFlagRepr,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StationInfoRepr {
+    station_num: Option<i32>,
+    lat_lon_deg: Option<(f64, f64)>,
+    elevation_m: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DataRowRepr {
+    pressure_hpa: Option<f64>,
+    temperature_c: Option<f64>,
+    wet_bulb_c: Option<f64>,
+    dew_point_c: Option<f64>,
+    theta_e_k: Option<f64>,
+    wind: Option<WindRepr>,
+    pvv_pa_s: Option<f64>,
+    height_m: Option<f64>,
+    cloud_fraction: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SoundingRepr {
+    schema_version: u32,
+    source: Option<String>,
+    station: StationInfoRepr,
+    valid_time: Option<NaiveDateTime>,
+    lead_time_hours: Option<i32>,
+
+    pressure_hpa: Vec<Option<f64>>,
+    temperature_c: Vec<Option<f64>>,
+    wet_bulb_c: Vec<Option<f64>>,
+    dew_point_c: Vec<Option<f64>>,
+    theta_e_k: Vec<Option<f64>>,
+    wind: Vec<Option<WindRepr>>,
+    pvv_pa_s: Vec<Option<f64>>,
+    height_m: Vec<Option<f64>>,
+    cloud_fraction: Vec<Option<f64>>,
+
+    mslp_hpa: Option<f64>,
+    station_pressure_hpa: Option<f64>,
+    sfc_temperature_c: Option<f64>,
+    sfc_dew_point_c: Option<f64>,
+    low_cloud: Option<f64>,
+    mid_cloud: Option<f64>,
+    high_cloud: Option<f64>,
+    precipitation_mm: Option<f64>,
+    sfc_wind: Option<WindRepr>,
+
+    // Optional, so hand-written input, or input in the older style without flags, deserializes.
+    #[serde(default)]
+    qc_flags: Vec<QcFlagsRepr>,
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                        Quality control
+--------------------------------------------------------------------------------------------------*/
+impl From<QcFlag> for QcFlagRepr {
+    fn from(flag: QcFlag) -> Self {
+        match flag {
+            QcFlag::Pass => QcFlagRepr::Pass,
+            QcFlag::Suspect => QcFlagRepr::Suspect,
+            QcFlag::Fail => QcFlagRepr::Fail,
+        }
+    }
+}
+
+impl From<QcFlagRepr> for QcFlag {
+    fn from(repr: QcFlagRepr) -> Self {
+        match repr {
+            QcFlagRepr::Pass => QcFlag::Pass,
+            QcFlagRepr::Suspect => QcFlag::Suspect,
+            QcFlagRepr::Fail => QcFlag::Fail,
+        }
+    }
+}
+
+impl From<&QcFlags> for QcFlagsRepr {
+    fn from(flags: &QcFlags) -> Self {
+        QcFlagsRepr {
+            pressure: flags.pressure.into(),
+            temperature: flags.temperature.into(),
+            dew_point: flags.dew_point.into(),
+            wind: flags.wind.into(),
+            height: flags.height.into(),
+        }
+    }
+}
+
+impl From<QcFlagsRepr> for QcFlags {
+    fn from(repr: QcFlagsRepr) -> Self {
+        QcFlags {
+            pressure: repr.pressure.into(),
+            temperature: repr.temperature.into(),
+            dew_point: repr.dew_point.into(),
+            wind: repr.wind.into(),
+            height: repr.height.into(),
+        }
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                         StationInfo
+--------------------------------------------------------------------------------------------------*/
+impl From<&StationInfo> for StationInfoRepr {
+    fn from(stn: &StationInfo) -> Self {
+        StationInfoRepr {
+            station_num: stn.station_num().into_option(),
+            lat_lon_deg: stn.location(),
+            elevation_m: to_opt(stn.elevation()),
+        }
+    }
+}
+
+impl From<StationInfoRepr> for StationInfo {
+    fn from(repr: StationInfoRepr) -> Self {
+        StationInfo::new()
+            .with_station(repr.station_num)
+            .with_lat_lon(repr.lat_lon_deg)
+            .with_elevation(from_opt::<metfor::Meters>(repr.elevation_m))
+    }
+}
+
+impl Serialize for StationInfo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StationInfoRepr::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for StationInfo {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        StationInfoRepr::deserialize(deserializer).map(StationInfo::from)
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                           DataRow
+--------------------------------------------------------------------------------------------------*/
+impl Serialize for DataRow {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DataRowRepr {
+            pressure_hpa: to_opt(self.pressure),
+            temperature_c: to_opt(self.temperature),
+            wet_bulb_c: to_opt(self.wet_bulb),
+            dew_point_c: to_opt(self.dew_point),
+            theta_e_k: to_opt(self.theta_e),
+            wind: to_wind(self.wind),
+            pvv_pa_s: to_opt(self.pvv),
+            height_m: to_opt(self.height),
+            cloud_fraction: self.cloud_fraction.into_option(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DataRow {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = DataRowRepr::deserialize(deserializer)?;
+
+        Ok(DataRow {
+            pressure: from_opt(repr.pressure_hpa),
+            temperature: from_opt(repr.temperature_c),
+            wet_bulb: from_opt(repr.wet_bulb_c),
+            dew_point: from_opt(repr.dew_point_c),
+            theta_e: from_opt(repr.theta_e_k),
+            wind: from_wind(repr.wind),
+            pvv: from_opt(repr.pvv_pa_s),
+            height: from_opt(repr.height_m),
+            cloud_fraction: Optioned::from(repr.cloud_fraction),
+        })
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                           Sounding
+--------------------------------------------------------------------------------------------------*/
+impl Serialize for Sounding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SoundingRepr {
+            schema_version: SCHEMA_VERSION,
+            source: self.source_description().map(ToOwned::to_owned),
+            station: StationInfoRepr::from(&self.station_info()),
+            valid_time: self.valid_time(),
+            lead_time_hours: self.lead_time().into_option(),
+
+            pressure_hpa: to_opt_vec(self.pressure_profile()),
+            temperature_c: to_opt_vec(self.temperature_profile()),
+            wet_bulb_c: to_opt_vec(self.wet_bulb_profile()),
+            dew_point_c: to_opt_vec(self.dew_point_profile()),
+            theta_e_k: to_opt_vec(self.theta_e_profile()),
+            wind: self.wind_profile().iter().cloned().map(to_wind).collect(),
+            pvv_pa_s: to_opt_vec(self.pvv_profile()),
+            height_m: to_opt_vec(self.height_profile()),
+            cloud_fraction: self
+                .cloud_fraction_profile()
+                .iter()
+                .map(|cld| cld.into_option())
+                .collect(),
+
+            mslp_hpa: to_opt(self.mslp()),
+            station_pressure_hpa: to_opt(self.station_pressure()),
+            sfc_temperature_c: to_opt(self.sfc_temperature()),
+            sfc_dew_point_c: to_opt(self.sfc_dew_point()),
+            low_cloud: self.low_cloud().into_option(),
+            mid_cloud: self.mid_cloud().into_option(),
+            high_cloud: self.high_cloud().into_option(),
+            precipitation_mm: to_opt(self.precipitation()),
+            sfc_wind: to_wind(self.sfc_wind()),
+
+            qc_flags: self.qc_flags().iter().map(QcFlagsRepr::from).collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Sounding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = SoundingRepr::deserialize(deserializer)?;
+
+        if repr.schema_version != SCHEMA_VERSION {
+            return Err(D::Error::custom(format!(
+                "unsupported sounding schema version {}, expected {}",
+                repr.schema_version, SCHEMA_VERSION
+            )));
+        }
+
+        let n = repr.pressure_hpa.len();
+        let lengths_match = [
+            repr.temperature_c.len(),
+            repr.wet_bulb_c.len(),
+            repr.dew_point_c.len(),
+            repr.theta_e_k.len(),
+            repr.wind.len(),
+            repr.pvv_pa_s.len(),
+            repr.height_m.len(),
+            repr.cloud_fraction.len(),
+            repr.qc_flags.len(),
+        ]
+        .iter()
+        .all(|&len| len == 0 || len == n);
+        if !lengths_match {
+            return Err(D::Error::custom(
+                "profiles must be empty or the same length as the pressure profile",
+            ));
+        }
+
+        // Set the surface values first, since the profiles are restored as they are and the
+        // surface builders would overwrite their first elements.
+        let snd = Sounding::new()
+            .with_source_description(repr.source)
+            .with_station_info(StationInfo::from(repr.station))
+            .with_valid_time(repr.valid_time)
+            .with_lead_time(repr.lead_time_hours)
+            .with_mslp(from_opt::<metfor::HectoPascal>(repr.mslp_hpa))
+            .with_station_pressure(from_opt::<metfor::HectoPascal>(repr.station_pressure_hpa))
+            .with_sfc_temperature(from_opt::<metfor::Celsius>(repr.sfc_temperature_c))
+            .with_sfc_dew_point(from_opt::<metfor::Celsius>(repr.sfc_dew_point_c))
+            .with_low_cloud(repr.low_cloud)
+            .with_mid_cloud(repr.mid_cloud)
+            .with_high_cloud(repr.high_cloud)
+            .with_precipitation(from_opt::<metfor::Mm>(repr.precipitation_mm))
+            .with_sfc_wind(from_wind(repr.sfc_wind))
+            .with_verbatim_profiles(Profiles {
+                pressure: from_opt_vec(repr.pressure_hpa),
+                temperature: from_opt_vec(repr.temperature_c),
+                wet_bulb: from_opt_vec(repr.wet_bulb_c),
+                dew_point: from_opt_vec(repr.dew_point_c),
+                theta_e: from_opt_vec(repr.theta_e_k),
+                wind: repr.wind.into_iter().map(from_wind).collect(),
+                pvv: from_opt_vec(repr.pvv_pa_s),
+                height: from_opt_vec(repr.height_m),
+                cloud_fraction: repr
+                    .cloud_fraction
+                    .into_iter()
+                    .map(Optioned::from)
+                    .collect(),
+            })
+            .with_qc_flags(repr.qc_flags.into_iter().map(QcFlags::from).collect());
+
+        Ok(snd)
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                     Conversion helpers
+--------------------------------------------------------------------------------------------------*/
+#[inline]
+fn to_opt<T: Quantity + Noned>(val: Optioned<T>) -> Option<f64> {
+    val.map(Quantity::unpack)
+}
+
+#[inline]
+fn from_opt<T: Quantity + Noned>(val: Option<f64>) -> Optioned<T> {
+    Optioned::from(val.map(T::pack))
+}
+
+#[inline]
+fn to_opt_vec<T: Quantity + Noned>(vals: &[Optioned<T>]) -> Vec<Option<f64>> {
+    vals.iter().cloned().map(to_opt).collect()
+}
+
+#[inline]
+fn from_opt_vec<T: Quantity + Noned>(vals: Vec<Option<f64>>) -> Vec<Optioned<T>> {
+    vals.into_iter().map(from_opt).collect()
+}
+
+#[inline]
+fn to_wind(wind: Optioned<WindSpdDir<Knots>>) -> Option<WindRepr> {
+    wind.map(|wind| WindRepr {
+        direction_deg: wind.direction,
+        speed_kt: wind.speed.unpack(),
+    })
+}
+
+#[inline]
+fn from_wind(wind: Option<WindRepr>) -> Optioned<WindSpdDir<Knots>> {
+    Optioned::from(wind.map(|wind| WindSpdDir {
+        speed: Knots(wind.speed_kt),
+        direction: wind.direction_deg,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+
+    #[test]
+    fn test_sounding_round_trip() {
+        let snd = make_test_sounding().with_lead_time(12);
+
+        let json = serde_json::to_string(&snd).unwrap();
+        assert!(json.contains("\"schema_version\":1"));
+        assert!(json.contains("\"mslp_hpa\":null"));
+
+        let snd2: Sounding = serde_json::from_str(&json).unwrap();
+        assert_eq!(snd.pressure_profile(), snd2.pressure_profile());
+        assert_eq!(snd.temperature_profile(), snd2.temperature_profile());
+        assert_eq!(snd.station_pressure(), snd2.station_pressure());
+        assert_eq!(snd.lead_time(), snd2.lead_time());
+        assert!(snd2.wind_profile().is_empty());
+    }
+
+    #[test]
+    fn test_surface_values_and_qc_flags_round_trip() {
+        use crate::qc::QualityControl;
+        use metfor::{Meters, PaPS};
+        use optional::some;
+
+        let snd = make_test_sounding()
+            .with_pvv_profile(vec![some(PaPS(-1.0)); 4])
+            .with_cloud_fraction_profile(vec![some(50.0); 4])
+            .with_height_profile(vec![some(Meters(100.0)); 4]);
+        let rows: Vec<DataRow> = snd
+            .bottom_up()
+            .enumerate()
+            .map(|(i, mut row)| {
+                if i == 0 {
+                    row.pvv = some(PaPS(-0.5));
+                    row.cloud_fraction = some(25.0);
+                    row.height = some(Meters(10.0));
+                }
+                row
+            })
+            .collect();
+        let snd = snd.with_data_rows(&rows);
+        let snd = QualityControl::new().apply(snd);
+        assert_eq!(snd.pvv_profile()[0], some(PaPS(-0.5)));
+
+        let json = serde_json::to_string(&snd).unwrap();
+        let snd2: Sounding = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(snd.pvv_profile(), snd2.pvv_profile());
+        assert_eq!(snd.cloud_fraction_profile(), snd2.cloud_fraction_profile());
+        assert_eq!(snd.height_profile(), snd2.height_profile());
+        assert_eq!(snd.qc_flags(), snd2.qc_flags());
+        assert_eq!(snd2.qc_flags().len(), 5);
+    }
+
+    #[test]
+    fn test_single_level_round_trip() {
+        use metfor::HectoPascal;
+        use optional::some;
+
+        let snd = Sounding::new()
+            .with_station_pressure(HectoPascal(1000.0))
+            .with_verbatim_profiles(Profiles {
+                pressure: vec![some(HectoPascal(1000.0))],
+                ..Profiles::default()
+            });
+
+        let json = serde_json::to_string(&snd).unwrap();
+        let snd2: Sounding = serde_json::from_str(&json).unwrap();
+        assert_eq!(snd2.pressure_profile().len(), 1);
+        assert_eq!(snd.pressure_profile(), snd2.pressure_profile());
+    }
+
+    #[test]
+    fn test_reject_mismatched_profile_lengths() {
+        let json = serde_json::to_string(&make_test_sounding())
+            .unwrap()
+            .replace("\"pressure_hpa\":[", "\"pressure_hpa\":[1050.0,");
+
+        assert!(serde_json::from_str::<Sounding>(&json).is_err());
+    }
+
+    #[test]
+    fn test_reject_unknown_schema_version() {
+        let json = serde_json::to_string(&make_test_sounding())
+            .unwrap()
+            .replace("\"schema_version\":1", "\"schema_version\":99");
+
+        assert!(serde_json::from_str::<Sounding>(&json).is_err());
+    }
+
+    #[test]
+    fn test_data_row_round_trip() {
+        let row = make_test_sounding().data_row(2).unwrap();
+
+        let json = serde_json::to_string(&row).unwrap();
+        assert!(json.contains("\"wind\":null"));
+
+        let row2: DataRow = serde_json::from_str(&json).unwrap();
+        assert_eq!(row.pressure, row2.pressure);
+        assert_eq!(row.temperature, row2.temperature);
+        assert!(row2.dew_point.is_none());
+        assert!(row2.wind.is_none());
+    }
+}
@@ -0,0 +1,250 @@
+//! A time series of soundings at one station, e.g. all the forecast hours of a model run.
+
+use std::ops::{Bound, RangeBounds};
+
+use chrono::NaiveDateTime;
+
+use crate::error::{Result, SoundingError};
+use crate::sounding::Sounding;
+use crate::station_info::StationInfo;
+
+/// A collection of soundings from the same station, sorted by valid time.
+///
+/// Every sounding must have a valid time, and no two may have the same valid time.
+///
+/// # Examples
+///
+/// ```rust
+/// use chrono::NaiveDate;
+/// use metfor::HectoPascal;
+/// use sounding_base::{Sounding, SoundingSeries, StationInfo};
+///
+/// let init = NaiveDate::from_ymd_opt(2019, 6, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+/// let stn = StationInfo::new().with_station(727730);
+///
+/// let series = SoundingSeries::from_soundings((0..4).map(|i| {
+///     Sounding::new()
+///         .with_station_info(stn)
+///         .with_valid_time(init + chrono::Duration::hours(3 * i))
+///         .with_lead_time(3 * i as i32)
+///         .with_mslp(HectoPascal(1010.0 - i as f64))
+/// }))
+/// .unwrap();
+///
+/// assert_eq!(series.len(), 4);
+/// assert_eq!(series.station_info().station_num().unwrap(), 727730);
+///
+/// let snd = series.by_lead_time(6).unwrap();
+/// assert_eq!(snd.valid_time().unwrap(), init + chrono::Duration::hours(6));
+///
+/// let mslp: Vec<_> = series.time_series(|snd| snd.mslp().unwrap()).collect();
+/// assert_eq!(mslp[3], (init + chrono::Duration::hours(9), HectoPascal(1007.0)));
+///
+/// let first_six_hours = series.range(init..init + chrono::Duration::hours(6));
+/// assert_eq!(first_six_hours.len(), 2);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SoundingSeries {
+    station: StationInfo,
+    soundings: Vec<Sounding>,
+}
+
+impl SoundingSeries {
+    /// Create an empty series.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a series from a collection of soundings in any order.
+    pub fn from_soundings<I>(soundings: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Sounding>,
+    {
+        let mut series = Self::new();
+        for snd in soundings {
+            series.push(snd)?;
+        }
+        Ok(series)
+    }
+
+    /// Add a sounding to the series, keeping it sorted by valid time.
+    ///
+    /// This fails if the sounding has no valid time, if there is already a sounding with the same
+    /// valid time, or if the station doesn't match the soundings already in the series.
+    pub fn push(&mut self, snd: Sounding) -> Result<()> {
+        let vt = snd.valid_time().ok_or(SoundingError::MissingValidTime)?;
+
+        if self.soundings.is_empty() {
+            self.station = snd.station_info();
+        } else if !self.station.is_same_station(&snd.station_info()) {
+            return Err(SoundingError::Incompatible(format!(
+                "sounding valid at {} is from a different station",
+                vt
+            )));
+        }
+
+        match self.search(vt) {
+            Ok(_) => Err(SoundingError::Incompatible(format!(
+                "duplicate valid time {}",
+                vt
+            ))),
+            Err(idx) => {
+                self.soundings.insert(idx, snd);
+                Ok(())
+            }
+        }
+    }
+
+    /// The station shared by all the soundings.
+    #[inline]
+    pub fn station_info(&self) -> StationInfo {
+        self.station
+    }
+
+    /// The number of soundings in the series.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.soundings.len()
+    }
+
+    /// Check if the series is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.soundings.is_empty()
+    }
+
+    /// Iterate over the soundings in order of valid time.
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<'_, Sounding> {
+        self.soundings.iter()
+    }
+
+    /// The valid times of the soundings in order.
+    pub fn valid_times<'a>(&'a self) -> impl Iterator<Item = NaiveDateTime> + 'a {
+        self.soundings.iter().filter_map(Sounding::valid_time)
+    }
+
+    /// Get the sounding valid at `valid_time`.
+    pub fn get(&self, valid_time: NaiveDateTime) -> Option<&Sounding> {
+        self.search(valid_time).ok().map(|idx| &self.soundings[idx])
+    }
+
+    /// Get the first sounding with the given lead time in hours.
+    pub fn by_lead_time(&self, lead_time: i32) -> Option<&Sounding> {
+        self.soundings
+            .iter()
+            .find(|snd| snd.lead_time().into_option() == Some(lead_time))
+    }
+
+    /// Get the soundings with valid times in `range`.
+    pub fn range<R>(&self, range: R) -> &[Sounding]
+    where
+        R: RangeBounds<NaiveDateTime>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(&vt) => self.partition(|t| t < vt),
+            Bound::Excluded(&vt) => self.partition(|t| t <= vt),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&vt) => self.partition(|t| t <= vt),
+            Bound::Excluded(&vt) => self.partition(|t| t < vt),
+            Bound::Unbounded => self.soundings.len(),
+        };
+
+        if start < end {
+            &self.soundings[start..end]
+        } else {
+            &[]
+        }
+    }
+
+    /// Iterate over some value of each sounding paired with its valid time, e.g. the mean sea
+    /// level pressure or CAPE.
+    pub fn time_series<'a, F, T>(&'a self, f: F) -> impl Iterator<Item = (NaiveDateTime, T)> + 'a
+    where
+        F: Fn(&Sounding) -> T + 'a,
+    {
+        self.soundings
+            .iter()
+            .filter_map(move |snd| snd.valid_time().map(|vt| (vt, f(snd))))
+    }
+
+    fn search(&self, vt: NaiveDateTime) -> std::result::Result<usize, usize> {
+        self.soundings
+            .binary_search_by_key(&Some(vt), Sounding::valid_time)
+    }
+
+    fn partition<P>(&self, pred: P) -> usize
+    where
+        P: Fn(NaiveDateTime) -> bool,
+    {
+        self.soundings
+            .partition_point(|snd| snd.valid_time().map(&pred).unwrap_or(false))
+    }
+}
+
+impl<'a> IntoIterator for &'a SoundingSeries {
+    type Item = &'a Sounding;
+    type IntoIter = std::slice::Iter<'a, Sounding>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.soundings.iter()
+    }
+}
+
+impl IntoIterator for SoundingSeries {
+    type Item = Sounding;
+    type IntoIter = std::vec::IntoIter<Sounding>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.soundings.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{Duration, NaiveDate};
+
+    #[test]
+    fn test_push_validation() {
+        let vt = NaiveDate::from_ymd_opt(2019, 6, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let stn = StationInfo::new().with_station(727730);
+        let snd = |hours| {
+            Sounding::new()
+                .with_station_info(stn)
+                .with_valid_time(vt + Duration::hours(hours))
+        };
+
+        let mut series = SoundingSeries::new();
+        series.push(snd(6)).unwrap();
+        series.push(snd(0)).unwrap();
+        series.push(snd(3)).unwrap();
+
+        let times: Vec<_> = series.valid_times().collect();
+        assert_eq!(
+            times,
+            vec![vt, vt + Duration::hours(3), vt + Duration::hours(6)]
+        );
+
+        assert!(series.push(snd(3)).is_err());
+        assert!(series.push(Sounding::new().with_station_info(stn)).is_err());
+        assert!(series
+            .push(snd(9).with_station_info(StationInfo::new().with_station(727740)))
+            .is_err());
+        assert_eq!(series.len(), 3);
+
+        assert_eq!(series.range(vt + Duration::hours(1)..).len(), 2);
+        assert_eq!(series.range(..=vt + Duration::hours(3)).len(), 2);
+        assert!(series.range(vt + Duration::hours(7)..).is_empty());
+        assert!(series.get(vt + Duration::hours(3)).is_some());
+        assert!(series.get(vt + Duration::hours(4)).is_none());
+    }
+}
@@ -0,0 +1,320 @@
+//! Reading and writing the SPC / SHARPpy `%RAW%` text format.
+//!
+//! The format is a `%TITLE%` section with a station identifier and the valid time in
+//! `YYMMDD/HHMM` format, followed by a `%RAW%` section of comma separated rows with the pressure
+//! (hPa), height (m), temperature (C), dew point (C), wind direction (degrees), and wind speed
+//! (knots), terminated by `%END%`. Missing values are written as `-9999.00`.
+//!
+//! SHARPpy treats the first row as the surface, so the first row read goes into the surface values
+//! of the `Sounding` and the surface values of a `Sounding` are written as the first row.
+
+use std::io::{BufRead, Write};
+
+use chrono::NaiveDateTime;
+use metfor::{Celsius, HectoPascal, Knots, Meters, Quantity, WindSpdDir};
+use optional::{none, some, Optioned};
+
+use crate::error::{Result, SoundingError};
+use crate::sounding::Sounding;
+use crate::station_info::StationInfo;
+
+const MISSING: f64 = -9999.0;
+const TIME_FORMAT: &str = "%y%m%d/%H%M";
+
+/// Read a sounding in the SHARPpy `%RAW%` format.
+///
+/// A numeric station identifier in the title becomes the station number, and the full title is
+/// kept as the source description.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal, Knots, Meters};
+/// use sounding_base::read_sharppy;
+///
+/// let text = "\
+/// %TITLE%
+///  72776   180308/1200
+///
+///    LEVEL       HGHT       TEMP       DWPT       WDIR       WSPD
+/// -------------------------------------------------------------------
+/// %RAW%
+///     902.00,    1131.00,      10.50,       6.00,     200.00,       6.00
+///     850.00,    1581.00,       5.60,      -0.40,     230.00,      16.00
+///     700.00,    3050.00,      -4.20,     -16.20,     250.00,      30.00
+///     500.00,    5640.00,     -20.60,   -9999.00,     260.00,      50.00
+/// %END%
+/// ";
+///
+/// let snd = read_sharppy(text.as_bytes()).unwrap();
+///
+/// assert_eq!(snd.station_info().station_num().unwrap(), 72776);
+/// assert_eq!(snd.station_info().elevation().unwrap(), Meters(1131.0));
+/// assert_eq!(snd.station_pressure().unwrap(), HectoPascal(902.0));
+/// assert_eq!(snd.sfc_wind().unwrap().speed, Knots(6.0));
+/// assert_eq!(snd.pressure_profile().len(), 4);
+/// assert_eq!(snd.temperature_profile()[2], Celsius(-4.2).into());
+/// assert!(snd.dew_point_profile()[3].is_none());
+/// ```
+pub fn read_sharppy<R: BufRead>(src: R) -> Result<Sounding> {
+    let mut title: Option<String> = None;
+    let mut in_title = false;
+    let mut in_raw = false;
+    let mut found_end = false;
+
+    let mut pressure = vec![];
+    let mut height = vec![];
+    let mut temperature = vec![];
+    let mut dew_point = vec![];
+    let mut wind = vec![];
+
+    for (i, line) in src.lines().enumerate() {
+        let line = line?;
+        let line_num = i + 1;
+        let line = line.trim();
+
+        match line {
+            "%TITLE%" => {
+                in_title = true;
+                continue;
+            }
+            "%RAW%" => {
+                in_raw = true;
+                continue;
+            }
+            "%END%" => {
+                found_end = true;
+                break;
+            }
+            "" => continue,
+            _ => {}
+        }
+
+        if in_raw {
+            let vals = line
+                .split(',')
+                .map(|val| {
+                    val.trim()
+                        .parse::<f64>()
+                        .map(|val| if val == MISSING { None } else { Some(val) })
+                        .map_err(|_| {
+                            SoundingError::parse(line_num, format!("invalid value '{}'", val))
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            if vals.len() != 6 {
+                return Err(SoundingError::parse(
+                    line_num,
+                    format!("expected 6 columns, found {}", vals.len()),
+                ));
+            }
+
+            pressure.push(Optioned::from(vals[0].map(HectoPascal)));
+            height.push(Optioned::from(vals[1].map(Meters)));
+            temperature.push(Optioned::from(vals[2].map(Celsius)));
+            dew_point.push(Optioned::from(vals[3].map(Celsius)));
+            wind.push(match (vals[4], vals[5]) {
+                (Some(direction), Some(speed)) => some(WindSpdDir {
+                    speed: Knots(speed),
+                    direction,
+                }),
+                _ => none(),
+            });
+        } else if in_title && title.is_none() {
+            title = Some(line.to_owned());
+        }
+    }
+
+    if !in_raw || !found_end {
+        return Err(SoundingError::UnexpectedEof);
+    }
+
+    let (station_num, valid_time) = title
+        .as_ref()
+        .map(|title| parse_title(title))
+        .unwrap_or((None, None));
+
+    if pressure.is_empty() {
+        return Ok(Sounding::new()
+            .with_source_description(title)
+            .with_station_info(StationInfo::new().with_station(station_num))
+            .with_valid_time(valid_time));
+    }
+
+    let stn = StationInfo::new()
+        .with_station(station_num)
+        .with_elevation(height.remove(0));
+
+    Ok(Sounding::new()
+        .with_source_description(title)
+        .with_station_info(stn)
+        .with_valid_time(valid_time)
+        .with_station_pressure(pressure.remove(0))
+        .with_sfc_temperature(temperature.remove(0))
+        .with_sfc_dew_point(dew_point.remove(0))
+        .with_sfc_wind(wind.remove(0))
+        .with_pressure_profile(pressure)
+        .with_height_profile(height)
+        .with_temperature_profile(temperature)
+        .with_dew_point_profile(dew_point)
+        .with_wind_profile(wind)
+        .fill_wet_bulb_and_theta_e())
+}
+
+/// Write a sounding in the SHARPpy `%RAW%` format.
+///
+/// The surface values are always written as the first row, with missing values as `-9999.00` and
+/// the station elevation as the height if there isn't one. Rows above the surface without a
+/// pressure are skipped. The station number is used as the station identifier in the title, or
+/// `----` if it is missing.
+///
+/// # Examples
+///
+/// ```rust
+/// use sounding_base::{read_sharppy, write_sharppy};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding();
+///
+/// let mut text: Vec<u8> = vec![];
+/// write_sharppy(&snd, &mut text).unwrap();
+///
+/// let snd2 = read_sharppy(text.as_slice()).unwrap();
+/// assert_eq!(snd.pressure_profile(), snd2.pressure_profile());
+/// assert_eq!(snd.temperature_profile(), snd2.temperature_profile());
+/// ```
+pub fn write_sharppy<W: Write>(snd: &Sounding, mut dest: W) -> Result<()> {
+    let stn_id = snd
+        .station_info()
+        .station_num()
+        .map(|num| num.to_string())
+        .unwrap_or_else(|| "----".to_owned());
+    let time = snd
+        .valid_time()
+        .map(|vt| vt.format(TIME_FORMAT).to_string())
+        .unwrap_or_else(|| "000000/0000".to_owned());
+
+    writeln!(dest, "%TITLE%")?;
+    writeln!(dest, " {}   {}", stn_id, time)?;
+    writeln!(dest)?;
+    writeln!(
+        dest,
+        "   LEVEL       HGHT       TEMP       DWPT       WDIR       WSPD"
+    )?;
+    writeln!(
+        dest,
+        "-------------------------------------------------------------------"
+    )?;
+    writeln!(dest, "%RAW%")?;
+
+    let elevation = snd.station_info().elevation();
+    let rows = snd.bottom_up().enumerate().filter_map(|(i, mut row)| {
+        if i == 0 {
+            if row.height.is_none() {
+                row.height = elevation;
+            }
+            Some(row)
+        } else if row.pressure.is_some() {
+            Some(row)
+        } else {
+            None
+        }
+    });
+
+    for row in rows {
+        let (direction, speed) = row
+            .wind
+            .map(|wind| (wind.direction, wind.speed.unpack()))
+            .unwrap_or((MISSING, MISSING));
+
+        writeln!(
+            dest,
+            "{:>10.2}, {:>10.2}, {:>10.2}, {:>10.2}, {:>10.2}, {:>10.2}",
+            or_missing(row.pressure),
+            or_missing(row.height),
+            or_missing(row.temperature),
+            or_missing(row.dew_point),
+            direction,
+            speed,
+        )?;
+    }
+
+    writeln!(dest, "%END%")?;
+
+    Ok(())
+}
+
+#[inline]
+fn or_missing<T: Quantity + optional::Noned>(val: Optioned<T>) -> f64 {
+    val.map_or(MISSING, Quantity::unpack)
+}
+
+/// Get the station number, if numeric, and valid time from a title line like
+/// ` OUN   190520/1200`.
+fn parse_title(title: &str) -> (Option<i32>, Option<NaiveDateTime>) {
+    let mut station_num = None;
+    let mut valid_time = None;
+
+    for token in title.split_whitespace() {
+        if token.contains('/') {
+            valid_time = NaiveDateTime::parse_from_str(token, TIME_FORMAT).ok();
+        } else if station_num.is_none() {
+            station_num = token.parse().ok();
+        }
+    }
+
+    (station_num, valid_time)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+
+    #[test]
+    fn test_round_trip_missing_surface_pressure() {
+        let snd = make_test_sounding()
+            .with_station_pressure(none::<HectoPascal>())
+            .with_station_info(StationInfo::new().with_elevation(Meters(300.0)));
+
+        let mut text: Vec<u8> = vec![];
+        write_sharppy(&snd, &mut text).unwrap();
+
+        let snd2 = read_sharppy(text.as_slice()).unwrap();
+        assert!(snd2.station_pressure().is_none());
+        assert_eq!(snd2.station_info().elevation(), some(Meters(300.0)));
+        assert_eq!(snd2.sfc_temperature(), snd.sfc_temperature());
+        assert_eq!(snd.pressure_profile()[1..], snd2.pressure_profile()[1..]);
+        assert_eq!(snd.temperature_profile(), snd2.temperature_profile());
+    }
+
+    #[test]
+    fn test_malformed_rows() {
+        let wrap = |rows: &str| format!("%TITLE%\n OUN   190520/1200\n%RAW%\n{}\n%END%\n", rows);
+
+        let text = wrap("1000.00, 100.00, 20.00, 10.00, 180.00");
+        match read_sharppy(text.as_bytes()) {
+            Err(SoundingError::Parse { line, .. }) => assert_eq!(line, 4),
+            res => panic!("expected a parse error, got {:?}", res),
+        }
+
+        let text = wrap(
+            "1000.00, 100.00, 20.00, 10.00, 180.00, 10.00\n925.00, abc, 18.00, 8.00, 190.00, 15.00",
+        );
+        match read_sharppy(text.as_bytes()) {
+            Err(SoundingError::Parse { line, msg }) => {
+                assert_eq!(line, 5);
+                assert!(msg.contains("abc"));
+            }
+            res => panic!("expected a parse error, got {:?}", res),
+        }
+
+        let text =
+            "%TITLE%\n OUN   190520/1200\n%RAW%\n1000.00, 100.00, 20.00, 10.00, 180.00, 10.00\n";
+        assert!(matches!(
+            read_sharppy(text.as_bytes()),
+            Err(SoundingError::UnexpectedEof)
+        ));
+    }
+}
@@ -5,6 +5,8 @@ use metfor::{Celsius, HectoPascal, Kelvin, Knots, Meters, Mm, PaPS, Quantity, Wi
 use optional::Optioned;
 
 use crate::data_row::DataRow;
+use crate::derived::DerivedCache;
+use crate::qc::{QcFlag, QcFlags};
 use crate::station_info::StationInfo;
 
 /// All the variables stored in the sounding.
@@ -47,6 +49,13 @@ pub struct Sounding {
     high_cloud: Optioned<f64>,
     precipitation: Optioned<Mm>,
     sfc_wind: Optioned<WindSpdDir<Knots>>,
+
+    // Quality control flags for each level, empty if quality control hasn't been run.
+    qc: Vec<QcFlags>,
+
+    // Profiles derived from the ones above, calculated when first requested. This is reset by the
+    // builders that change their inputs.
+    derived: DerivedCache,
 }
 
 macro_rules! make_profile_setter {
@@ -56,7 +65,7 @@ macro_rules! make_profile_setter {
             if !profile.is_empty() {
                 profile.insert(0, self.$sfc_val);
             }
-            Self {$p_var: profile, ..self}
+            Self {$p_var: profile, qc: vec![], derived: DerivedCache::default(), ..self}
         }
     };
     ($(#[$attr:meta])* => $name:tt, $method:ident(), $inner_type:tt, $p_var:ident) => {
@@ -65,7 +74,7 @@ macro_rules! make_profile_setter {
             if !profile.is_empty() {
                 profile.insert(0, self.$method().into());
             }
-            Self {$p_var: profile, ..self}
+            Self {$p_var: profile, qc: vec![], derived: DerivedCache::default(), ..self}
         }
     };
     ($(#[$attr:meta])* => $name:tt, $sfc_val:expr, $inner_type:tt, $p_var:ident) => {
@@ -74,7 +83,7 @@ macro_rules! make_profile_setter {
             if !profile.is_empty() {
                 profile.insert(0, $sfc_val);
             }
-            Self {$p_var: profile, ..self}
+            Self {$p_var: profile, qc: vec![], derived: DerivedCache::default(), ..self}
         }
     };
 }
@@ -181,7 +190,7 @@ impl Sounding {
         /// use sounding_base::Sounding;
         /// use metfor::HectoPascal;
         /// use optional::{some, Optioned};
-        /// 
+        ///
         /// let data = vec![1000.0, 925.0, 850.0, 700.0, 500.0, 300.0, 250.0, 200.0, 150.0, 100.0];
         /// let pressure_data: Vec<Optioned<HectoPascal>> = data.into_iter()
         ///     .map(HectoPascal)
@@ -297,6 +306,70 @@ impl Sounding {
         &self.theta_e
     }
 
+    /// Builder method to calculate any missing values of the wet bulb and equivalent potential
+    /// temperature profiles from the temperature, dew point and pressure.
+    ///
+    /// Values already in the profiles are kept, and so are their lengths, except that a missing
+    /// profile is created to match the pressure profile. This is useful when the sounding came
+    /// from a format without these variables. Quality control flags are kept too, since the
+    /// values calculated from flagged temperatures or dew points are masked by the same flags.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::Celsius;
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let dew_points = vec![15.0, 10.0, 0.0, -10.0].into_iter().map(Celsius).map(some).collect();
+    /// let snd = make_test_sounding()
+    ///     .with_sfc_dew_point(Celsius(16.0))
+    ///     .with_dew_point_profile(dew_points);
+    /// assert!(snd.wet_bulb_profile().is_empty());
+    /// assert!(snd.theta_e_profile().is_empty());
+    ///
+    /// let snd = snd.fill_wet_bulb_and_theta_e();
+    /// assert_eq!(snd.wet_bulb_profile().len(), 5);
+    /// assert!(snd.wet_bulb_profile().iter().all(|wb| wb.is_some()));
+    /// assert!(snd.theta_e_profile().iter().all(|theta_e| theta_e.is_some()));
+    /// ```
+    pub fn fill_wet_bulb_and_theta_e(mut self) -> Self {
+        macro_rules! fill_profile {
+            ($profile:ident, $func:path) => {{
+                let n = self.pressure.len();
+                let mut profile = std::mem::take(&mut self.$profile);
+                let was_empty = profile.is_empty();
+                if was_empty {
+                    profile.resize(n, optional::none());
+                }
+
+                for (i, val) in profile
+                    .iter_mut()
+                    .take(n)
+                    .enumerate()
+                    .filter(|(_, v)| v.is_none())
+                {
+                    let p = self.pressure[i].into_option();
+                    let t = self.temperature.get(i).and_then(|t| t.into_option());
+                    let dp = self.dew_point.get(i).and_then(|dp| dp.into_option());
+
+                    if let (Some(p), Some(t), Some(dp)) = (p, t, dp) {
+                        *val = $func(t, dp, p).into();
+                    }
+                }
+
+                if !(was_empty && profile.iter().all(|val| val.is_none())) {
+                    self.$profile = profile;
+                }
+            }};
+        }
+
+        fill_profile!(wet_bulb, metfor::wet_bulb);
+        fill_profile!(theta_e, metfor::theta_e);
+
+        self
+    }
+
     /// Builder method for the wind profile.
     ///
     /// See `set_pressure_profile` for an example of usage, keeping in mind the units type may
@@ -308,6 +381,7 @@ impl Sounding {
         }
         Self {
             wind: profile,
+            qc: vec![],
             ..self
         }
     }
@@ -440,6 +514,8 @@ impl Sounding {
         }
 
         self.station_pressure = pressure;
+        self.qc = vec![];
+        self.derived = DerivedCache::default();
         self.update_sfc_wet_bulb_theta_e(); // updates wet bulb and theta_e profiles
         self
     }
@@ -484,6 +560,8 @@ impl Sounding {
         }
 
         self.sfc_temperature = sfc_temperature;
+        self.qc = vec![];
+        self.derived = DerivedCache::default();
         self.update_sfc_wet_bulb_theta_e(); // updates wet bulb and theta_e profiles
         self
     }
@@ -528,6 +606,8 @@ impl Sounding {
         }
 
         self.sfc_dew_point = sfc_dew_point;
+        self.qc = vec![];
+        self.derived = DerivedCache::default();
         self.update_sfc_wet_bulb_theta_e(); // updates wet bulb and theta_e profiles
         self
     }
@@ -575,7 +655,11 @@ impl Sounding {
             self.wind[0] = sfc_wind;
         }
 
-        Self { sfc_wind, ..self }
+        Self {
+            sfc_wind,
+            qc: vec![],
+            ..self
+        }
     }
 
     /// Get the surface wind.
@@ -644,7 +728,7 @@ impl Sounding {
 
         debug_assert!({
             if let Some(cld) = low_cloud.into_option() {
-                cld >= 0.0 && cld <= 1.0
+                (0.0..=1.0).contains(&cld)
             } else {
                 true
             }
@@ -679,7 +763,7 @@ impl Sounding {
 
         debug_assert!({
             if let Some(cld) = mid_cloud.into_option() {
-                cld >= 0.0 && cld <= 1.0
+                (0.0..=1.0).contains(&cld)
             } else {
                 true
             }
@@ -714,7 +798,7 @@ impl Sounding {
 
         debug_assert!({
             if let Some(cld) = high_cloud.into_option() {
-                cld >= 0.0 && cld <= 1.0
+                (0.0..=1.0).contains(&cld)
             } else {
                 true
             }
@@ -828,11 +912,7 @@ impl Sounding {
     /// ```
     #[inline]
     pub fn bottom_up<'a>(&'a self) -> impl Iterator<Item = DataRow> + 'a {
-        ProfileIterator {
-            next_idx: 0,
-            direction: 1,
-            src: self,
-        }
+        self.bottom_up_with_qc(QcFlag::Fail)
     }
 
     /// Get a top down iterator over the data rows. The last value returned is the surface values.
@@ -881,13 +961,50 @@ impl Sounding {
     /// ```
     #[inline]
     pub fn top_down<'a>(&'a self) -> impl Iterator<Item = DataRow> + 'a {
+        self.top_down_with_qc(QcFlag::Fail)
+    }
+
+    /// Get a bottom up iterator over the data rows with the values flagged worse than `allowed`
+    /// by quality control replaced by missing values. See `QualityControl` for an example.
+    #[inline]
+    pub fn bottom_up_with_qc<'a>(&'a self, allowed: QcFlag) -> impl Iterator<Item = DataRow> + 'a {
         ProfileIterator {
-            next_idx: (self.pressure.len() - 1) as isize,
+            next_idx: 0,
+            direction: 1,
+            allowed,
+            src: self,
+        }
+    }
+
+    /// Get a top down iterator over the data rows with the values flagged worse than `allowed` by
+    /// quality control replaced by missing values.
+    #[inline]
+    pub fn top_down_with_qc<'a>(&'a self, allowed: QcFlag) -> impl Iterator<Item = DataRow> + 'a {
+        ProfileIterator {
+            next_idx: self.pressure.len() as isize - 1,
             direction: -1,
+            allowed,
             src: self,
         }
     }
 
+    /// Builder method for the quality control flags, one for each level with the surface first.
+    ///
+    /// Usually these come from `QualityControl::apply`. The flags are matched to the levels by
+    /// index, so any builder method that changes the profiles clears them.
+    #[inline]
+    pub fn with_qc_flags(self, qc: Vec<QcFlags>) -> Self {
+        debug_assert!(qc.is_empty() || qc.len() == self.pressure.len());
+        Self { qc, ..self }
+    }
+
+    /// Get the quality control flags for each level, with the surface first. This is empty if
+    /// quality control hasn't been run.
+    #[inline]
+    pub fn qc_flags(&self) -> &[QcFlags] {
+        &self.qc
+    }
+
     /// Get a row of data values from this sounding.
     ///
     /// # Examples
@@ -962,7 +1079,7 @@ impl Sounding {
         let tgt_p = HectoPascal::from(target_p);
 
         let mut idx: usize = 0;
-        let mut best_abs_diff: f64 = ::std::f64::MAX;
+        let mut best_abs_diff: f64 = f64::MAX;
         for (i, &p_opt) in self.pressure.iter().enumerate() {
             if let Some(p) = p_opt.into_option() {
                 let abs_diff = (tgt_p.unpack() - p.unpack()).abs();
@@ -983,6 +1100,79 @@ impl Sounding {
         }
     }
 
+    /// Builder method to set the surface values and profiles from a list of rows, the first row
+    /// is the surface. A profile is only set if at least one row has a value for it, and the
+    /// surface values of the profiles are taken from the first row as is, rather than the defaults
+    /// the profile builders would use.
+    pub(crate) fn with_data_rows(self, rows: &[DataRow]) -> Self {
+        macro_rules! collect_profile {
+            ($var:ident) => {{
+                let profile: Vec<_> = rows.iter().skip(1).map(|row| row.$var).collect();
+                if rows.iter().any(|row| row.$var.is_some()) {
+                    profile
+                } else {
+                    vec![]
+                }
+            }};
+        }
+
+        let sfc = match rows.first() {
+            Some(sfc) => *sfc,
+            None => return self,
+        };
+
+        let mut snd = self
+            .with_station_pressure(sfc.pressure)
+            .with_sfc_temperature(sfc.temperature)
+            .with_sfc_dew_point(sfc.dew_point)
+            .with_sfc_wind(sfc.wind)
+            .with_pressure_profile(collect_profile!(pressure))
+            .with_temperature_profile(collect_profile!(temperature))
+            .with_wet_bulb_profile(collect_profile!(wet_bulb))
+            .with_dew_point_profile(collect_profile!(dew_point))
+            .with_theta_e_profile(collect_profile!(theta_e))
+            .with_wind_profile(collect_profile!(wind))
+            .with_pvv_profile(collect_profile!(pvv))
+            .with_height_profile(collect_profile!(height))
+            .with_cloud_fraction_profile(collect_profile!(cloud_fraction))
+            .with_qc_flags(vec![]);
+
+        macro_rules! set_surface {
+            ($($var:ident),*) => {
+                $(
+                    if let Some(val) = snd.$var.first_mut() {
+                        *val = sfc.$var;
+                    }
+                )*
+            };
+        }
+
+        set_surface!(wet_bulb, theta_e, pvv, height, cloud_fraction);
+
+        snd
+    }
+
+    /// Builder method to set all the profiles exactly as given, with the surface values in the
+    /// first element, instead of inserting the surface values like the profile builders do. This
+    /// is for restoring a copy of a sounding, so the surface values should be set first.
+    #[cfg(feature = "serde")]
+    pub(crate) fn with_verbatim_profiles(self, profiles: Profiles) -> Self {
+        Self {
+            pressure: profiles.pressure,
+            temperature: profiles.temperature,
+            wet_bulb: profiles.wet_bulb,
+            dew_point: profiles.dew_point,
+            theta_e: profiles.theta_e,
+            wind: profiles.wind,
+            pvv: profiles.pvv,
+            height: profiles.height,
+            cloud_fraction: profiles.cloud_fraction,
+            qc: vec![],
+            derived: DerivedCache::default(),
+            ..self
+        }
+    }
+
     #[inline]
     fn surface_wet_bulb(&self) -> Option<Celsius> {
         let sfc_t = self.sfc_temperature.into_option()?;
@@ -1006,6 +1196,12 @@ impl Sounding {
         self.station_info().elevation().into_option()
     }
 
+    /// The derived profiles calculated so far.
+    #[inline]
+    pub(crate) fn derived_cache(&self) -> &DerivedCache {
+        &self.derived
+    }
+
     #[inline]
     fn update_sfc_wet_bulb_theta_e(&mut self) {
         if let (Some(sfc_p), Some(sfc_t), Some(sfc_dp)) = (
@@ -1024,11 +1220,28 @@ impl Sounding {
     }
 }
 
+/// All the profiles of a sounding, with the surface values first. See
+/// `Sounding::with_verbatim_profiles`.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Profiles {
+    pub(crate) pressure: Vec<Optioned<HectoPascal>>,
+    pub(crate) temperature: Vec<Optioned<Celsius>>,
+    pub(crate) wet_bulb: Vec<Optioned<Celsius>>,
+    pub(crate) dew_point: Vec<Optioned<Celsius>>,
+    pub(crate) theta_e: Vec<Optioned<Kelvin>>,
+    pub(crate) wind: Vec<Optioned<WindSpdDir<Knots>>>,
+    pub(crate) pvv: Vec<Optioned<PaPS>>,
+    pub(crate) height: Vec<Optioned<Meters>>,
+    pub(crate) cloud_fraction: Vec<Optioned<f64>>,
+}
+
 /// Iterator over the data rows of a sounding. This may be a top down or bottom up iterator where
 /// either the last or first row returned is the surface data.
 struct ProfileIterator<'a> {
     next_idx: isize,
     direction: isize, // +1 for bottom up, -1 for top down
+    allowed: QcFlag,
     src: &'a Sounding,
 }
 
@@ -1037,7 +1250,14 @@ impl<'a> Iterator for ProfileIterator<'a> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        let result = self.src.data_row(self.next_idx as usize);
+        let idx = self.next_idx as usize;
+        let result = self
+            .src
+            .data_row(idx)
+            .map(|row| match self.src.qc.get(idx) {
+                Some(flags) => flags.mask(row, self.allowed),
+                None => row,
+            });
         self.next_idx += self.direction;
         result
     }
@@ -1099,4 +1319,85 @@ mod test {
             5
         );
     }
+
+    #[test]
+    fn test_qc_flags_cleared_by_builders() {
+        use crate::interpolation::on_pressure_levels;
+        use optional::some;
+
+        let mut flags = vec![QcFlags::default(); 5];
+        flags[1].temperature = QcFlag::Fail; // 1000 hPa
+        let snd = doctest::make_test_sounding().with_qc_flags(flags);
+        assert!(snd.data_row(1).unwrap().temperature.is_some());
+        assert!(snd
+            .bottom_up_with_qc(QcFlag::Pass)
+            .nth(1)
+            .unwrap()
+            .temperature
+            .is_none());
+
+        let interpolated = on_pressure_levels(&snd, &[HectoPascal(950.0), HectoPascal(900.0)]);
+        assert!(interpolated.qc_flags().is_empty());
+        assert!(interpolated
+            .bottom_up_with_qc(QcFlag::Pass)
+            .all(|row| row.temperature.is_some()));
+
+        let t = vec![some(Celsius(19.0)); 4];
+        let replaced = snd.clone().with_temperature_profile(t);
+        assert!(replaced.qc_flags().is_empty());
+        assert_eq!(
+            replaced
+                .bottom_up_with_qc(QcFlag::Pass)
+                .nth(1)
+                .unwrap()
+                .temperature,
+            some(Celsius(19.0))
+        );
+
+        assert!(snd
+            .clone()
+            .with_sfc_temperature(Celsius(22.0))
+            .qc_flags()
+            .is_empty());
+        assert_eq!(snd.fill_wet_bulb_and_theta_e().qc_flags().len(), 5);
+    }
+
+    #[test]
+    fn test_fill_keeps_profile_lengths() {
+        use optional::{none, some};
+
+        let dew_points = vec![some(Celsius(10.0)); 4];
+        let theta_e = vec![
+            none(),
+            some(Kelvin(330.0)),
+            none(),
+            none(),
+            none(),
+            some(Kelvin(340.0)),
+        ];
+        let snd = doctest::make_test_sounding()
+            .with_dew_point_profile(dew_points)
+            .with_theta_e_profile(theta_e)
+            .fill_wet_bulb_and_theta_e();
+
+        assert_eq!(snd.wet_bulb_profile().len(), 5);
+        let theta_e = snd.theta_e_profile();
+        assert_eq!(theta_e.len(), 7);
+        assert!(theta_e[0].is_none()); // No surface dew point.
+        assert!(theta_e[1].is_some());
+        assert_eq!(theta_e[2], some(Kelvin(330.0)));
+        assert!(theta_e[3].is_some() && theta_e[4].is_some());
+        assert!(theta_e[5].is_none()); // Above the top pressure level.
+        assert_eq!(theta_e[6], some(Kelvin(340.0)));
+
+        // Without pressures nothing is filled, or lost.
+        let snd = Sounding::new()
+            .with_temperature_profile(vec![some(Celsius(10.0)); 2])
+            .with_dew_point_profile(vec![some(Celsius(5.0)); 2])
+            .with_theta_e_profile(vec![some(Kelvin(300.0)); 2])
+            .fill_wet_bulb_and_theta_e();
+        assert!(snd.wet_bulb_profile().is_empty());
+        assert_eq!(snd.theta_e_profile().len(), 3);
+        assert_eq!(snd.theta_e_profile()[1], some(Kelvin(300.0)));
+    }
 }
@@ -178,4 +178,23 @@ impl StationInfo {
     pub fn elevation(&self) -> Optioned<Meters> {
         self.elevation
     }
+
+    /// Check if two soundings are from the same place. Missing values only match missing values.
+    pub(crate) fn is_same_station(&self, other: &StationInfo) -> bool {
+        const TOL: f64 = 1.0e-6;
+
+        let close = |a: f64, b: f64| (a - b).abs() < TOL;
+
+        self.num.into_option() == other.num.into_option()
+            && match (self.location, other.location) {
+                (Some((lat0, lon0)), Some((lat1, lon1))) => close(lat0, lat1) && close(lon0, lon1),
+                (None, None) => true,
+                _ => false,
+            }
+            && match (self.elevation.into_option(), other.elevation.into_option()) {
+                (Some(elev0), Some(elev1)) => close(elev0.0, elev1.0),
+                (None, None) => true,
+                _ => false,
+            }
+    }
 }
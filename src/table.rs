@@ -0,0 +1,311 @@
+//! Human readable, fixed width tables of sounding profiles.
+
+use std::fmt::{self, Display, Formatter};
+use std::io::Write;
+
+use crate::data_row::ProfileVariable;
+use crate::error::Result;
+use crate::sounding::Sounding;
+
+/// A column of a table, the variable, its labels, and how to format it.
+#[derive(Clone, Debug)]
+struct Column {
+    var: ProfileVariable,
+    label: String,
+    units: String,
+    convert: Option<fn(f64) -> f64>,
+    precision: usize,
+}
+
+impl Column {
+    fn new(var: ProfileVariable) -> Self {
+        use crate::data_row::ProfileVariable::*;
+
+        let (label, units, precision) = match var {
+            Pressure => ("PRES", "hPa", 1),
+            Temperature => ("TEMP", "C", 1),
+            WetBulb => ("WBT", "C", 1),
+            DewPoint => ("DWPT", "C", 1),
+            ThetaE => ("THTE", "K", 1),
+            WindDirection => ("DRCT", "deg", 0),
+            WindSpeed => ("SPED", "kt", 0),
+            Pvv => ("OMEG", "Pa/s", 3),
+            Height => ("HGHT", "m", 0),
+            CloudFraction => ("CLD", "%", 0),
+        };
+
+        Column {
+            var,
+            label: label.to_owned(),
+            units: units.to_owned(),
+            convert: None,
+            precision,
+        }
+    }
+}
+
+/// Format the profiles of a sounding as a fixed width table for people to read.
+///
+/// The table starts with a header describing the station and the valid time, followed by a row of
+/// column labels and a row of units. Missing values are shown as `----` by default, and the
+/// surface row is marked with `SFC`. `Sounding` implements `Display` with the default table.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, Fahrenheit};
+/// use sounding_base::{ProfileVariable, TableFormatter};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let snd = make_test_sounding();
+///
+/// let table = TableFormatter::new()
+///     .with_columns(&[ProfileVariable::Pressure, ProfileVariable::DewPoint])
+///     .with_converted_column(ProfileVariable::Temperature, "TEMP", "F", |t| {
+///         Fahrenheit::from(Celsius(t)).0
+///     })
+///     .format(&snd);
+///
+/// let mut lines = table.lines().skip(1); // Skip the station header.
+/// assert_eq!(lines.next().unwrap(), "       PRES  DWPT  TEMP");
+/// assert_eq!(lines.next().unwrap(), "        hPa     C     F");
+/// assert_eq!(lines.next().unwrap(), "SFC  1005.0  ----  69.8");
+/// assert_eq!(lines.next().unwrap(), "     1000.0  ----  68.0");
+///
+/// // The default table is used for `Display`.
+/// assert!(snd.to_string().contains("HGHT"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct TableFormatter {
+    columns: Vec<Column>,
+    missing: String,
+    top_down: bool,
+}
+
+impl Default for TableFormatter {
+    fn default() -> Self {
+        use crate::data_row::ProfileVariable::*;
+
+        TableFormatter {
+            columns: [
+                Pressure,
+                Height,
+                Temperature,
+                WetBulb,
+                DewPoint,
+                ThetaE,
+                WindDirection,
+                WindSpeed,
+                Pvv,
+                CloudFraction,
+            ]
+            .iter()
+            .cloned()
+            .map(Column::new)
+            .collect(),
+            missing: "----".to_owned(),
+            top_down: false,
+        }
+    }
+}
+
+impl TableFormatter {
+    /// Create a new formatter with all the variables as columns in their native units, listing
+    /// the levels from the surface up.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder method to select the columns, replacing any columns already selected.
+    #[inline]
+    pub fn with_columns(mut self, columns: &[ProfileVariable]) -> Self {
+        self.columns = columns.iter().cloned().map(Column::new).collect();
+        self
+    }
+
+    /// Builder method to append a column with the values converted to other units, e.g. for
+    /// temperatures in Fahrenheit.
+    #[inline]
+    pub fn with_converted_column(
+        mut self,
+        var: ProfileVariable,
+        label: &str,
+        units: &str,
+        convert: fn(f64) -> f64,
+    ) -> Self {
+        self.columns.push(Column {
+            label: label.to_owned(),
+            units: units.to_owned(),
+            convert: Some(convert),
+            ..Column::new(var)
+        });
+        self
+    }
+
+    /// Builder method to set the text shown for missing values.
+    #[inline]
+    pub fn with_missing_value(mut self, missing: &str) -> Self {
+        self.missing = missing.to_owned();
+        self
+    }
+
+    /// Builder method to list the levels from the top down, ending with the surface.
+    #[inline]
+    pub fn with_top_down(mut self, top_down: bool) -> Self {
+        self.top_down = top_down;
+        self
+    }
+
+    /// Write the table.
+    pub fn write<W: Write>(&self, snd: &Sounding, mut dest: W) -> Result<()> {
+        dest.write_all(self.format(snd).as_bytes())?;
+        Ok(())
+    }
+
+    /// Format the table as a string.
+    pub fn format(&self, snd: &Sounding) -> String {
+        const MARKER: &str = "SFC";
+
+        let mut rows: Vec<(usize, Vec<String>)> = (0..snd.pressure_profile().len())
+            .filter_map(|i| snd.data_row(i).map(|row| (i, row)))
+            .map(|(i, row)| {
+                let vals = self
+                    .columns
+                    .iter()
+                    .map(|col| {
+                        col.var
+                            .value(&row)
+                            .map(|val| col.convert.map(|f| f(val)).unwrap_or(val))
+                            .map(|val| format!("{:.*}", col.precision, val))
+                            .unwrap_or_else(|| self.missing.clone())
+                    })
+                    .collect();
+                (i, vals)
+            })
+            .collect();
+        if self.top_down {
+            rows.reverse();
+        }
+
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(j, col)| {
+                rows.iter()
+                    .map(|(_, vals)| vals[j].len())
+                    .chain(vec![col.label.len(), col.units.len()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let line = |marker: &str, vals: &mut dyn Iterator<Item = &str>| {
+            let mut line = format!("{:<width$}", marker, width = MARKER.len());
+            for (val, width) in vals.zip(&widths) {
+                line.push_str(&format!("  {:>width$}", val, width = width));
+            }
+            line.push('\n');
+            line
+        };
+
+        let mut table = header(snd);
+        table.push('\n');
+        table.push_str(&line(
+            "",
+            &mut self.columns.iter().map(|c| c.label.as_str()),
+        ));
+        table.push_str(&line(
+            "",
+            &mut self.columns.iter().map(|c| c.units.as_str()),
+        ));
+        for (i, vals) in &rows {
+            let marker = if *i == 0 { MARKER } else { "" };
+            table.push_str(&line(marker, &mut vals.iter().map(String::as_str)));
+        }
+
+        table
+    }
+}
+
+/// Describe the station and valid time of a sounding on one line.
+fn header(snd: &Sounding) -> String {
+    let stn = snd.station_info();
+
+    let mut header = match stn.station_num().into_option() {
+        Some(num) => format!("Station {}", num),
+        None => "Station unknown".to_owned(),
+    };
+    if let Some((lat, lon)) = stn.location() {
+        header.push_str(&format!(" ({:.2}, {:.2})", lat, lon));
+    }
+    if let Some(elev) = stn.elevation().into_option() {
+        header.push_str(&format!(" {:.0} m", elev.0));
+    }
+
+    if let Some(vt) = snd.valid_time() {
+        header.push_str(&vt.format("  Valid %Y-%m-%d %H:%MZ").to_string());
+    }
+    if let Some(lt) = snd.lead_time().into_option() {
+        header.push_str(&format!(" F{:03}", lt));
+    }
+
+    header
+}
+
+impl Display for Sounding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&TableFormatter::default().format(self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+    use crate::station_info::StationInfo;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_display() {
+        let snd = make_test_sounding()
+            .with_station_info(StationInfo::new().with_station(72776))
+            .with_valid_time(
+                NaiveDate::from_ymd_opt(2018, 3, 8)
+                    .unwrap()
+                    .and_hms_opt(12, 0, 0)
+                    .unwrap(),
+            )
+            .with_lead_time(6);
+
+        let text = snd.to_string();
+        assert_eq!(text, TableFormatter::new().format(&snd));
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "Station 72776  Valid 2018-03-08 12:00Z F006");
+        assert!(lines[1].split_whitespace().eq(vec![
+            "PRES", "HGHT", "TEMP", "WBT", "DWPT", "THTE", "DRCT", "SPED", "OMEG", "CLD"
+        ]));
+        assert_eq!(lines.len(), 3 + snd.pressure_profile().len());
+        assert!(lines[3].starts_with("SFC"));
+        assert!(lines[3].contains("1005.0"));
+        assert!(lines[4].starts_with("   "));
+    }
+
+    #[test]
+    fn test_top_down() {
+        let snd = make_test_sounding();
+        let table = TableFormatter::new()
+            .with_columns(&[ProfileVariable::Pressure])
+            .with_top_down(true)
+            .format(&snd);
+
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[3].trim(), "700.0");
+        assert_eq!(
+            lines.last().unwrap().split_whitespace().collect::<Vec<_>>(),
+            vec!["SFC", "1005.0"]
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Reduce high resolution profiles to their significant levels.
+
+use metfor::{CelsiusDiff, Knots, Quantity, WindUV};
+
+use crate::data_row::DataRow;
+use crate::interpolation::interpolate_rows;
+use crate::sounding::Sounding;
+
+/// Select the significant levels of a sounding, in the style of the WMO rules for radiosonde
+/// reports.
+///
+/// Starting from the surface and the top level, the level that departs the most from a straight
+/// line, in log(p), between the levels already selected is added until every level can be
+/// reproduced by linear interpolation within the tolerances. The tolerances apply to temperature,
+/// relative humidity, and the wind vector. The defaults are 1 C, 15 percent, and 10 knots.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal};
+/// use optional::some;
+/// use sounding_base::{SignificantLevels, Sounding};
+///
+/// // Evenly spaced in log(p), with a temperature spike near 900 hPa.
+/// let p: Vec<_> = (0..=30).map(|i| some(HectoPascal(1000.0 * 0.99f64.powi(i)))).collect();
+/// let t: Vec<_> = (0..=30)
+///     .map(|i| if i == 10 { 5.0 } else { 20.0 - i as f64 })
+///     .map(|t| some(Celsius(t)))
+///     .collect();
+/// let snd = Sounding::new().with_pressure_profile(p).with_temperature_profile(t);
+///
+/// let (thinned, kept) = SignificantLevels::new().thin(&snd);
+/// assert_eq!(kept, vec![0, 1, 10, 11, 12, 31]);
+/// assert_eq!(thinned.pressure_profile().len(), kept.len());
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct SignificantLevels {
+    temperature: f64,
+    relative_humidity: f64,
+    wind: f64,
+}
+
+impl Default for SignificantLevels {
+    fn default() -> Self {
+        SignificantLevels {
+            temperature: 1.0,
+            relative_humidity: 15.0,
+            wind: 10.0,
+        }
+    }
+}
+
+impl SignificantLevels {
+    /// Create a selector with the default tolerances.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder method for the temperature tolerance.
+    #[inline]
+    pub fn with_temperature_tolerance<T>(mut self, tolerance: T) -> Self
+    where
+        CelsiusDiff: From<T>,
+    {
+        self.temperature = CelsiusDiff::from(tolerance).unpack();
+        self
+    }
+
+    /// Builder method for the relative humidity tolerance in percent.
+    #[inline]
+    pub fn with_humidity_tolerance(mut self, tolerance: f64) -> Self {
+        self.relative_humidity = tolerance;
+        self
+    }
+
+    /// Builder method for the tolerance of the wind vector difference.
+    #[inline]
+    pub fn with_wind_tolerance<S>(mut self, tolerance: S) -> Self
+    where
+        S: metfor::Speed,
+        Knots: From<S>,
+    {
+        self.wind = Knots::from(tolerance).unpack();
+        self
+    }
+
+    /// Thin a sounding to its significant levels.
+    ///
+    /// Returns the thinned sounding and the indices of the levels kept, where index 0 is the
+    /// surface, which is always kept. Levels without a pressure are dropped.
+    pub fn thin(&self, snd: &Sounding) -> (Sounding, Vec<usize>) {
+        let rows: Vec<(usize, DataRow)> = snd
+            .bottom_up()
+            .enumerate()
+            .filter(|(_, row)| row.pressure.is_some())
+            .collect();
+
+        let mut kept = vec![false; rows.len()];
+        if let Some(last) = kept.last_mut() {
+            *last = true;
+        }
+        if let Some(first) = kept.first_mut() {
+            *first = true;
+        }
+
+        let mut segments = vec![(0, rows.len().saturating_sub(1))];
+        while let Some((bottom, top)) = segments.pop() {
+            if top <= bottom + 1 {
+                continue;
+            }
+
+            let worst = (bottom + 1..top)
+                .map(|i| (i, self.departure(&rows[bottom].1, &rows[top].1, &rows[i].1)))
+                .fold(
+                    (bottom, 1.0),
+                    |worst, next| {
+                        if next.1 > worst.1 {
+                            next
+                        } else {
+                            worst
+                        }
+                    },
+                );
+
+            if worst.0 != bottom {
+                kept[worst.0] = true;
+                segments.push((bottom, worst.0));
+                segments.push((worst.0, top));
+            }
+        }
+
+        let mut indices: Vec<usize> = rows
+            .iter()
+            .zip(&kept)
+            .filter(|(_, &keep)| keep)
+            .map(|((idx, _), _)| *idx)
+            .collect();
+        if indices.first() != Some(&0) {
+            indices.insert(0, 0);
+        }
+
+        let thinned_rows: Vec<DataRow> = indices
+            .iter()
+            .map(|&idx| snd.data_row(idx).unwrap_or_default())
+            .collect();
+
+        (snd.clone().with_data_rows(&thinned_rows), indices)
+    }
+
+    /// The largest departure of `row` from a line between `bottom` and `top`, as a fraction of the
+    /// tolerances.
+    fn departure(&self, bottom: &DataRow, top: &DataRow, row: &DataRow) -> f64 {
+        let ln_p = |row: &DataRow| row.pressure.map_or(f64::NAN, |p| p.unpack().ln());
+        let w = (ln_p(bottom) - ln_p(row)) / (ln_p(bottom) - ln_p(top));
+        let line = interpolate_rows(bottom, top, w);
+
+        let mut departure: f64 = 0.0;
+
+        if let (Some(t0), Some(t1)) = (
+            line.temperature.into_option(),
+            row.temperature.into_option(),
+        ) {
+            departure = departure.max((t1.unpack() - t0.unpack()).abs() / self.temperature);
+        }
+
+        let rh = |row: &DataRow| {
+            let t = row.temperature.into_option()?;
+            let dp = row.dew_point.into_option()?;
+            metfor::rh(t, dp).map(|rh| rh * 100.0)
+        };
+        if let (Some(rh_bottom), Some(rh_top), Some(rh)) = (rh(bottom), rh(top), rh(row)) {
+            let rh_line = rh_bottom + w * (rh_top - rh_bottom);
+            departure = departure.max((rh - rh_line).abs() / self.relative_humidity);
+        }
+
+        if let (Some(w0), Some(w1)) = (line.wind.into_option(), row.wind.into_option()) {
+            let w0: WindUV<Knots> = WindUV::from(w0);
+            let w1: WindUV<Knots> = WindUV::from(w1);
+            let diff = (w1.u.unpack() - w0.u.unpack()).hypot(w1.v.unpack() - w0.v.unpack());
+            departure = departure.max(diff / self.wind);
+        }
+
+        departure
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+    use metfor::{Celsius, HectoPascal, WindSpdDir};
+    use optional::{none, some};
+
+    /// Ten levels evenly spaced in log(p) with a constant temperature.
+    fn isothermal() -> Sounding {
+        let p: Vec<_> = (0..10)
+            .map(|i| some(HectoPascal(1000.0 * 0.99f64.powi(i))))
+            .collect();
+        Sounding::new()
+            .with_pressure_profile(p)
+            .with_temperature_profile(vec![some(Celsius(10.0)); 10])
+    }
+
+    #[test]
+    fn test_humidity_tolerance() {
+        let dp: Vec<_> = (0..10)
+            .map(|i| some(Celsius(if i == 4 { -10.0 } else { 5.0 })))
+            .collect();
+        let snd = isothermal().with_dew_point_profile(dp);
+
+        let (_, kept) = SignificantLevels::new().thin(&snd);
+        assert_eq!(kept, vec![0, 1, 4, 5, 6, 10]);
+
+        let (_, kept) = SignificantLevels::new()
+            .with_humidity_tolerance(100.0)
+            .thin(&snd);
+        assert_eq!(kept, vec![0, 1, 10]);
+    }
+
+    #[test]
+    fn test_wind_tolerance() {
+        let wind: Vec<_> = (0..10)
+            .map(|i| {
+                some(WindSpdDir {
+                    speed: Knots(if i == 6 { 40.0 } else { 20.0 }),
+                    direction: 270.0,
+                })
+            })
+            .collect();
+        let snd = isothermal().with_wind_profile(wind);
+
+        let (thinned, kept) = SignificantLevels::new().thin(&snd);
+        assert_eq!(kept, vec![0, 1, 6, 7, 8, 10]);
+        assert_eq!(thinned.wind_profile()[3].unwrap().speed, Knots(40.0));
+
+        let (_, kept) = SignificantLevels::new()
+            .with_wind_tolerance(Knots(25.0))
+            .thin(&snd);
+        assert_eq!(kept, vec![0, 1, 10]);
+    }
+
+    #[test]
+    fn test_surface_without_pressure() {
+        let snd = make_test_sounding().with_station_pressure(none::<HectoPascal>());
+
+        let (thinned, kept) = SignificantLevels::new().thin(&snd);
+        assert_eq!(kept[0], 0);
+        assert_eq!(kept.len(), thinned.pressure_profile().len());
+        assert!(thinned.station_pressure().is_none());
+        assert_eq!(thinned.sfc_temperature(), some(Celsius(21.0)));
+        assert_eq!(thinned.pressure_profile()[1], some(HectoPascal(1000.0)));
+    }
+}
@@ -0,0 +1,504 @@
+//! Compare forecast soundings with observed soundings.
+
+use metfor::{CelsiusDiff, HectoPascal, Knots, Meters, Quantity, WindUV};
+use optional::{none, Optioned};
+
+use crate::data_row::DataRow;
+use crate::error::{Result, SoundingError};
+use crate::interpolation::interpolate_at_pressure;
+use crate::sounding::Sounding;
+
+/// The variables compared by a `Comparison`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VerificationVariable {
+    /// Temperature error in C.
+    Temperature,
+    /// Dew point error in C.
+    DewPoint,
+    /// Wind vector error in knots, which is the speed of the forecast minus observed wind vector.
+    /// This is never negative.
+    Wind,
+    /// Geopotential height error in meters.
+    Height,
+}
+
+impl VerificationVariable {
+    /// All the variables.
+    pub const ALL: [VerificationVariable; 4] = [
+        VerificationVariable::Temperature,
+        VerificationVariable::DewPoint,
+        VerificationVariable::Wind,
+        VerificationVariable::Height,
+    ];
+
+    #[inline]
+    fn index(self) -> usize {
+        match self {
+            VerificationVariable::Temperature => 0,
+            VerificationVariable::DewPoint => 1,
+            VerificationVariable::Wind => 2,
+            VerificationVariable::Height => 3,
+        }
+    }
+}
+
+/// The forecast minus observed values at a single pressure level.
+#[derive(Clone, Copy, Debug)]
+pub struct LevelError {
+    /// Pressure level in hPa
+    pub pressure: HectoPascal,
+    /// Temperature error
+    pub temperature: Optioned<CelsiusDiff>,
+    /// Dew point error
+    pub dew_point: Optioned<CelsiusDiff>,
+    /// Wind vector error
+    pub wind: Optioned<WindUV<Knots>>,
+    /// Geopotential height error
+    pub height: Optioned<Meters>,
+}
+
+impl LevelError {
+    fn new(pressure: HectoPascal, forecast: &DataRow, observed: &DataRow) -> Self {
+        macro_rules! diff {
+            ($field:ident) => {
+                forecast
+                    .$field
+                    .into_option()
+                    .and_then(|f| observed.$field.map(|o| f - o))
+                    .into()
+            };
+        }
+
+        let wind = match (forecast.wind.into_option(), observed.wind.into_option()) {
+            (Some(f), Some(o)) => {
+                let f = WindUV::<Knots>::from(f);
+                let o = WindUV::<Knots>::from(o);
+                WindUV {
+                    u: Knots(f.u.unpack() - o.u.unpack()),
+                    v: Knots(f.v.unpack() - o.v.unpack()),
+                }
+                .into()
+            }
+            _ => none(),
+        };
+
+        LevelError {
+            pressure,
+            temperature: diff!(temperature),
+            dew_point: diff!(dew_point),
+            wind,
+            height: diff!(height),
+        }
+    }
+
+    /// The forecast minus observed error of a variable in the units documented by
+    /// `VerificationVariable`.
+    pub fn error(&self, var: VerificationVariable) -> Optioned<f64> {
+        match var {
+            VerificationVariable::Temperature => self.temperature.map_t(Quantity::unpack),
+            VerificationVariable::DewPoint => self.dew_point.map_t(Quantity::unpack),
+            VerificationVariable::Wind => self.wind.map_t(|w| w.u.unpack().hypot(w.v.unpack())),
+            VerificationVariable::Height => self.height.map_t(Quantity::unpack),
+        }
+    }
+
+    /// The absolute error of a variable.
+    #[inline]
+    pub fn abs_error(&self, var: VerificationVariable) -> Optioned<f64> {
+        self.error(var).map_t(f64::abs)
+    }
+}
+
+/// A level by level comparison of a forecast sounding with an observed sounding for the same
+/// station and valid time.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{Celsius, HectoPascal};
+/// use sounding_base::{Comparison, VerificationVariable};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let observed = make_test_sounding();
+/// let temperatures: Vec<_> = observed
+///     .temperature_profile()
+///     .iter()
+///     .skip(1) // The surface value is added by the builder.
+///     .map(|t| t.map_t(|t| Celsius(t.0 + 1.0)))
+///     .collect();
+/// let forecast = observed.clone().with_temperature_profile(temperatures);
+///
+/// let cmp = Comparison::new(&forecast, &observed).unwrap();
+/// assert_eq!(cmp.levels()[0].pressure, HectoPascal(1000.0));
+/// assert_eq!(cmp.levels()[0].error(VerificationVariable::Temperature).unwrap(), 1.0);
+/// assert!(cmp.levels()[0].error(VerificationVariable::DewPoint).is_none());
+///
+/// let rmse = cmp.rmse(VerificationVariable::Temperature, HectoPascal(1000.0), HectoPascal(700.0));
+/// assert_eq!(rmse.unwrap(), 1.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    levels: Vec<LevelError>,
+}
+
+impl Comparison {
+    /// Compare at the pressure levels of the observed sounding, not including the surface, by
+    /// interpolating the forecast to those levels.
+    ///
+    /// This fails if the soundings are not for the same station and valid time.
+    pub fn new(forecast: &Sounding, observed: &Sounding) -> Result<Self> {
+        check_compatible(forecast, observed)?;
+
+        let levels = observed
+            .bottom_up()
+            .skip(1)
+            .filter_map(|obs| {
+                let p = obs.pressure.into_option()?;
+                let fc = interpolate_at_pressure(forecast, p)?;
+                Some(LevelError::new(p, &fc, &obs))
+            })
+            .collect();
+
+        Ok(Comparison { levels })
+    }
+
+    /// Compare at the given pressure levels by interpolating both soundings to those levels.
+    ///
+    /// Levels that aren't in both soundings are left out. This fails if the soundings are not for
+    /// the same station and valid time.
+    pub fn on_levels(
+        forecast: &Sounding,
+        observed: &Sounding,
+        levels: &[HectoPascal],
+    ) -> Result<Self> {
+        check_compatible(forecast, observed)?;
+
+        let levels = levels
+            .iter()
+            .filter_map(|&p| {
+                let fc = interpolate_at_pressure(forecast, p)?;
+                let obs = interpolate_at_pressure(observed, p)?;
+                Some(LevelError::new(p, &fc, &obs))
+            })
+            .collect();
+
+        Ok(Comparison { levels })
+    }
+
+    /// The errors at each level, from the bottom up.
+    #[inline]
+    pub fn levels(&self) -> &[LevelError] {
+        &self.levels
+    }
+
+    /// The root mean square error of a variable over the levels from `bottom` to `top`, inclusive.
+    pub fn rmse(
+        &self,
+        var: VerificationVariable,
+        bottom: HectoPascal,
+        top: HectoPascal,
+    ) -> Optioned<f64> {
+        let mut count = 0;
+        let mut sum_sq = 0.0;
+        for err in self
+            .levels
+            .iter()
+            .filter(|lvl| lvl.pressure <= bottom && lvl.pressure >= top)
+            .filter_map(|lvl| lvl.error(var).into_option())
+        {
+            count += 1;
+            sum_sq += err * err;
+        }
+
+        if count > 0 {
+            Optioned::some((sum_sq / count as f64).sqrt())
+        } else {
+            none()
+        }
+    }
+}
+
+fn check_compatible(forecast: &Sounding, observed: &Sounding) -> Result<()> {
+    if !forecast
+        .station_info()
+        .is_same_station(&observed.station_info())
+    {
+        return Err(SoundingError::Incompatible(
+            "forecast and observation are from different stations".to_owned(),
+        ));
+    }
+    if forecast.valid_time() != observed.valid_time() {
+        return Err(SoundingError::Incompatible(
+            "forecast and observation have different valid times".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Summary statistics of the errors of one variable at one level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ErrorStats {
+    /// The number of forecast and observation pairs.
+    pub count: usize,
+    /// The mean error. For the wind this is the speed of the mean vector error.
+    pub bias: f64,
+    /// The mean absolute error.
+    pub mean_abs_error: f64,
+    /// The root mean square error.
+    pub rmse: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Sums {
+    count: usize,
+    sum: f64,
+    sum_abs: f64,
+    sum_sq: f64,
+}
+
+/// Accumulate error statistics over many forecast and observation pairs on fixed pressure levels.
+///
+/// # Examples
+///
+/// ```rust
+/// use metfor::{HectoPascal, Knots, WindSpdDir};
+/// use optional::some;
+/// use sounding_base::{ErrorAccumulator, VerificationVariable};
+/// # use sounding_base::doctest::make_test_sounding;
+///
+/// let levels = [HectoPascal(850.0), HectoPascal(700.0)];
+/// let mut acc = ErrorAccumulator::new(&levels);
+///
+/// let observed = make_test_sounding();
+/// acc.add(&observed, &observed).unwrap();
+///
+/// // A 10 knot wind from the west forecast where a 10 knot wind from the south was observed.
+/// let wind = |direction| vec![some(WindSpdDir { speed: Knots(10.0), direction }); 4];
+/// let observed = observed.with_wind_profile(wind(180.0));
+/// let forecast = observed.clone().with_wind_profile(wind(270.0));
+/// acc.add(&forecast, &observed).unwrap();
+///
+/// let stats = acc.stats(VerificationVariable::Temperature, 0).unwrap();
+/// assert_eq!(stats.count, 2);
+/// assert_eq!(stats.rmse, 0.0);
+///
+/// assert_eq!(acc.pairs(), 2);
+/// let wind = acc.stats(VerificationVariable::Wind, 1).unwrap();
+/// assert_eq!(wind.count, 1);
+/// assert!((wind.rmse - 200.0f64.sqrt()).abs() < 1.0e-9);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ErrorAccumulator {
+    levels: Vec<HectoPascal>,
+    pairs: usize,
+    // Indexed by level, then variable.
+    sums: Vec<[Sums; 4]>,
+    wind_sums: Vec<(f64, f64)>,
+}
+
+impl ErrorAccumulator {
+    /// Create an accumulator for the given pressure levels.
+    pub fn new(levels: &[HectoPascal]) -> Self {
+        ErrorAccumulator {
+            levels: levels.to_vec(),
+            pairs: 0,
+            sums: vec![Default::default(); levels.len()],
+            wind_sums: vec![(0.0, 0.0); levels.len()],
+        }
+    }
+
+    /// Compare a forecast with an observation and add the errors to the totals.
+    ///
+    /// This fails if the soundings are not for the same station and valid time.
+    pub fn add(&mut self, forecast: &Sounding, observed: &Sounding) -> Result<()> {
+        let cmp = Comparison::on_levels(forecast, observed, &self.levels)?;
+
+        for lvl in cmp.levels() {
+            let idx = match self.levels.iter().position(|&p| p == lvl.pressure) {
+                Some(idx) => idx,
+                None => continue,
+            };
+
+            for &var in &VerificationVariable::ALL {
+                if let Some(err) = lvl.error(var).into_option() {
+                    let sums = &mut self.sums[idx][var.index()];
+                    sums.count += 1;
+                    sums.sum += err;
+                    sums.sum_abs += err.abs();
+                    sums.sum_sq += err * err;
+                }
+            }
+
+            if let Some(wind) = lvl.wind.into_option() {
+                self.wind_sums[idx].0 += wind.u.unpack();
+                self.wind_sums[idx].1 += wind.v.unpack();
+            }
+        }
+
+        self.pairs += 1;
+        Ok(())
+    }
+
+    /// The pressure levels.
+    #[inline]
+    pub fn levels(&self) -> &[HectoPascal] {
+        &self.levels
+    }
+
+    /// The number of forecast and observation pairs added.
+    #[inline]
+    pub fn pairs(&self) -> usize {
+        self.pairs
+    }
+
+    /// The statistics of a variable at a level index, `None` if there are no errors there.
+    pub fn stats(&self, var: VerificationVariable, level_idx: usize) -> Option<ErrorStats> {
+        let sums = self.sums.get(level_idx)?[var.index()];
+        if sums.count == 0 {
+            return None;
+        }
+
+        let n = sums.count as f64;
+        let bias = if var == VerificationVariable::Wind {
+            self.mean_wind_error(level_idx)
+                .map(|w| w.u.unpack().hypot(w.v.unpack()))?
+        } else {
+            sums.sum / n
+        };
+
+        Some(ErrorStats {
+            count: sums.count,
+            bias,
+            mean_abs_error: sums.sum_abs / n,
+            rmse: (sums.sum_sq / n).sqrt(),
+        })
+    }
+
+    /// The mean wind vector error at a level index.
+    pub fn mean_wind_error(&self, level_idx: usize) -> Option<WindUV<Knots>> {
+        let count = self.sums.get(level_idx)?[VerificationVariable::Wind.index()].count;
+        if count == 0 {
+            return None;
+        }
+
+        let (u, v) = self.wind_sums[level_idx];
+        Some(WindUV {
+            u: Knots(u / count as f64),
+            v: Knots(v / count as f64),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+    use metfor::Celsius;
+    use optional::some;
+
+    /// The test sounding with the temperatures above the surface shifted by `offsets`.
+    fn shifted(offsets: [f64; 4]) -> Sounding {
+        let snd = make_test_sounding();
+        let t: Vec<_> = snd.temperature_profile()[1..]
+            .iter()
+            .zip(offsets.iter())
+            .map(|(t, dt)| t.map_t(|t| Celsius(t.0 + dt)))
+            .collect();
+        snd.with_temperature_profile(t)
+    }
+
+    #[test]
+    fn test_comparison_levels() {
+        let observed = make_test_sounding()
+            .with_pressure_profile(vec![
+                some(HectoPascal(1000.0)),
+                none(),
+                some(HectoPascal(850.0)),
+                some(HectoPascal(600.0)),
+            ])
+            .with_dew_point_profile(vec![some(Celsius(10.0)); 4]);
+        let forecast = shifted([1.0, 1.0, -2.0, 1.0]);
+
+        // The level without a pressure and the level above the top of the forecast are left out.
+        let cmp = Comparison::new(&forecast, &observed).unwrap();
+        let pressures: Vec<_> = cmp.levels().iter().map(|lvl| lvl.pressure).collect();
+        assert_eq!(pressures, vec![HectoPascal(1000.0), HectoPascal(850.0)]);
+
+        let temperature = |lvl: &LevelError| lvl.error(VerificationVariable::Temperature).unwrap();
+        assert_eq!(temperature(&cmp.levels()[0]), 1.0);
+        assert_eq!(temperature(&cmp.levels()[1]), -2.0);
+        assert_eq!(
+            cmp.levels()[1]
+                .abs_error(VerificationVariable::Temperature)
+                .unwrap(),
+            2.0
+        );
+        // Missing in the forecast.
+        assert!(cmp.levels()[0]
+            .error(VerificationVariable::DewPoint)
+            .is_none());
+
+        let levels = [HectoPascal(925.0), HectoPascal(500.0)];
+        let cmp = Comparison::on_levels(&forecast, &observed, &levels).unwrap();
+        assert_eq!(cmp.levels().len(), 1);
+        assert_eq!(cmp.levels()[0].pressure, HectoPascal(925.0));
+    }
+
+    #[test]
+    fn test_accumulated_stats() {
+        let levels = [HectoPascal(850.0), HectoPascal(700.0), HectoPascal(500.0)];
+        let mut acc = ErrorAccumulator::new(&levels);
+
+        let observed = make_test_sounding();
+        acc.add(&shifted([0.0, 0.0, 1.0, 2.0]), &observed).unwrap();
+        acc.add(&shifted([0.0, 0.0, -3.0, 0.0]), &observed).unwrap();
+        // The forecast is missing 700 hPa.
+        let forecast = shifted([0.0, 0.0, 2.0, 0.0]).with_temperature_profile(vec![
+            some(Celsius(20.0)),
+            some(Celsius(18.0)),
+            some(Celsius(12.0)),
+            none(),
+        ]);
+        acc.add(&forecast, &observed).unwrap();
+
+        assert_eq!(acc.pairs(), 3);
+
+        let stats = acc.stats(VerificationVariable::Temperature, 0).unwrap();
+        assert_eq!(stats.count, 3);
+        assert!(stats.bias.abs() < 1.0e-9);
+        assert!((stats.mean_abs_error - 2.0).abs() < 1.0e-9);
+        assert!((stats.rmse - (14.0f64 / 3.0).sqrt()).abs() < 1.0e-9);
+
+        let stats = acc.stats(VerificationVariable::Temperature, 1).unwrap();
+        assert_eq!(stats.count, 2);
+        assert!((stats.bias - 1.0).abs() < 1.0e-9);
+        assert!((stats.rmse - 2.0f64.sqrt()).abs() < 1.0e-9);
+
+        // Neither sounding reaches 500 hPa, and there are no dew points.
+        assert!(acc.stats(VerificationVariable::Temperature, 2).is_none());
+        assert!(acc.stats(VerificationVariable::DewPoint, 0).is_none());
+        assert!(acc.mean_wind_error(0).is_none());
+        assert!(acc.stats(VerificationVariable::Temperature, 3).is_none());
+    }
+
+    #[test]
+    fn test_incompatible() {
+        let observed = make_test_sounding().with_lead_time(0);
+        let forecast = make_test_sounding().with_valid_time(
+            chrono::NaiveDate::from_ymd_opt(2019, 6, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+        );
+
+        assert!(matches!(
+            Comparison::new(&forecast, &observed),
+            Err(SoundingError::Incompatible(_))
+        ));
+
+        let mut acc = ErrorAccumulator::new(&[HectoPascal(850.0)]);
+        assert!(acc.add(&forecast, &observed).is_err());
+        assert_eq!(acc.pairs(), 0);
+    }
+}
@@ -0,0 +1,191 @@
+//! Convert between the pressure vertical velocity and the vertical velocity in m/s.
+//!
+//! The conversion uses the hydrostatic approximation w = -omega / (rho g). The density is
+//! calculated from the virtual temperature if there is a dew point, or from the temperature if
+//! not. Positive vertical velocities are rising motion.
+
+use metfor::{MetersPSec, PaPS, Quantity, Speed};
+use optional::{Noned, Optioned};
+
+use crate::data_row::DataRow;
+use crate::derived::density;
+use crate::sounding::Sounding;
+
+impl Sounding {
+    /// Get the vertical velocity profile, calculated from the pressure vertical velocity.
+    ///
+    /// A value is missing if the pressure vertical velocity, temperature, or pressure is missing,
+    /// and if there is no pressure vertical velocity profile the result is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::{MetersPSec, PaPS};
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let snd = make_test_sounding();
+    /// assert!(snd.vertical_velocity_profile().is_empty());
+    ///
+    /// let pvv = vec![-1.0, 0.0, 1.0, -2.0].into_iter().map(PaPS).map(some).collect();
+    /// let snd = snd.with_pvv_profile(pvv);
+    ///
+    /// let w = snd.vertical_velocity_profile();
+    /// assert_eq!(w[0].unwrap(), MetersPSec(0.0)); // Surface
+    ///
+    /// // About 1.2 kg/m^3 at 1000 hPa and 20 C.
+    /// let w = w[1].unwrap().0;
+    /// assert!(w > 0.08 && w < 0.09);
+    /// ```
+    pub fn vertical_velocity_profile(&self) -> Vec<Optioned<MetersPSec>> {
+        if self.pvv_profile().is_empty() {
+            return vec![];
+        }
+
+        self.bottom_up()
+            .map(|row| vertical_velocity(&row).into())
+            .collect()
+    }
+
+    /// Builder method for the pressure vertical velocity profile from the vertical velocity.
+    ///
+    /// The vertical velocity is converted with the density at each level, so the pressure and
+    /// temperature profiles must be set first, and values at levels without them are missing. Like
+    /// `with_pvv_profile`, the profile does not include the surface. Values beyond the top of the
+    /// pressure profile are dropped. The QC flags are ignored for the conversion, so every value
+    /// that can be converted is kept.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::{Knots, MetersPSec};
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let w = vec![0.1, 0.5, -0.2, 1.0].into_iter().map(MetersPSec).map(some).collect();
+    /// let snd = make_test_sounding().with_vertical_velocity_profile(w);
+    ///
+    /// assert!(snd.pvv_profile()[1].unwrap().0 < 0.0); // Rising motion is negative omega.
+    ///
+    /// let w = snd.vertical_velocity_profile();
+    /// assert!((w[2].unwrap().0 - 0.5).abs() < 1.0e-9);
+    ///
+    /// // Other units work too.
+    /// let w = vec![some(Knots(1.0)); 4];
+    /// let snd = make_test_sounding().with_vertical_velocity_profile(w);
+    /// assert!((snd.vertical_velocity_profile()[4].unwrap().0 - 0.514).abs() < 1.0e-3);
+    /// ```
+    pub fn with_vertical_velocity_profile<S>(self, profile: Vec<Optioned<S>>) -> Self
+    where
+        S: Speed + Noned,
+        MetersPSec: From<S>,
+    {
+        let levels = self.pressure_profile().len().saturating_sub(1);
+
+        let pvv = profile
+            .into_iter()
+            .take(levels)
+            .enumerate()
+            .map(|(i, w)| {
+                let w = MetersPSec::from(w.into_option()?).unpack();
+                let rho = density(&self.data_row(i + 1)?)?;
+
+                Some(PaPS(w * rho * metfor::g))
+            })
+            .map(Optioned::from)
+            .collect();
+
+        self.with_pvv_profile(pvv)
+    }
+
+    /// Find the level with the strongest rising motion, and the vertical velocity there.
+    ///
+    /// Returns `None` if no level is rising.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::{HectoPascal, MetersPSec};
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let w = vec![0.1, 0.5, -0.2, 0.3].into_iter().map(MetersPSec).map(some).collect();
+    /// let snd = make_test_sounding().with_vertical_velocity_profile(w);
+    ///
+    /// let (row, w) = snd.max_ascent_level().unwrap();
+    /// assert_eq!(row.pressure.unwrap(), HectoPascal(925.0));
+    /// assert!((w.0 - 0.5).abs() < 1.0e-9);
+    ///
+    /// let (row, w) = snd.max_descent_level().unwrap();
+    /// assert_eq!(row.pressure.unwrap(), HectoPascal(850.0));
+    /// assert!((w.0 + 0.2).abs() < 1.0e-9);
+    /// ```
+    pub fn max_ascent_level(&self) -> Option<(DataRow, MetersPSec)> {
+        self.extreme_vertical_velocity_level(|w| w)
+    }
+
+    /// Find the level with the strongest sinking motion, and the vertical velocity there, which is
+    /// negative.
+    ///
+    /// Returns `None` if no level is sinking. See `max_ascent_level` for an example.
+    pub fn max_descent_level(&self) -> Option<(DataRow, MetersPSec)> {
+        self.extreme_vertical_velocity_level(|w| -w)
+    }
+
+    /// Find the level with the largest positive value of `key` applied to the vertical velocity.
+    fn extreme_vertical_velocity_level<F>(&self, key: F) -> Option<(DataRow, MetersPSec)>
+    where
+        F: Fn(f64) -> f64,
+    {
+        self.bottom_up()
+            .filter_map(|row| Some((row, vertical_velocity(&row)?)))
+            .filter(|&(_, w)| key(w.unpack()) > 0.0)
+            .fold(
+                None,
+                |best: Option<(DataRow, MetersPSec)>, next| match best {
+                    Some(best) if key(best.1.unpack()) >= key(next.1.unpack()) => Some(best),
+                    _ => Some(next),
+                },
+            )
+    }
+}
+
+fn vertical_velocity(row: &DataRow) -> Option<MetersPSec> {
+    let omega = row.pvv.into_option()?.unpack();
+    let rho = density(row)?;
+
+    Some(MetersPSec(omega / (rho * metfor::g)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::qc::{QcFlag, QualityControl};
+    use crate::sounding::doctest::make_test_sounding;
+
+    #[test]
+    fn test_builder_truncates_and_ignores_qc() {
+        let snd = QualityControl::new().apply(make_test_sounding());
+        let mut flags = snd.qc_flags().to_vec();
+        flags[2].temperature = QcFlag::Fail;
+        let snd = snd.with_qc_flags(flags);
+
+        // One more value than there are levels.
+        let w: Vec<_> = vec![0.1, 0.5, -0.2, 1.0, 2.0]
+            .into_iter()
+            .map(MetersPSec)
+            .map(optional::some)
+            .collect();
+        let snd = snd.with_vertical_velocity_profile(w);
+
+        let pvv = snd.pvv_profile();
+        assert_eq!(pvv.len(), snd.pressure_profile().len());
+        assert!(pvv[2].is_some()); // 925 hPa failed QC, but still converted.
+        assert!(pvv[4].is_some());
+
+        // Nothing to convert with.
+        let w = vec![optional::some(MetersPSec(1.0)); 4];
+        let snd = crate::Sounding::new().with_vertical_velocity_profile(w);
+        assert!(snd.pvv_profile().iter().all(|pvv| pvv.is_none()));
+    }
+}
@@ -0,0 +1,346 @@
+//! Access the wind profile as u/v components or in other units.
+
+use metfor::{Knots, Speed, WindSpdDir, WindUV};
+use optional::{Noned, Optioned};
+
+use crate::data_row::DataRow;
+use crate::sounding::Sounding;
+
+/// A representation of the wind that can be converted to and from the speed and direction in
+/// knots that a `Sounding` stores.
+///
+/// It is implemented for `WindSpdDir` and `WindUV` in any speed units that convert to and from
+/// `Knots`, e.g. `MetersPSec` or `Kph`.
+pub trait WindRepresentation: Noned + Copy {
+    /// Convert from the stored representation.
+    fn from_stored(wind: WindSpdDir<Knots>) -> Self;
+
+    /// Convert to the stored representation.
+    fn into_stored(self) -> WindSpdDir<Knots>;
+}
+
+impl<S> WindRepresentation for WindSpdDir<S>
+where
+    S: Speed + Noned + From<Knots>,
+    Knots: From<S>,
+{
+    #[inline]
+    fn from_stored(wind: WindSpdDir<Knots>) -> Self {
+        WindSpdDir {
+            speed: S::from(wind.speed),
+            direction: wind.direction,
+        }
+    }
+
+    #[inline]
+    fn into_stored(self) -> WindSpdDir<Knots> {
+        WindSpdDir {
+            speed: Knots::from(self.speed),
+            direction: self.direction,
+        }
+    }
+}
+
+impl<S> WindRepresentation for WindUV<S>
+where
+    S: Speed + Noned + From<Knots>,
+    Knots: From<S>,
+{
+    #[inline]
+    fn from_stored(wind: WindSpdDir<Knots>) -> Self {
+        WindUV::from(wind)
+    }
+
+    #[inline]
+    fn into_stored(self) -> WindSpdDir<Knots> {
+        WindSpdDir::from(self)
+    }
+}
+
+impl Sounding {
+    /// Builder method for the wind profile as u/v components.
+    ///
+    /// The components are converted to the speed and direction in knots that is stored. Like
+    /// `with_wind_profile`, the profile does not include the surface.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::{Knots, MetersPSec, WindUV};
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let uv: Vec<_> = [-5.0, 0.0, 5.0, 10.0]
+    ///     .iter()
+    ///     .map(|&u| some(WindUV { u: MetersPSec(u), v: MetersPSec(5.0) }))
+    ///     .collect();
+    ///
+    /// let snd = make_test_sounding().with_wind_uv_profile(uv);
+    ///
+    /// let west = snd.wind_profile()[4].unwrap(); // 700 hPa
+    /// assert!((west.direction - 243.43).abs() < 0.01);
+    /// assert!((west.speed.0 - 21.73).abs() < 0.01);
+    ///
+    /// let uv: WindUV<Knots> = snd.wind_uv_profile()[1].unwrap(); // 1000 hPa
+    /// assert!((uv.u.0 + 9.719).abs() < 1.0e-3);
+    /// assert!((uv.v.0 - 9.719).abs() < 1.0e-3);
+    /// ```
+    #[inline]
+    pub fn with_wind_uv_profile<S>(self, profile: Vec<Optioned<WindUV<S>>>) -> Self
+    where
+        S: Speed + Noned + From<Knots>,
+        Knots: From<S>,
+    {
+        self.with_wind_profile_as(profile)
+    }
+
+    /// Builder method for the wind profile in any representation and units.
+    ///
+    /// See `with_wind_uv_profile` for an example.
+    #[inline]
+    pub fn with_wind_profile_as<W>(self, profile: Vec<Optioned<W>>) -> Self
+    where
+        W: WindRepresentation,
+    {
+        let profile = profile
+            .into_iter()
+            .map(|w| w.map_t(W::into_stored))
+            .collect();
+
+        self.with_wind_profile(profile)
+    }
+
+    /// Get the wind profile as u/v components, with the surface first.
+    ///
+    /// See `with_wind_uv_profile` for an example.
+    #[inline]
+    pub fn wind_uv_profile<S>(&self) -> Vec<Optioned<WindUV<S>>>
+    where
+        S: Speed + Noned + From<Knots>,
+        Knots: From<S>,
+    {
+        self.wind_profile_as()
+    }
+
+    /// Get the wind profile in any representation and units, with the surface first.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::{Knots, MetersPSec, WindSpdDir};
+    /// use optional::{some, Optioned};
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let wind: Vec<_> = (1..=4)
+    ///     .map(|i| some(WindSpdDir { speed: Knots(10.0 * i as f64), direction: 270.0 }))
+    ///     .collect();
+    /// let snd = make_test_sounding().with_wind_profile(wind);
+    ///
+    /// let wind: Vec<Optioned<WindSpdDir<MetersPSec>>> = snd.wind_profile_as();
+    /// assert!(wind[0].is_none()); // No surface wind
+    /// assert!((wind[1].unwrap().speed.0 - 5.144).abs() < 1.0e-3);
+    /// assert_eq!(wind[1].unwrap().direction, 270.0);
+    /// ```
+    #[inline]
+    pub fn wind_profile_as<W>(&self) -> Vec<Optioned<W>>
+    where
+        W: WindRepresentation,
+    {
+        self.wind_profile()
+            .iter()
+            .map(|w| w.map_t(W::from_stored))
+            .collect()
+    }
+
+    /// Get the surface wind as u/v components.
+    ///
+    /// See `with_sfc_wind_as` for an example.
+    #[inline]
+    pub fn sfc_wind_uv<S>(&self) -> Optioned<WindUV<S>>
+    where
+        S: Speed + Noned + From<Knots>,
+        Knots: From<S>,
+    {
+        self.sfc_wind_as()
+    }
+
+    /// Get the surface wind in any representation and units.
+    ///
+    /// See `with_sfc_wind_as` for an example.
+    #[inline]
+    pub fn sfc_wind_as<W>(&self) -> Optioned<W>
+    where
+        W: WindRepresentation,
+    {
+        self.sfc_wind().map_t(W::from_stored)
+    }
+
+    /// Builder method for the surface wind in any representation and units.
+    ///
+    /// Unlike `with_sfc_wind`, this also takes a speed and direction in units other than knots.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::{MetersPSec, WindSpdDir, WindUV};
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let wind = WindSpdDir { speed: MetersPSec(10.0), direction: 270.0 };
+    /// let snd = make_test_sounding().with_sfc_wind_as(some(wind));
+    ///
+    /// assert!((snd.sfc_wind().unwrap().speed.0 - 19.438).abs() < 1.0e-3);
+    ///
+    /// let uv: WindUV<MetersPSec> = snd.sfc_wind_uv().unwrap();
+    /// assert!((uv.u.0 - 10.0).abs() < 1.0e-9);
+    /// assert!(uv.v.0.abs() < 1.0e-9);
+    ///
+    /// let wind: WindSpdDir<MetersPSec> = snd.sfc_wind_as().unwrap();
+    /// assert!((wind.speed.0 - 10.0).abs() < 1.0e-9);
+    /// ```
+    #[inline]
+    pub fn with_sfc_wind_as<W>(self, value: Optioned<W>) -> Self
+    where
+        W: WindRepresentation,
+    {
+        self.with_sfc_wind(value.map_t(W::into_stored))
+    }
+
+    /// Get a bottom up iterator over the data rows paired with the wind in any representation and
+    /// units. The first value is the surface.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::{Knots, MetersPSec, WindSpdDir};
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let wind = vec![some(WindSpdDir { speed: Knots(20.0), direction: 200.0 }); 4];
+    /// let snd = make_test_sounding()
+    ///     .with_sfc_wind(WindSpdDir { speed: Knots(10.0), direction: 180.0 })
+    ///     .with_wind_profile(wind);
+    ///
+    /// let (row, wind) = snd.bottom_up_with_wind::<WindSpdDir<MetersPSec>>().next().unwrap();
+    /// assert_eq!(row.wind.unwrap().speed, Knots(10.0));
+    /// let wind = wind.unwrap();
+    /// assert!((wind.speed.0 - 5.144).abs() < 1.0e-3);
+    /// assert_eq!(wind.direction, 180.0);
+    /// ```
+    #[inline]
+    pub fn bottom_up_with_wind<'a, W>(&'a self) -> impl Iterator<Item = (DataRow, Optioned<W>)> + 'a
+    where
+        W: WindRepresentation + 'a,
+    {
+        self.bottom_up()
+            .map(|row| (row, row.wind.map_t(W::from_stored)))
+    }
+
+    /// Get a top down iterator over the data rows paired with the wind in any representation and
+    /// units. The last value is the surface.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use metfor::{Knots, WindSpdDir, WindUV};
+    /// use optional::some;
+    /// # use sounding_base::doctest::make_test_sounding;
+    ///
+    /// let wind: Vec<_> = (1..=4)
+    ///     .map(|i| some(WindSpdDir { speed: Knots(10.0 * i as f64), direction: 180.0 }))
+    ///     .collect();
+    /// let snd = make_test_sounding().with_wind_profile(wind);
+    ///
+    /// let mut iter = snd.top_down_with_wind::<WindUV<Knots>>();
+    /// let (row, wind) = iter.next().unwrap();
+    /// assert_eq!(row.pressure.unwrap().0, 700.0);
+    /// let wind = wind.unwrap(); // A south wind blows toward the north.
+    /// assert!(wind.u.0.abs() < 1.0e-9);
+    /// assert!((wind.v.0 - 40.0).abs() < 1.0e-9);
+    ///
+    /// let (row, wind) = iter.last().unwrap();
+    /// assert_eq!(row.pressure.unwrap().0, 1005.0);
+    /// assert!(wind.is_none()); // No surface wind
+    /// ```
+    #[inline]
+    pub fn top_down_with_wind<'a, W>(&'a self) -> impl Iterator<Item = (DataRow, Optioned<W>)> + 'a
+    where
+        W: WindRepresentation + 'a,
+    {
+        self.top_down()
+            .map(|row| (row, row.wind.map_t(W::from_stored)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sounding::doctest::make_test_sounding;
+    use metfor::{MetersPSec, Quantity};
+    use optional::{none, some};
+
+    fn uv_profile<S: Speed + Noned>(
+        components: [Option<(f64, f64)>; 4],
+        unit: fn(f64) -> S,
+    ) -> Vec<Optioned<WindUV<S>>> {
+        components
+            .iter()
+            .map(|uv| match *uv {
+                Some((u, v)) => some(WindUV {
+                    u: unit(u),
+                    v: unit(v),
+                }),
+                None => none(),
+            })
+            .collect()
+    }
+
+    fn assert_round_trip<S>(unit: fn(f64) -> S)
+    where
+        S: Speed + Noned + From<Knots>,
+        Knots: From<S>,
+    {
+        let components = [
+            Some((0.0, 0.0)),
+            None,
+            Some((3.0, -4.0)),
+            Some((-10.0, 0.0)),
+        ];
+        let snd = make_test_sounding().with_wind_uv_profile(uv_profile(components, unit));
+
+        let winds: Vec<Optioned<WindUV<S>>> = snd.wind_uv_profile();
+        assert_eq!(winds.len(), 5);
+        assert!(winds[0].is_none()); // No surface wind
+
+        for (wind, uv) in winds[1..].iter().zip(components.iter()) {
+            match *uv {
+                Some((u, v)) => {
+                    let wind = wind.unwrap();
+                    assert!((wind.u.unpack() - u).abs() < 1.0e-9);
+                    assert!((wind.v.unpack() - v).abs() < 1.0e-9);
+                }
+                None => assert!(wind.is_none()),
+            }
+        }
+
+        let calm = snd.wind_profile()[1].unwrap();
+        assert!(calm.speed.unpack().abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn test_uv_round_trip() {
+        assert_round_trip(MetersPSec);
+        assert_round_trip(Knots);
+
+        // Stored in knots.
+        let components = [None, None, Some((3.0, -4.0)), None];
+        let snd = make_test_sounding().with_wind_uv_profile(uv_profile(components, MetersPSec));
+        let wind = snd.wind_profile()[3].unwrap();
+        assert!((wind.speed.unpack() - 9.719).abs() < 1.0e-3);
+        assert!((wind.direction - 323.13).abs() < 0.01);
+
+        let uv: WindUV<Knots> = snd.wind_uv_profile()[3].unwrap();
+        assert!((uv.u.unpack() - 5.832).abs() < 1.0e-3);
+        assert!((uv.v.unpack() + 7.776).abs() < 1.0e-3);
+    }
+}
@@ -0,0 +1,140 @@
+//! Tests of the exit codes and output of the `sounding` command line tool.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+const CSV: &str = "\
+pressure_hPa,height_m,temperature_C,dew_point_C,wind_direction_deg,wind_speed_kt
+1000,100,25,18,180,10
+850,1500,15,12,200,20
+700,3100,5,-2,230,30
+500,5700,-12,-30,250,40
+";
+
+const BUFKIT: &str = "\
+SNPARM = PRES;TMPC;TMWC;DWPC;THTE;DRCT;SKNT;OMEG;CFRL;HGHT
+
+STID = KMSO STNM = 727730 TIME = 170401/0000
+SLAT = 46.92 SLON = -114.08 SELV = 1335.0
+STIM = 0
+
+PRES TMPC TMWC DWPC THTE DRCT SKNT OMEG
+CFRL HGHT
+870.10 4.66 0.72 -4.64 298.61 264.81 5.25 -0.20
+0.00 1362.00
+700.30 -5.94 -9.52 -20.51 297.35 250.56 19.31 0.30
+-9999.00 3020.00
+";
+
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sounding"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The tool may exit before reading its input, e.g. for bad arguments.
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_convert() {
+    let path = std::env::temp_dir().join(format!("sounding-cli-{}.buf", std::process::id()));
+    std::fs::write(&path, BUFKIT).unwrap();
+
+    let output = run(&["convert", path.to_str().unwrap()], "");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    let text = stdout(&output);
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines[0].starts_with("pressure_hPa,"));
+    assert!(lines[2].starts_with("870.1"));
+    assert!(lines[3].starts_with("700.3"));
+
+    // Only one sounding can be converted.
+    let two = format!("{}\n{}", BUFKIT, &BUFKIT[BUFKIT.find("STID").unwrap()..]);
+    let output = run(&["convert", "--from", "bufkit"], &two);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout(&output).is_empty());
+
+    let output = run(&["summary", "--from", "bufkit"], &two);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output).matches("station:").count(), 2);
+
+    let output = run(&["convert", "--to", "sharppy"], CSV);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("%RAW%"));
+
+    let output = run(&["convert", "--to", "bufkit"], CSV);
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run(&["convert", "--from", "grib"], CSV);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_summary() {
+    let output = run(&["summary"], CSV);
+    assert_eq!(output.status.code(), Some(0));
+
+    let text = stdout(&output);
+    assert!(text.contains("levels:           3"));
+    assert!(text.contains("station pressure: 1000.0 hPa"));
+    assert!(text.contains("k index:          32.0"));
+    assert!(text.contains("total totals:     51.0"));
+    assert!(text.contains("lifted index:"));
+    assert!(text.contains("precip. water:"));
+
+    let output = run(&["summary", "--from", "bufkit"], BUFKIT);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("station:          727730"));
+}
+
+#[test]
+fn test_extract() {
+    let output = run(&["extract", "--pressure", "900"], CSV);
+    assert_eq!(output.status.code(), Some(0));
+
+    let text = stdout(&output);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with("900.00,"));
+
+    // Out of range is not an error, there just isn't a row.
+    let output = run(&["extract", "--pressure", "200"], CSV);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output).lines().count(), 1);
+
+    let output = run(&["extract"], CSV);
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run(&["extract", "--pressure", "abc"], CSV);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_validate() {
+    let output = run(&["validate"], CSV);
+    assert_eq!(output.status.code(), Some(0));
+
+    // A dew point above the temperature fails.
+    let bad = CSV.replace("850,1500,15,12", "850,1500,15,20");
+    let output = run(&["validate"], &bad);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("level 1: dew point 20.0 failed"));
+
+    let output = run(&["validate", "--from", "sharppy"], CSV);
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run(&[], "");
+    assert_eq!(output.status.code(), Some(2));
+}